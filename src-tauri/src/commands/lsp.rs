@@ -1,33 +1,55 @@
-use crate::lsp::{LspManager, types::LspResult};
-use lsp_types::{CompletionItem, Hover};
-use serde::Serialize;
-use std::path::PathBuf;
+use super::fuzzy::{FuzzyMatchRequest, fuzzy_match};
+use crate::lsp::{
+   LspManager,
+   diagnostics::parse_severity,
+   trace::TraceEntry,
+   types::{
+      CallHierarchyDirection, CodeActionItem, CodeActionOutcome, CompletionEntry,
+      CompletionListResult, DiagnosticItem, DocumentHighlightItem, DocumentLinkItem,
+      DocumentSymbolItem, FoldingRangeItem, GotoKind, HierarchyNode, InlayHintItem, LocationItem,
+      LspResult, PrepareRenameItem, ServerCapabilitiesItem, ServerKey, SignatureHelpItem,
+      TypeHierarchyDirection, WorkspaceSymbolItem, WorkspaceSymbolMatch,
+   },
+   workspace_edit::{WorkspaceEditPreview, WorkspaceEditResult},
+};
+use lsp_types::{
+   CallHierarchyItem, CodeAction, CodeActionOrCommand, CompletionContext, CompletionItem,
+   CompletionTriggerKind, DocumentLink, Hover, InlayHint, Position, Range,
+   SignatureHelpTriggerKind, TextDocumentContentChangeEvent, TraceValue, TypeHierarchyItem,
+   WorkspaceEdit, WorkspaceSymbol,
+};
+use serde_json::Value;
+use std::{
+   collections::HashMap,
+   path::{Path, PathBuf},
+};
 use tauri::State;
 
 #[tauri::command]
-pub async fn lsp_start(
-   lsp_manager: State<'_, LspManager>,
-   workspace_path: String,
-) -> LspResult<()> {
+pub fn lsp_start(lsp_manager: State<'_, LspManager>, workspace_path: String) -> LspResult<()> {
    log::info!("lsp_start command called with path: {}", workspace_path);
-   lsp_manager
-      .start_lsp_for_workspace(PathBuf::from(workspace_path))
-      .await
-      .map_err(|e| {
-         log::error!("Failed to start LSP: {}", e);
-         e.into()
-      })
+   lsp_manager.start_lsp_for_workspace(PathBuf::from(workspace_path));
+   Ok(())
 }
 
 #[tauri::command]
-pub fn lsp_stop(lsp_manager: State<'_, LspManager>, workspace_path: String) -> LspResult<()> {
+pub async fn lsp_stop(lsp_manager: State<'_, LspManager>, workspace_path: String) -> LspResult<()> {
    log::info!("lsp_stop command called with path: {}", workspace_path);
    lsp_manager
       .shutdown_workspace(&PathBuf::from(workspace_path))
-      .map_err(|e| {
-         log::error!("Failed to stop LSP: {}", e);
-         e.into()
-      })
+      .await;
+   Ok(())
+}
+
+#[tauri::command]
+pub async fn lsp_restart_server(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+) -> LspResult<()> {
+   lsp_manager
+      .restart_server(&server)
+      .await
+      .map_err(Into::into)
 }
 
 #[tauri::command]
@@ -36,47 +58,492 @@ pub async fn lsp_get_completions(
    file_path: String,
    line: u32,
    character: u32,
-) -> LspResult<Vec<CompletionItem>> {
+   trigger_kind: Option<CompletionTriggerKind>,
+   trigger_character: Option<String>,
+   request_id: Option<String>,
+) -> LspResult<CompletionListResult> {
    log::info!(
       "lsp_get_completions called for {}:{}:{}",
       file_path,
       line,
       character
    );
+   let trigger_kind = trigger_kind.unwrap_or(if trigger_character.is_some() {
+      CompletionTriggerKind::TRIGGER_CHARACTER
+   } else {
+      CompletionTriggerKind::INVOKED
+   });
+   let context = CompletionContext {
+      trigger_kind,
+      trigger_character,
+   };
+
    let result = lsp_manager
-      .get_completions(&file_path, line, character)
+      .get_completions(
+         &file_path,
+         Position::new(line, character),
+         context,
+         request_id,
+      )
       .await
       .map_err(|e| {
          log::error!("Failed to get completions: {}", e);
          e.into()
       });
    if let Ok(ref completions) = result {
-      log::info!("Got {} completions", completions.len());
+      log::info!("Got {} completions", completions.items.len());
    }
    result
 }
 
+#[tauri::command]
+pub async fn lsp_resolve_completion(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   item: CompletionItem,
+   file_path: String,
+) -> LspResult<CompletionEntry> {
+   lsp_manager
+      .resolve_completion(&server, item, &file_path)
+      .await
+      .map_err(Into::into)
+}
+
 #[tauri::command]
 pub async fn lsp_get_hover(
    lsp_manager: State<'_, LspManager>,
    file_path: String,
    line: u32,
    character: u32,
+   request_id: Option<String>,
 ) -> LspResult<Option<Hover>> {
    lsp_manager
-      .get_hover(&file_path, line, character)
+      .get_hover(&file_path, line, character, request_id)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_signature_help(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+   trigger_kind: Option<SignatureHelpTriggerKind>,
+   trigger_character: Option<String>,
+   is_retrigger: Option<bool>,
+) -> LspResult<Option<SignatureHelpItem>> {
+   let trigger_kind = trigger_kind.unwrap_or(if trigger_character.is_some() {
+      SignatureHelpTriggerKind::TRIGGER_CHARACTER
+   } else {
+      SignatureHelpTriggerKind::INVOKED
+   });
+   lsp_manager
+      .signature_help(
+         &file_path,
+         Position::new(line, character),
+         trigger_kind,
+         trigger_character,
+         is_retrigger.unwrap_or(false),
+      )
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_inlay_hints(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   range: Range,
+) -> LspResult<Vec<InlayHintItem>> {
+   lsp_manager
+      .inlay_hints(&file_path, range)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_resolve_inlay_hint(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   hint: InlayHint,
+) -> LspResult<InlayHintItem> {
+   lsp_manager
+      .resolve_inlay_hint(&server, hint)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_document_symbols(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+) -> LspResult<Vec<DocumentSymbolItem>> {
+   lsp_manager
+      .document_symbols(&file_path)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_prepare_call_hierarchy(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+) -> LspResult<Vec<HierarchyNode>> {
+   lsp_manager
+      .prepare_call_hierarchy(&file_path, line, character)
+      .await
+      .map_err(Into::into)
+}
+
+/// Expands a call hierarchy node returned by `lsp_prepare_call_hierarchy` or an earlier
+/// expansion.
+#[tauri::command]
+pub async fn lsp_call_hierarchy_calls(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   item: CallHierarchyItem,
+   direction: CallHierarchyDirection,
+) -> LspResult<Vec<HierarchyNode>> {
+   lsp_manager
+      .call_hierarchy_calls(&server, item, direction)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_prepare_type_hierarchy(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+) -> LspResult<Vec<HierarchyNode>> {
+   lsp_manager
+      .prepare_type_hierarchy(&file_path, line, character)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_type_hierarchy_types(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   item: TypeHierarchyItem,
+   direction: TypeHierarchyDirection,
+) -> LspResult<Vec<HierarchyNode>> {
+   lsp_manager
+      .type_hierarchy_types(&server, item, direction)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_document_highlights(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+) -> LspResult<Vec<DocumentHighlightItem>> {
+   lsp_manager
+      .document_highlights(&file_path, line, character)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_document_links(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+) -> LspResult<Vec<DocumentLinkItem>> {
+   lsp_manager
+      .document_links(&file_path)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_resolve_document_link(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   link: DocumentLink,
+) -> LspResult<DocumentLinkItem> {
+   lsp_manager
+      .resolve_document_link(&server, link)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_folding_ranges(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+) -> LspResult<Vec<FoldingRangeItem>> {
+   lsp_manager
+      .folding_ranges(&file_path)
+      .await
+      .map_err(Into::into)
+}
+
+/// Expand-selection steps for each position, innermost range first.
+#[tauri::command]
+pub async fn lsp_selection_ranges(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   positions: Vec<Position>,
+) -> LspResult<Vec<Vec<Range>>> {
+   lsp_manager
+      .selection_ranges(&file_path, positions)
+      .await
+      .map_err(Into::into)
+}
+
+/// Workspace symbols from every server, ranked for the symbol picker with `fuzzy_match`.
+#[tauri::command]
+pub async fn lsp_workspace_symbols(
+   lsp_manager: State<'_, LspManager>,
+   query: String,
+   workspace_path: Option<String>,
+) -> LspResult<Vec<WorkspaceSymbolMatch>> {
+   let symbols = lsp_manager
+      .workspace_symbols(&query, workspace_path.as_deref().map(Path::new))
+      .await?;
+   Ok(rank_symbols(&query, symbols))
+}
+
+#[tauri::command]
+pub async fn lsp_resolve_workspace_symbol(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   symbol: WorkspaceSymbol,
+) -> LspResult<WorkspaceSymbolItem> {
+   lsp_manager
+      .resolve_workspace_symbol(&server, symbol)
+      .await
+      .map_err(Into::into)
+}
+
+/// Scores symbol names against the query, best first. Servers filter loosely and differently,
+/// so ranking the merged list here keeps the picker consistent with file search.
+fn rank_symbols(query: &str, symbols: Vec<WorkspaceSymbolItem>) -> Vec<WorkspaceSymbolMatch> {
+   let mut by_name: HashMap<String, Vec<WorkspaceSymbolItem>> = HashMap::new();
+   for symbol in symbols {
+      by_name.entry(symbol.name.clone()).or_default().push(symbol);
+   }
+
+   let matches = fuzzy_match(FuzzyMatchRequest {
+      pattern: query.to_string(),
+      items: by_name.keys().cloned().collect(),
+      case_sensitive: None,
+      normalize: None,
+   });
+
+   matches
+      .into_iter()
+      .flat_map(|matched| {
+         let symbols = by_name.remove(&matched.text).unwrap_or_default();
+         symbols.into_iter().map(move |symbol| WorkspaceSymbolMatch {
+            symbol,
+            score: matched.score,
+            indices: matched.indices.clone(),
+         })
+      })
+      .collect()
+}
+
+/// Cancels a completion or hover request started with the same `request_id`.
+#[tauri::command]
+pub fn lsp_cancel_request(lsp_manager: State<'_, LspManager>, request_id: String) {
+   lsp_manager.cancel_request(&request_id);
+}
+
+#[tauri::command]
+pub async fn lsp_goto_definition(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+) -> LspResult<Vec<LocationItem>> {
+   lsp_manager
+      .goto(GotoKind::Definition, &file_path, line, character)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_goto_declaration(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+) -> LspResult<Vec<LocationItem>> {
+   lsp_manager
+      .goto(GotoKind::Declaration, &file_path, line, character)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_goto_type_definition(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+) -> LspResult<Vec<LocationItem>> {
+   lsp_manager
+      .goto(GotoKind::TypeDefinition, &file_path, line, character)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_goto_implementation(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+) -> LspResult<Vec<LocationItem>> {
+   lsp_manager
+      .goto(GotoKind::Implementation, &file_path, line, character)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_find_references(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+   include_declaration: Option<bool>,
+) -> LspResult<Vec<LocationItem>> {
+   lsp_manager
+      .find_references(
+         &file_path,
+         line,
+         character,
+         include_declaration.unwrap_or(true),
+      )
       .await
       .map_err(Into::into)
 }
 
 #[tauri::command]
-pub fn lsp_document_open(
+pub async fn lsp_prepare_rename(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+) -> LspResult<Option<PrepareRenameItem>> {
+   lsp_manager
+      .prepare_rename(&file_path, line, character)
+      .await
+      .map_err(Into::into)
+}
+
+/// Computes a rename and returns a preview; nothing is written until the returned edit is passed
+/// to `lsp_apply_workspace_edit`.
+#[tauri::command]
+pub async fn lsp_rename(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   line: u32,
+   character: u32,
+   new_name: String,
+) -> LspResult<Option<WorkspaceEditPreview>> {
+   lsp_manager
+      .rename(&file_path, line, character, new_name)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub fn lsp_apply_workspace_edit(
+   lsp_manager: State<'_, LspManager>,
+   edit: WorkspaceEdit,
+) -> WorkspaceEditResult {
+   lsp_manager.apply_workspace_edit(edit)
+}
+
+#[tauri::command]
+pub async fn lsp_code_actions(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   range: Range,
+   only: Option<Vec<String>>,
+) -> LspResult<Vec<CodeActionItem>> {
+   lsp_manager
+      .code_actions(&file_path, range, only)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_resolve_code_action(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   action: CodeAction,
+) -> LspResult<CodeAction> {
+   lsp_manager
+      .resolve_code_action(&server, action)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_apply_code_action(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   action: CodeActionOrCommand,
+) -> LspResult<CodeActionOutcome> {
+   lsp_manager
+      .apply_code_action(&server, action)
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_execute_command(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   command: String,
+   arguments: Option<Vec<Value>>,
+) -> LspResult<Option<Value>> {
+   lsp_manager
+      .execute_command(&server, command, arguments.unwrap_or_default())
+      .await
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub fn lsp_get_server_capabilities(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+) -> Vec<ServerCapabilitiesItem> {
+   lsp_manager.server_capabilities(&file_path)
+}
+
+#[tauri::command]
+pub fn lsp_respond_to_message_request(
+   lsp_manager: State<'_, LspManager>,
+   id: u64,
+   action: Option<String>,
+) -> LspResult<()> {
+   lsp_manager
+      .respond_to_message_request(id, action)
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn lsp_document_open(
    lsp_manager: State<'_, LspManager>,
    file_path: String,
    content: String,
 ) -> LspResult<()> {
    lsp_manager
       .notify_document_open(&file_path, content)
+      .await
       .map_err(Into::into)
 }
 
@@ -84,11 +551,12 @@ pub fn lsp_document_open(
 pub fn lsp_document_change(
    lsp_manager: State<'_, LspManager>,
    file_path: String,
-   content: String,
+   content: Option<String>,
+   changes: Option<Vec<TextDocumentContentChangeEvent>>,
    version: i32,
 ) -> LspResult<()> {
    lsp_manager
-      .notify_document_change(&file_path, content, version)
+      .notify_document_change(&file_path, content, changes.unwrap_or_default(), version)
       .map_err(Into::into)
 }
 
@@ -100,34 +568,86 @@ pub fn lsp_document_close(lsp_manager: State<'_, LspManager>, file_path: String)
 }
 
 #[tauri::command]
-pub fn lsp_is_language_supported(file_path: String) -> bool {
-   let path = PathBuf::from(file_path);
-   let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+pub fn lsp_is_language_supported(
+   lsp_manager: State<'_, LspManager>,
+   file_path: String,
+   workspace_path: Option<String>,
+) -> bool {
+   lsp_manager
+      .registry(workspace_path.as_deref().map(Path::new))
+      .is_file_supported(Path::new(&file_path))
+}
+
+#[tauri::command]
+pub fn get_diagnostics(
+   lsp_manager: State<'_, LspManager>,
+   path: Option<String>,
+   severity: Option<String>,
+) -> Result<Vec<DiagnosticItem>, String> {
+   let severity = match severity.as_deref().filter(|s| !s.is_empty()) {
+      Some(name) => {
+         Some(parse_severity(name).ok_or_else(|| format!("Unknown severity: {}", name))?)
+      }
+      None => None,
+   };
 
-   // Support TypeScript, JavaScript, and related files
-   matches!(
-      extension,
-      "ts" | "tsx" | "js" | "jsx" | "mjs" | "cjs" | "json"
-   )
+   Ok(lsp_manager.get_diagnostics(path.as_deref().map(Path::new), severity))
 }
 
-#[derive(Debug, Serialize)]
-pub struct DiagnosticItem {
-   pub file: String,
-   pub line: u32,
-   pub column: u32,
-   pub severity: String,
-   pub message: String,
-   pub source: Option<String>,
+/// Pulls project-wide diagnostics from servers that support it, for the problems list. Results
+/// arrive as `diagnostics-changed` events and through `get_diagnostics`.
+#[tauri::command]
+pub async fn lsp_pull_workspace_diagnostics(
+   lsp_manager: State<'_, LspManager>,
+   workspace_path: Option<String>,
+) -> LspResult<()> {
+   lsp_manager
+      .pull_workspace_diagnostics(workspace_path.as_deref().map(Path::new))
+      .await;
+   Ok(())
 }
 
 #[tauri::command]
-pub fn get_diagnostics(
-   _lsp_manager: State<'_, LspManager>,
-   _path: Option<String>,
-   _severity: Option<String>,
-) -> Result<Vec<DiagnosticItem>, String> {
-   // LSP diagnostics caching is not yet implemented.
-   // Return an empty list so the frontend can handle the message gracefully.
-   Ok(Vec::new())
+pub fn lsp_get_trace(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   since: Option<u64>,
+) -> LspResult<Vec<TraceEntry>> {
+   lsp_manager
+      .trace_entries(&server, since)
+      .map_err(Into::into)
+}
+
+#[tauri::command]
+pub fn lsp_clear_trace(lsp_manager: State<'_, LspManager>, server: ServerKey) -> LspResult<()> {
+   lsp_manager.clear_trace(&server).map_err(Into::into)
+}
+
+/// Starts or stops streaming a server's traffic as `lsp-trace` events, for the inspector.
+#[tauri::command]
+pub fn lsp_watch_trace(lsp_manager: State<'_, LspManager>, server: ServerKey, enabled: bool) {
+   lsp_manager.watch_trace(&server, enabled);
+}
+
+#[tauri::command]
+pub fn lsp_export_trace(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   path: String,
+) -> LspResult<()> {
+   lsp_manager
+      .export_trace(&server, Path::new(&path))
+      .map_err(Into::into)
+}
+
+/// Sends `$/setTrace` with `off`, `messages` or `verbose`.
+#[tauri::command]
+pub fn lsp_set_trace(
+   lsp_manager: State<'_, LspManager>,
+   server: ServerKey,
+   value: TraceValue,
+) -> LspResult<()> {
+   lsp_manager
+      .set_server_trace(&server, value)
+      .map_err(Into::into)
 }
@@ -1,366 +1,298 @@
+use crate::{
+   lsp::{LspManager, semantic_tokens::SemanticTokenSpan},
+   syntax::{
+      SyntaxDocuments, Token, TokenRange, TokenUpdate, UserLanguage, detect_language,
+      get_language_config, highlight, load_user_languages,
+   },
+};
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use tree_sitter_highlight::{HighlightConfiguration, HighlightEvent, Highlighter};
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Token {
-   pub start: usize,
-   pub end: usize,
-   pub token_type: String,
-   pub class_name: String,
+use lsp_types::TextDocumentContentChangeEvent;
+use std::ops::Range;
+use tauri::{AppHandle, Manager, State};
+
+/// Maps an LSP semantic token onto the classes tree-sitter tokens use. Modifiers and the semantic
+/// type are added as extra classes (`token-macro`, `token-mod-mutable`) so themes can style
+/// what tree-sitter can't tell apart. Unknown types return `None` and don't override anything.
+fn semantic_token_class(span: &SemanticTokenSpan) -> Option<(String, String)> {
+   let readonly = span.modifiers.iter().any(|m| m == "readonly");
+   let token_type = match span.token_type.as_str() {
+      "namespace" | "type" | "class" | "enum" | "interface" | "struct" | "typeParameter" => "type",
+      "parameter" | "variable" if readonly => "constant",
+      "parameter" | "variable" => "identifier",
+      "property" | "event" => "property",
+      "enumMember" => "constant",
+      "function" | "method" | "macro" | "decorator" => "function",
+      "keyword" | "modifier" => "keyword",
+      "comment" => "comment",
+      "string" | "regexp" => "string",
+      "number" => "number",
+      "operator" => "operator",
+      _ => return None,
+   };
+
+   let mut class_name = format!("token-{} token-{}", token_type, span.token_type);
+   for modifier in &span.modifiers {
+      class_name.push_str(" token-mod-");
+      class_name.push_str(modifier);
+   }
+   Some((token_type.to_string(), class_name))
 }
 
-// Standard highlight names used by Tree-sitter
-const HIGHLIGHT_NAMES: &[&str] = &[
-   "attribute",
-   "comment",
-   "constant",
-   "constant.builtin",
-   "constructor",
-   "embedded",
-   "error",
-   "function",
-   "function.builtin",
-   "function.method",
-   "keyword",
-   "keyword.control",
-   "keyword.function",
-   "keyword.operator",
-   "keyword.return",
-   "module",
-   "number",
-   "operator",
-   "property",
-   "property.builtin",
-   "punctuation",
-   "punctuation.bracket",
-   "punctuation.delimiter",
-   "punctuation.special",
-   "string",
-   "string.escape",
-   "string.special",
-   "tag",
-   "type",
-   "type.builtin",
-   "variable",
-   "variable.builtin",
-   "variable.parameter",
-];
-
-fn get_language_config(language_name: &str) -> Result<HighlightConfiguration> {
-   match language_name {
-      "javascript" | "js" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_javascript::LANGUAGE.into(),
-            language_name,
-            tree_sitter_javascript::HIGHLIGHT_QUERY,
-            tree_sitter_javascript::INJECTIONS_QUERY,
-            tree_sitter_javascript::LOCALS_QUERY,
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "typescript" | "ts" => {
-         // TypeScript inherits JavaScript highlights, so we use JavaScript's query
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
-            language_name,
-            tree_sitter_javascript::HIGHLIGHT_QUERY,
-            tree_sitter_javascript::INJECTIONS_QUERY,
-            tree_sitter_javascript::LOCALS_QUERY,
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "tsx" => {
-         // TSX also inherits JavaScript highlights
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_typescript::LANGUAGE_TSX.into(),
-            language_name,
-            tree_sitter_javascript::HIGHLIGHT_QUERY,
-            tree_sitter_javascript::INJECTIONS_QUERY,
-            tree_sitter_javascript::LOCALS_QUERY,
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "json" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_json::LANGUAGE.into(),
-            language_name,
-            tree_sitter_json::HIGHLIGHTS_QUERY,
-            "", // JSON doesn't have injections/locals
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "yaml" | "yml" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_yaml::LANGUAGE.into(),
-            language_name,
-            tree_sitter_yaml::HIGHLIGHTS_QUERY,
-            "", // YAML doesn't have injections/locals
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "go" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_go::LANGUAGE.into(),
-            language_name,
-            tree_sitter_go::HIGHLIGHTS_QUERY,
-            "", // Go doesn't have injections/locals in the current version
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "ruby" | "rb" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_ruby::LANGUAGE.into(),
-            language_name,
-            tree_sitter_ruby::HIGHLIGHTS_QUERY,
-            "", // Ruby doesn't have injections/locals in this version
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "rust" | "rs" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_rust::LANGUAGE.into(),
-            language_name,
-            tree_sitter_rust::HIGHLIGHTS_QUERY,
-            tree_sitter_rust::INJECTIONS_QUERY,
-            "", // Rust doesn't have LOCALS_QUERY in this version
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "erb" | "html.erb" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_embedded_template::LANGUAGE.into(),
-            language_name,
-            tree_sitter_embedded_template::HIGHLIGHTS_QUERY,
-            "", // ERB doesn't have injections/locals in this version
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "python" | "py" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_python::LANGUAGE.into(),
-            language_name,
-            tree_sitter_python::HIGHLIGHTS_QUERY,
-            "", // Python doesn't have injections/locals in this version
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "html" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_html::LANGUAGE.into(),
-            language_name,
-            tree_sitter_html::HIGHLIGHTS_QUERY,
-            tree_sitter_html::INJECTIONS_QUERY,
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "css" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_css::LANGUAGE.into(),
-            language_name,
-            tree_sitter_css::HIGHLIGHTS_QUERY,
-            "", // CSS doesn't have injections/locals
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "markdown" | "md" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_md::LANGUAGE.into(),
-            language_name,
-            tree_sitter_md::HIGHLIGHT_QUERY_BLOCK,
-            tree_sitter_md::INJECTION_QUERY_BLOCK,
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "bash" | "sh" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_bash::LANGUAGE.into(),
-            language_name,
-            tree_sitter_bash::HIGHLIGHT_QUERY,
-            "", // Bash doesn't have injections/locals in this version
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "toml" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_toml_ng::LANGUAGE.into(),
-            language_name,
-            tree_sitter_toml_ng::HIGHLIGHTS_QUERY,
-            "", // TOML doesn't have injections/locals in this version
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "java" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_java::LANGUAGE.into(),
-            language_name,
-            tree_sitter_java::HIGHLIGHTS_QUERY,
-            "", // Java doesn't have injections/locals in this version
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
-      }
-      "c" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_c::LANGUAGE.into(),
-            language_name,
-            tree_sitter_c::HIGHLIGHT_QUERY,
-            "", // C doesn't have injections/locals in this version
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
+/// Layers semantic tokens over tree-sitter tokens. Where they overlap the semantic token wins,
+/// since the server knows what a name resolves to; tree-sitter tokens are trimmed or split around
+/// it and still cover everything the server leaves out. Both inputs are sorted by `start`.
+pub fn overlay_semantic_tokens(syntax: Vec<Token>, semantic: Vec<Token>) -> Vec<Token> {
+   if semantic.is_empty() {
+      return syntax;
+   }
+
+   let mut merged = Vec::with_capacity(syntax.len() + semantic.len());
+   let mut next_semantic = 0;
+
+   for token in syntax {
+      let mut start = token.start;
+      // Skip semantic tokens that end before this one starts
+      while next_semantic < semantic.len() && semantic[next_semantic].end <= start {
+         next_semantic += 1;
       }
-      "cpp" | "cxx" | "cc" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_cpp::LANGUAGE.into(),
-            language_name,
-            tree_sitter_cpp::HIGHLIGHT_QUERY,
-            "", // C++ doesn't have injections/locals in this version
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
+
+      for overlay in semantic[next_semantic..]
+         .iter()
+         .take_while(|overlay| overlay.start < token.end)
+      {
+         if overlay.start > start {
+            merged.push(Token {
+               start,
+               end: overlay.start,
+               ..token.clone()
+            });
+         }
+         start = start.max(overlay.end);
       }
-      "php" => {
-         let mut config = HighlightConfiguration::new(
-            tree_sitter_php::LANGUAGE_PHP.into(),
-            language_name,
-            tree_sitter_php::HIGHLIGHTS_QUERY,
-            tree_sitter_php::INJECTIONS_QUERY,
-            "",
-         )?;
-         config.configure(HIGHLIGHT_NAMES);
-         Ok(config)
+
+      if start < token.end {
+         merged.push(Token { start, ..token });
       }
-      _ => anyhow::bail!("Unsupported language: {}", language_name),
    }
+
+   merged.extend(semantic);
+   merged.sort_by_key(|token| token.start);
+   merged
 }
 
-fn map_highlight_to_class(highlight_name: &str) -> (&str, &str) {
-   match highlight_name {
-      "keyword" | "keyword.control" | "keyword.function" | "keyword.operator"
-      | "keyword.return" => ("keyword", "token-keyword"),
-      "string" | "string.escape" | "string.special" => ("string", "token-string"),
-      "number" => ("number", "token-number"),
-      "constant" | "constant.builtin" => ("constant", "token-constant"),
-      "comment" => ("comment", "token-comment"),
-      "function" | "function.builtin" | "function.method" => ("function", "token-function"),
-      "type" | "type.builtin" => ("type", "token-type"),
-      "variable" | "variable.builtin" | "variable.parameter" => ("identifier", "token-identifier"),
-      "property" | "property.builtin" => ("property", "token-property"),
-      "operator" => ("operator", "token-operator"),
-      "punctuation" | "punctuation.bracket" | "punctuation.delimiter" | "punctuation.special" => {
-         ("punctuation", "token-punctuation")
+#[tauri::command]
+pub async fn get_tokens(
+   lsp_manager: State<'_, LspManager>,
+   content: String,
+   file_extension: String,
+   file_path: Option<String>,
+) -> Result<Vec<Token>, String> {
+   let language = detect_language(file_path.as_deref(), &file_extension, &content)
+      .ok_or_else(|| format!("Unsupported file extension: {}", file_extension))?;
+
+   let tokens =
+      tokenize_content(&content, &language).map_err(|e| format!("Failed to tokenize: {e}"))?;
+
+   // Semantic tokens only apply to documents open with a language server
+   let Some(file_path) = file_path else {
+      return Ok(tokens);
+   };
+   let semantic = semantic_tokens(&lsp_manager, &file_path, &content).await;
+   Ok(overlay_semantic_tokens(tokens, semantic))
+}
+
+/// Semantic tokens of a file from its language server, classed like tree-sitter tokens. Empty
+/// when no server provides them for `content`.
+async fn semantic_tokens(lsp_manager: &LspManager, file_path: &str, content: &str) -> Vec<Token> {
+   let spans = match lsp_manager.semantic_tokens(file_path, content).await {
+      Ok(Some(spans)) => spans,
+      Ok(None) => return Vec::new(),
+      Err(e) => {
+         log::debug!("Semantic tokens unavailable for {}: {}", file_path, e);
+         return Vec::new();
       }
-      "tag" => ("jsx", "token-jsx"),
-      "attribute" => ("jsx-attribute", "token-jsx-attribute"),
-      _ => ("text", "token-text"),
-   }
+   };
+
+   spans
+      .iter()
+      .filter_map(|span| {
+         semantic_token_class(span).map(|(token_type, class_name)| Token {
+            start: span.start,
+            end: span.end,
+            token_type,
+            class_name,
+            capture: None,
+         })
+      })
+      .collect()
+}
+
+/// Layers the semantic tokens of a file over `tokens`, which cover `ranges` of `content`.
+/// Semantic tokens are clipped to `ranges` so they don't paint over what the frontend kept.
+async fn overlay_semantic_tokens_in(
+   lsp_manager: &LspManager,
+   file_path: &str,
+   content: &str,
+   tokens: Vec<Token>,
+   ranges: &[Range<usize>],
+) -> Vec<Token> {
+   let semantic = semantic_tokens(lsp_manager, file_path, content)
+      .await
+      .into_iter()
+      .flat_map(|token| {
+         ranges.iter().filter_map(move |range| {
+            let start = token.start.max(range.start);
+            let end = token.end.min(range.end);
+            (start < end).then(|| Token {
+               start,
+               end,
+               ..token.clone()
+            })
+         })
+      })
+      .collect();
+   overlay_semantic_tokens(tokens, semantic)
+}
+
+/// Opens a document for incremental highlighting. Tokens are then fetched with
+/// `get_tokens_in_range` and edits sent with `edit_syntax_document`.
+#[tauri::command]
+pub async fn open_syntax_document(
+   syntax_documents: State<'_, SyntaxDocuments>,
+   document_id: String,
+   content: String,
+   file_extension: String,
+   file_path: Option<String>,
+) -> Result<(), String> {
+   let language = detect_language(file_path.as_deref(), &file_extension, &content)
+      .ok_or_else(|| format!("Unsupported file extension: {}", file_extension))?;
+   syntax_documents
+      .open(&document_id, &language, file_path, content)
+      .map_err(|e| format!("Failed to parse: {e}"))
 }
 
+/// Tokens of an open document in a byte or line range, typically the viewport plus a margin.
+/// Documents opened with a file path get semantic tokens layered on top, like `get_tokens`.
 #[tauri::command]
-pub async fn get_tokens(content: String, file_extension: String) -> Result<Vec<Token>, String> {
-   // Determine language from extension
-   let language = match file_extension.as_str() {
-      "js" | "jsx" => "javascript",
-      "ts" => "typescript",
-      "tsx" => "tsx",
-      "json" => "json",
-      "yml" | "yaml" => "yaml",
-      "go" => "go",
-      "rb" | "ruby" => "ruby",
-      "rs" => "rust",
-      "erb" | "html.erb" => "erb",
-      "py" => "python",
-      "html" | "htm" => "html",
-      "css" => "css",
-      "md" | "markdown" => "markdown",
-      "sh" | "bash" => "bash",
-      "toml" => "toml",
-      "java" => "java",
-      "c" => "c",
-      "cpp" | "cxx" | "cc" | "c++" | "hpp" | "hxx" | "h++" => "cpp",
-      "php" => "php",
-      _ => return Err(format!("Unsupported file extension: {}", file_extension)),
+pub async fn get_tokens_in_range(
+   lsp_manager: State<'_, LspManager>,
+   syntax_documents: State<'_, SyntaxDocuments>,
+   document_id: String,
+   range: TokenRange,
+) -> Result<Vec<Token>, String> {
+   let tokens = syntax_documents
+      .tokens_in_range(&document_id, range)
+      .map_err(|e| format!("Failed to tokenize: {e}"))?;
+   let Some((file_path, text)) = syntax_documents.source(&document_id) else {
+      return Ok(tokens);
    };
+   let range = range.to_bytes(&text);
+   Ok(overlay_semantic_tokens_in(&lsp_manager, &file_path, &text, tokens, &[range]).await)
+}
 
-   tokenize_content(&content, language).map_err(|e| format!("Failed to tokenize: {e}"))
+/// Applies edits in the same form as `lsp_document_change` and returns the tokens of the
+/// ranges that need repainting, with semantic tokens layered on top like `get_tokens_in_range`.
+/// Servers only have semantic tokens for the new text once `lsp_document_change` reached them.
+#[tauri::command]
+pub async fn edit_syntax_document(
+   lsp_manager: State<'_, LspManager>,
+   syntax_documents: State<'_, SyntaxDocuments>,
+   document_id: String,
+   changes: Vec<TextDocumentContentChangeEvent>,
+) -> Result<TokenUpdate, String> {
+   let update = syntax_documents
+      .edit(&document_id, &changes)
+      .map_err(|e| format!("Failed to update tokens: {e}"))?;
+   let Some((file_path, text)) = syntax_documents.source(&document_id) else {
+      return Ok(update);
+   };
+   let ranges: Vec<Range<usize>> = update
+      .ranges
+      .iter()
+      .map(|range| range.start..range.end)
+      .collect();
+   let tokens =
+      overlay_semantic_tokens_in(&lsp_manager, &file_path, &text, update.tokens, &ranges).await;
+   Ok(TokenUpdate { tokens, ..update })
+}
+
+#[tauri::command]
+pub fn close_syntax_document(syntax_documents: State<'_, SyntaxDocuments>, document_id: String) {
+   syntax_documents.close(&document_id);
+}
+
+/// Rescans the user config directory for grammars, language definitions and query overrides.
+/// Documents opened afterwards use the changes.
+#[tauri::command]
+pub fn reload_user_grammars(app_handle: AppHandle) -> Result<Vec<UserLanguage>, String> {
+   let config_dir = app_handle
+      .path()
+      .app_config_dir()
+      .map_err(|e| format!("Failed to get config directory: {e}"))?;
+   Ok(load_user_languages(&config_dir))
 }
 
 pub fn tokenize_content(content: &str, language: &str) -> Result<Vec<Token>> {
    let config = get_language_config(language)?;
-   let mut highlighter = Highlighter::new();
-
-   let highlights = highlighter
-      .highlight(&config, content.as_bytes(), None, |_| None)?
-      .collect::<Result<Vec<_>, _>>()?;
-
-   let mut tokens = Vec::new();
-   let mut current_highlight: Option<usize> = None;
-
-   for event in highlights {
-      match event {
-         HighlightEvent::Source { start, end } => {
-            if let Some(highlight_idx) = current_highlight {
-               let highlight_name = HIGHLIGHT_NAMES.get(highlight_idx).unwrap_or(&"text");
-               let (token_type, class_name) = map_highlight_to_class(highlight_name);
-
-               // Skip whitespace-only tokens
-               let text = &content[start..end];
-               if !text.trim().is_empty() {
-                  tokens.push(Token {
-                     start,
-                     end,
-                     token_type: token_type.to_string(),
-                     class_name: class_name.to_string(),
-                  });
-               }
-            }
-         }
-         HighlightEvent::HighlightStart(highlight) => {
-            current_highlight = Some(highlight.0);
-         }
-         HighlightEvent::HighlightEnd => {
-            current_highlight = None;
-         }
-      }
-   }
-
-   Ok(tokens)
+   let tree = config.parse(content, None)?;
+   Ok(highlight(&config, &tree, content, 0..content.len()))
 }
 
 #[cfg(test)]
 mod tests {
    use super::*;
 
+   #[test]
+   fn test_overlay_semantic_tokens() {
+      let token = |start, end, token_type: &str| Token {
+         start,
+         end,
+         token_type: token_type.to_string(),
+         class_name: format!("token-{}", token_type),
+         capture: None,
+      };
+      let syntax = vec![
+         token(0, 3, "keyword"),
+         token(4, 20, "string"),
+         token(21, 25, "identifier"),
+      ];
+      let semantic = vec![
+         token(8, 12, "identifier"),
+         token(21, 25, "function"),
+         token(30, 34, "type"),
+      ];
+
+      let merged: Vec<(usize, usize, String)> = overlay_semantic_tokens(syntax, semantic)
+         .into_iter()
+         .map(|t| (t.start, t.end, t.token_type))
+         .collect();
+      assert_eq!(
+         merged,
+         vec![
+            (0, 3, "keyword".to_string()),
+            (4, 8, "string".to_string()),
+            (8, 12, "identifier".to_string()),
+            (12, 20, "string".to_string()),
+            (21, 25, "function".to_string()),
+            (30, 34, "type".to_string()),
+         ]
+      );
+
+      let span = SemanticTokenSpan {
+         start: 0,
+         end: 7,
+         token_type: "macro".to_string(),
+         modifiers: vec!["mutable".to_string()],
+      };
+      assert_eq!(
+         semantic_token_class(&span),
+         Some((
+            "function".to_string(),
+            "token-function token-macro token-mod-mutable".to_string()
+         ))
+      );
+   }
+
    #[test]
    fn test_tokenize_javascript() {
       let code = r#"const greeting = "Hello, world!";
@@ -651,4 +583,126 @@ numbers = [1, 2, 3].map { |n| n * 2 }"#;
       );
       assert!(token_types.contains(&"number"), "Should have number tokens");
    }
+
+   #[test]
+   fn test_tokenize_injections() {
+      let token_at = |tokens: &[Token], code: &str, text: &str| {
+         let start = code.find(text).unwrap();
+         tokens
+            .iter()
+            .find(|t| t.start == start)
+            .map(|t| t.token_type.clone())
+      };
+
+      let html = "<script>const a = 1;</script>\n<style>p { color: red; }</style>";
+      let tokens = tokenize_content(html, "html").unwrap();
+      assert_eq!(token_at(&tokens, html, "const").as_deref(), Some("keyword"));
+      assert_eq!(
+         token_at(&tokens, html, "color").as_deref(),
+         Some("property")
+      );
+
+      // Markdown injects its inline grammar, which injects HTML; fences are looked up by name
+      // or extension
+      let markdown = "Some <b>bold</b> text\n\n```rs\nfn main() {}\n```\n";
+      let tokens = tokenize_content(markdown, "markdown").unwrap();
+      assert_eq!(token_at(&tokens, markdown, "b>").as_deref(), Some("jsx"));
+      assert_eq!(
+         token_at(&tokens, markdown, "fn").as_deref(),
+         Some("keyword")
+      );
+
+      let erb = "<p><% if @user %>Hi</p>";
+      let tokens = tokenize_content(erb, "erb").unwrap();
+      assert_eq!(token_at(&tokens, erb, "p>").as_deref(), Some("jsx"));
+      assert_eq!(token_at(&tokens, erb, "if").as_deref(), Some("keyword"));
+   }
+
+   #[test]
+   fn test_tokenize_typescript() {
+      let code = r#"interface Greeter<T> { greet(name: string): T; }
+
+@Component({})
+class Hello {
+    say(message: string) {
+        return message;
+    }
+}"#;
+
+      let tokens = tokenize_content(code, "typescript").unwrap();
+      let capture_at = |text: &str, nth: usize| {
+         let start = code.match_indices(text).nth(nth).unwrap().0;
+         tokens
+            .iter()
+            .find(|t| t.start == start)
+            .and_then(|t| t.capture.clone())
+      };
+
+      assert_eq!(capture_at("interface", 0).as_deref(), Some("keyword"));
+      assert_eq!(capture_at("string", 0).as_deref(), Some("type.builtin"));
+      assert_eq!(capture_at("T", 1).as_deref(), Some("type"));
+      assert_eq!(
+         capture_at("Component", 0).as_deref(),
+         Some("function.decorator")
+      );
+      // The parameter's reference is highlighted like its definition
+      assert_eq!(
+         capture_at("message", 0).as_deref(),
+         Some("variable.parameter")
+      );
+      assert_eq!(
+         capture_at("message", 1).as_deref(),
+         Some("variable.parameter")
+      );
+
+      let parameter = tokens
+         .iter()
+         .find(|t| t.capture.as_deref() == Some("variable.parameter"))
+         .unwrap();
+      assert_eq!(parameter.token_type, "identifier");
+      assert_eq!(
+         parameter.class_name,
+         "token-identifier token-variable-parameter"
+      );
+   }
+
+   #[test]
+   fn test_tokenize_more_grammars() {
+      let samples = [
+         (
+            "csharp",
+            "public class Greeter { string Greet() { return \"hi\"; } }",
+         ),
+         (
+            "elixir",
+            "defmodule Greeter do\n  def greet, do: \"hi\"\nend",
+         ),
+         ("swift", "func greet() -> String { return \"hi\" }"),
+         ("lua", "local function greet()\n  return \"hi\"\nend"),
+         ("kotlin", "fun greet(): String {\n  return \"hi\"\n}"),
+         ("sql", "SELECT name FROM users WHERE name = 'hi';"),
+         ("dockerfile", "FROM alpine\nLABEL greeting=\"hi\"\n"),
+         ("zig", "pub fn greet() []const u8 {\n    return \"hi\";\n}"),
+         (
+            "haskell",
+            "greet :: String\ngreet = if True then \"hi\" else \"\"",
+         ),
+         ("scala", "object Greeter {\n  def greet: String = \"hi\"\n}"),
+         ("nix", "let greeting = \"hi\"; in greeting"),
+         ("hcl", "variable \"greeting\" {\n  default = \"hi\"\n}"),
+         ("make", "ifdef GREETING\nMESSAGE = \"hi\"\nendif\n"),
+      ];
+      for (language, code) in samples {
+         let tokens = tokenize_content(code, language).unwrap();
+         let token_types: Vec<&str> = tokens.iter().map(|t| t.token_type.as_str()).collect();
+         assert!(
+            token_types.contains(&"keyword"),
+            "{language} should have keyword tokens"
+         );
+         assert!(
+            token_types.contains(&"string"),
+            "{language} should have string tokens: {token_types:?}"
+         );
+      }
+   }
 }
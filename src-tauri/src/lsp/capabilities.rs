@@ -0,0 +1,395 @@
+use lsp_types::*;
+
+/// Name sent as `clientInfo` in `initialize`.
+pub const CLIENT_NAME: &str = "Athas";
+
+/// Capabilities announced in `initialize`. Servers turn features off for anything left out, so
+/// this has to track what the backend actually handles; extend it alongside new requests.
+pub fn client_capabilities() -> ClientCapabilities {
+   ClientCapabilities {
+      general: Some(GeneralClientCapabilities {
+         position_encodings: Some(vec![PositionEncodingKind::UTF16]),
+         ..Default::default()
+      }),
+      workspace: Some(workspace_capabilities()),
+      text_document: Some(text_document_capabilities()),
+      window: Some(WindowClientCapabilities {
+         work_done_progress: Some(true),
+         show_message: Some(ShowMessageRequestClientCapabilities {
+            message_action_item: Some(MessageActionItemCapabilities {
+               additional_properties_support: Some(false),
+            }),
+         }),
+         show_document: Some(ShowDocumentClientCapabilities { support: true }),
+      }),
+      ..Default::default()
+   }
+}
+
+fn workspace_capabilities() -> WorkspaceClientCapabilities {
+   WorkspaceClientCapabilities {
+      apply_edit: Some(true),
+      workspace_edit: Some(WorkspaceEditClientCapabilities {
+         document_changes: Some(true),
+         resource_operations: Some(vec![
+            ResourceOperationKind::Create,
+            ResourceOperationKind::Rename,
+            ResourceOperationKind::Delete,
+         ]),
+         normalizes_line_endings: Some(false),
+         ..Default::default()
+      }),
+      execute_command: Some(DynamicRegistrationClientCapabilities {
+         dynamic_registration: Some(false),
+      }),
+      workspace_folders: Some(true),
+      configuration: Some(true),
+      symbol: Some(WorkspaceSymbolClientCapabilities {
+         dynamic_registration: Some(false),
+         symbol_kind: Some(symbol_kinds()),
+         tag_support: None,
+         resolve_support: Some(WorkspaceSymbolResolveSupportCapability {
+            properties: vec!["location.range".to_string()],
+         }),
+      }),
+      diagnostic: Some(DiagnosticWorkspaceClientCapabilities {
+         refresh_support: Some(true),
+      }),
+      semantic_tokens: Some(SemanticTokensWorkspaceClientCapabilities {
+         refresh_support: Some(true),
+      }),
+      inlay_hint: Some(InlayHintWorkspaceClientCapabilities {
+         refresh_support: Some(true),
+      }),
+      ..Default::default()
+   }
+}
+
+fn text_document_capabilities() -> TextDocumentClientCapabilities {
+   let goto = Some(GotoCapability {
+      dynamic_registration: Some(false),
+      link_support: Some(true),
+   });
+
+   TextDocumentClientCapabilities {
+      synchronization: Some(TextDocumentSyncClientCapabilities {
+         dynamic_registration: Some(false),
+         will_save: Some(false),
+         will_save_wait_until: Some(false),
+         did_save: Some(false),
+      }),
+      completion: Some(CompletionClientCapabilities {
+         dynamic_registration: Some(false),
+         completion_item: Some(CompletionItemCapability {
+            snippet_support: Some(true),
+            documentation_format: Some(vec![MarkupKind::Markdown, MarkupKind::PlainText]),
+            deprecated_support: Some(true),
+            label_details_support: Some(true),
+            resolve_support: Some(CompletionItemCapabilityResolveSupport {
+               properties: vec![
+                  "documentation".to_string(),
+                  "detail".to_string(),
+                  "additionalTextEdits".to_string(),
+               ],
+            }),
+            ..Default::default()
+         }),
+         completion_item_kind: Some(CompletionItemKindCapability { value_set: None }),
+         context_support: Some(true),
+         ..Default::default()
+      }),
+      hover: Some(HoverClientCapabilities {
+         dynamic_registration: Some(false),
+         content_format: Some(vec![MarkupKind::Markdown, MarkupKind::PlainText]),
+      }),
+      signature_help: Some(SignatureHelpClientCapabilities {
+         dynamic_registration: Some(false),
+         signature_information: Some(SignatureInformationSettings {
+            documentation_format: Some(vec![MarkupKind::Markdown, MarkupKind::PlainText]),
+            parameter_information: Some(ParameterInformationSettings {
+               label_offset_support: Some(true),
+            }),
+            active_parameter_support: Some(true),
+         }),
+         context_support: Some(true),
+      }),
+      inlay_hint: Some(InlayHintClientCapabilities {
+         dynamic_registration: Some(false),
+         resolve_support: Some(InlayHintResolveClientCapabilities {
+            properties: vec!["tooltip".to_string(), "label.tooltip".to_string()],
+         }),
+      }),
+      document_symbol: Some(DocumentSymbolClientCapabilities {
+         dynamic_registration: Some(false),
+         symbol_kind: Some(symbol_kinds()),
+         hierarchical_document_symbol_support: Some(true),
+         tag_support: None,
+      }),
+      document_highlight: Some(DynamicRegistrationClientCapabilities {
+         dynamic_registration: Some(false),
+      }),
+      document_link: Some(DocumentLinkClientCapabilities {
+         dynamic_registration: Some(false),
+         tooltip_support: Some(true),
+      }),
+      folding_range: Some(FoldingRangeClientCapabilities {
+         dynamic_registration: Some(false),
+         range_limit: Some(5000),
+         line_folding_only: Some(false),
+         folding_range_kind: Some(FoldingRangeKindCapability {
+            value_set: Some(vec![
+               FoldingRangeKind::Comment,
+               FoldingRangeKind::Imports,
+               FoldingRangeKind::Region,
+            ]),
+         }),
+         folding_range: Some(FoldingRangeCapability {
+            collapsed_text: Some(true),
+         }),
+      }),
+      selection_range: Some(SelectionRangeClientCapabilities {
+         dynamic_registration: Some(false),
+      }),
+      diagnostic: Some(DiagnosticClientCapabilities {
+         dynamic_registration: Some(false),
+         related_document_support: Some(true),
+      }),
+      call_hierarchy: Some(DynamicRegistrationClientCapabilities {
+         dynamic_registration: Some(false),
+      }),
+      type_hierarchy: Some(DynamicRegistrationClientCapabilities {
+         dynamic_registration: Some(false),
+      }),
+      semantic_tokens: Some(SemanticTokensClientCapabilities {
+         dynamic_registration: Some(false),
+         requests: SemanticTokensClientCapabilitiesRequests {
+            range: Some(false),
+            full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+         },
+         token_types: vec![
+            SemanticTokenType::NAMESPACE,
+            SemanticTokenType::TYPE,
+            SemanticTokenType::CLASS,
+            SemanticTokenType::ENUM,
+            SemanticTokenType::INTERFACE,
+            SemanticTokenType::STRUCT,
+            SemanticTokenType::TYPE_PARAMETER,
+            SemanticTokenType::PARAMETER,
+            SemanticTokenType::VARIABLE,
+            SemanticTokenType::PROPERTY,
+            SemanticTokenType::ENUM_MEMBER,
+            SemanticTokenType::EVENT,
+            SemanticTokenType::FUNCTION,
+            SemanticTokenType::METHOD,
+            SemanticTokenType::MACRO,
+            SemanticTokenType::KEYWORD,
+            SemanticTokenType::MODIFIER,
+            SemanticTokenType::COMMENT,
+            SemanticTokenType::STRING,
+            SemanticTokenType::NUMBER,
+            SemanticTokenType::REGEXP,
+            SemanticTokenType::OPERATOR,
+            SemanticTokenType::DECORATOR,
+         ],
+         token_modifiers: vec![
+            SemanticTokenModifier::DECLARATION,
+            SemanticTokenModifier::DEFINITION,
+            SemanticTokenModifier::READONLY,
+            SemanticTokenModifier::STATIC,
+            SemanticTokenModifier::DEPRECATED,
+            SemanticTokenModifier::ABSTRACT,
+            SemanticTokenModifier::ASYNC,
+            SemanticTokenModifier::MODIFICATION,
+            SemanticTokenModifier::DOCUMENTATION,
+            SemanticTokenModifier::DEFAULT_LIBRARY,
+         ],
+         formats: vec![TokenFormat::RELATIVE],
+         overlapping_token_support: Some(false),
+         multiline_token_support: Some(false),
+         server_cancel_support: Some(true),
+         augments_syntax_tokens: Some(true),
+      }),
+      definition: goto,
+      declaration: goto,
+      type_definition: goto,
+      implementation: goto,
+      references: Some(DynamicRegistrationClientCapabilities {
+         dynamic_registration: Some(false),
+      }),
+      rename: Some(RenameClientCapabilities {
+         dynamic_registration: Some(false),
+         prepare_support: Some(true),
+         prepare_support_default_behavior: Some(PrepareSupportDefaultBehavior::IDENTIFIER),
+         honors_change_annotations: Some(false),
+      }),
+      code_action: Some(CodeActionClientCapabilities {
+         dynamic_registration: Some(false),
+         code_action_literal_support: Some(CodeActionLiteralSupport {
+            code_action_kind: CodeActionKindLiteralSupport {
+               value_set: [
+                  CodeActionKind::EMPTY,
+                  CodeActionKind::QUICKFIX,
+                  CodeActionKind::REFACTOR,
+                  CodeActionKind::REFACTOR_EXTRACT,
+                  CodeActionKind::REFACTOR_INLINE,
+                  CodeActionKind::REFACTOR_REWRITE,
+                  CodeActionKind::SOURCE,
+                  CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                  CodeActionKind::SOURCE_FIX_ALL,
+               ]
+               .iter()
+               .map(|kind| kind.as_str().to_string())
+               .collect(),
+            },
+         }),
+         is_preferred_support: Some(true),
+         disabled_support: Some(true),
+         data_support: Some(true),
+         resolve_support: Some(CodeActionCapabilityResolveSupport {
+            properties: vec!["edit".to_string()],
+         }),
+         honors_change_annotations: Some(false),
+      }),
+      publish_diagnostics: Some(PublishDiagnosticsClientCapabilities {
+         related_information: Some(true),
+         tag_support: Some(TagSupport {
+            value_set: vec![DiagnosticTag::UNNECESSARY, DiagnosticTag::DEPRECATED],
+         }),
+         version_support: Some(false),
+         code_description_support: Some(true),
+         data_support: Some(true),
+      }),
+      ..Default::default()
+   }
+}
+
+/// Every kind, so servers don't fall back to the few from the first protocol version.
+fn symbol_kinds() -> SymbolKindCapability {
+   SymbolKindCapability {
+      value_set: Some(
+         [
+            SymbolKind::FILE,
+            SymbolKind::MODULE,
+            SymbolKind::NAMESPACE,
+            SymbolKind::PACKAGE,
+            SymbolKind::CLASS,
+            SymbolKind::METHOD,
+            SymbolKind::PROPERTY,
+            SymbolKind::FIELD,
+            SymbolKind::CONSTRUCTOR,
+            SymbolKind::ENUM,
+            SymbolKind::INTERFACE,
+            SymbolKind::FUNCTION,
+            SymbolKind::VARIABLE,
+            SymbolKind::CONSTANT,
+            SymbolKind::STRING,
+            SymbolKind::NUMBER,
+            SymbolKind::BOOLEAN,
+            SymbolKind::ARRAY,
+            SymbolKind::OBJECT,
+            SymbolKind::KEY,
+            SymbolKind::NULL,
+            SymbolKind::ENUM_MEMBER,
+            SymbolKind::STRUCT,
+            SymbolKind::EVENT,
+            SymbolKind::OPERATOR,
+            SymbolKind::TYPE_PARAMETER,
+         ]
+         .to_vec(),
+      ),
+   }
+}
+
+/// How a server wants `didChange` sent. Incremental ranges are only produced in UTF-16, so a
+/// server that settled on another position encoding gets the full text instead. Servers that
+/// haven't said anything get full sync, which every server understands.
+pub fn change_sync_kind(capabilities: Option<&ServerCapabilities>) -> TextDocumentSyncKind {
+   let Some(capabilities) = capabilities else {
+      return TextDocumentSyncKind::FULL;
+   };
+
+   let kind = match &capabilities.text_document_sync {
+      Some(TextDocumentSyncCapability::Kind(kind)) => *kind,
+      Some(TextDocumentSyncCapability::Options(options)) => {
+         options.change.unwrap_or(TextDocumentSyncKind::NONE)
+      }
+      None => TextDocumentSyncKind::NONE,
+   };
+
+   let utf16 = capabilities
+      .position_encoding
+      .as_ref()
+      .is_none_or(|encoding| *encoding == PositionEncodingKind::UTF16);
+   if kind == TextDocumentSyncKind::INCREMENTAL && !utf16 {
+      return TextDocumentSyncKind::FULL;
+   }
+   kind
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn server(
+      sync: TextDocumentSyncKind,
+      encoding: Option<PositionEncodingKind>,
+   ) -> ServerCapabilities {
+      ServerCapabilities {
+         text_document_sync: Some(TextDocumentSyncCapability::Kind(sync)),
+         position_encoding: encoding,
+         ..Default::default()
+      }
+   }
+
+   #[test]
+   fn test_change_sync_kind_falls_back_to_full_without_utf16() {
+      assert_eq!(change_sync_kind(None), TextDocumentSyncKind::FULL);
+      assert_eq!(
+         change_sync_kind(Some(&ServerCapabilities::default())),
+         TextDocumentSyncKind::NONE
+      );
+
+      let incremental = TextDocumentSyncKind::INCREMENTAL;
+      assert_eq!(
+         change_sync_kind(Some(&server(incremental, None))),
+         incremental
+      );
+      assert_eq!(
+         change_sync_kind(Some(&server(
+            incremental,
+            Some(PositionEncodingKind::UTF16)
+         ))),
+         incremental
+      );
+      assert_eq!(
+         change_sync_kind(Some(&server(incremental, Some(PositionEncodingKind::UTF8)))),
+         TextDocumentSyncKind::FULL
+      );
+      assert_eq!(
+         change_sync_kind(Some(&server(
+            incremental,
+            Some(PositionEncodingKind::UTF32)
+         ))),
+         TextDocumentSyncKind::FULL
+      );
+      assert_eq!(
+         change_sync_kind(Some(&server(
+            TextDocumentSyncKind::NONE,
+            Some(PositionEncodingKind::UTF8)
+         ))),
+         TextDocumentSyncKind::NONE
+      );
+
+      let options = ServerCapabilities {
+         text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+               change: Some(incremental),
+               ..Default::default()
+            },
+         )),
+         position_encoding: Some(PositionEncodingKind::UTF8),
+         ..Default::default()
+      };
+      assert_eq!(change_sync_kind(Some(&options)), TextDocumentSyncKind::FULL);
+   }
+}
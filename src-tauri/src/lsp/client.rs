@@ -1,4 +1,8 @@
-use anyhow::{Context, Result};
+use super::{
+   capabilities,
+   trace::{MessageTrace, TraceDirection},
+};
+use anyhow::{Context, Result, bail};
 use crossbeam_channel::{Sender, bounded};
 use lsp_types::*;
 use serde_json::{Value, json};
@@ -9,24 +13,222 @@ use std::{
    process::{Child, Command, Stdio},
    sync::{
       Arc, Mutex,
-      atomic::{AtomicU64, Ordering},
+      atomic::{AtomicBool, AtomicU64, Ordering},
    },
    thread,
+   time::Duration,
 };
-use tokio::sync::oneshot;
+use tokio::sync::{Notify, oneshot};
 
 type PendingRequests = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>>;
 
+/// A message the server initiated, as opposed to a response to one of our requests.
+pub enum ServerMessage {
+   Notification {
+      method: String,
+      params: Value,
+   },
+   Request {
+      method: String,
+      params: Value,
+      responder: Responder,
+   },
+}
+
+/// Called on the reader thread for every server-initiated message, so it must not block.
+pub type ServerMessageHandler = Arc<dyn Fn(ServerMessage) + Send + Sync>;
+
+/// Called once from the reader thread when the server's stdout closes, after pending requests
+/// have been failed.
+pub type ExitHandler = Box<dyn FnOnce() + Send>;
+
+/// JSON-RPC error code for methods the other side doesn't implement
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// How long `shutdown` waits for the server to acknowledge before giving up on it.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a request may take before it's cancelled. Interactive requests are abandoned quickly
+/// since a late answer is useless; indexing-bound ones get more slack.
+fn request_timeout(method: &str) -> Duration {
+   match method {
+      "textDocument/completion"
+      | "completionItem/resolve"
+      | "textDocument/hover"
+      | "textDocument/signatureHelp" => Duration::from_secs(10),
+      "initialize" | "workspace/executeCommand" | "textDocument/rename" => Duration::from_secs(120),
+      // Servers may hold this open until something changes
+      "workspace/diagnostic" => Duration::from_secs(300),
+      _ => Duration::from_secs(30),
+   }
+}
+
+/// Why a request didn't produce a result.
+#[derive(Debug)]
+pub enum RequestError {
+   /// The server answered with an error response
+   Server {
+      code: i64,
+      message: String,
+   },
+   /// Cancelled on our side, `$/cancelRequest` was sent
+   Cancelled,
+   TimedOut(Duration),
+}
+
+impl std::fmt::Display for RequestError {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      match self {
+         Self::Server { code, message } => write!(f, "LSP error {}: {}", code, message),
+         Self::Cancelled => write!(f, "Request cancelled"),
+         Self::TimedOut(timeout) => write!(f, "Request timed out after {:?}", timeout),
+      }
+   }
+}
+
+impl std::error::Error for RequestError {
+}
+
+/// Whether a request failed only because its result stopped mattering: cancelled by us, or
+/// dropped by the server with `RequestCancelled` or `ContentModified`. Not worth a warning.
+pub fn is_cancellation(error: &anyhow::Error) -> bool {
+   match error.downcast_ref::<RequestError>() {
+      Some(RequestError::Cancelled) => true,
+      Some(RequestError::Server { code, .. }) => {
+         *code == error_codes::REQUEST_CANCELLED || *code == error_codes::CONTENT_MODIFIED
+      }
+      _ => false,
+   }
+}
+
+/// Whether the server doesn't implement the method at all, for requests whose support servers
+/// can't advertise in capabilities we parse.
+pub fn is_method_not_found(error: &anyhow::Error) -> bool {
+   matches!(
+      error.downcast_ref::<RequestError>(),
+      Some(RequestError::Server { code, .. }) if *code == METHOD_NOT_FOUND
+   )
+}
+
+/// Cancels in-flight requests it's passed to. Cheap to clone; all clones share the state.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+   inner: Arc<(AtomicBool, Notify)>,
+}
+
+impl CancellationToken {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   pub fn cancel(&self) {
+      self.inner.0.store(true, Ordering::SeqCst);
+      self.inner.1.notify_waiters();
+   }
+
+   pub fn is_cancelled(&self) -> bool {
+      self.inner.0.load(Ordering::SeqCst)
+   }
+
+   async fn cancelled(&self) {
+      // The waiter is registered before checking so a cancel in between isn't missed
+      let notified = self.inner.1.notified();
+      if self.is_cancelled() {
+         return;
+      }
+      notified.await;
+   }
+}
+
+/// Answers one server-to-client request. Can be moved elsewhere and used later, for requests
+/// that wait on the user.
+pub struct Responder {
+   id: Value,
+   outbox: Outbox,
+}
+
+impl Responder {
+   pub fn respond(self, result: Value) {
+      let response = json!({
+          "jsonrpc": "2.0",
+          "id": self.id,
+          "result": result,
+      });
+      let _ = self.outbox.send(&response);
+   }
+
+   pub fn respond_error(self, code: i64, message: &str) {
+      let response = json!({
+          "jsonrpc": "2.0",
+          "id": self.id,
+          "error": { "code": code, "message": message },
+      });
+      let _ = self.outbox.send(&response);
+   }
+}
+
+/// The server's stdin. Everything sent through it is recorded in the trace.
+#[derive(Clone)]
+struct Outbox {
+   tx: Sender<String>,
+   trace: Arc<MessageTrace>,
+}
+
+impl Outbox {
+   fn send(&self, message: &Value) -> Result<()> {
+      let body = message.to_string();
+      self.trace.record(TraceDirection::Outgoing, message, &body);
+      self
+         .tx
+         .send(format!("Content-Length: {}\r\n\r\n{}", body.len(), body))
+         .context("Language server stdin is closed")
+   }
+}
+
+/// A request waiting on its answer. Dropping it before the answer arrives withdraws the request
+/// with `$/cancelRequest` if it was sent; the server may still answer, which is then ignored.
+struct PendingRequest<'a> {
+   client: &'a LspClient,
+   id: u64,
+   sent: bool,
+}
+
+impl Drop for PendingRequest<'_> {
+   fn drop(&mut self) {
+      let withdrawn = self
+         .client
+         .pending_requests
+         .lock()
+         .unwrap()
+         .remove(&self.id)
+         .is_some();
+      if withdrawn && self.sent {
+         let _ = self.client.notify::<notification::Cancel>(CancelParams {
+            id: NumberOrString::Number(self.id as i32),
+         });
+      }
+   }
+}
+
 #[derive(Clone)]
 pub struct LspClient {
    request_counter: Arc<AtomicU64>,
-   stdin_tx: Sender<String>,
+   outbox: Outbox,
    pending_requests: PendingRequests,
    capabilities: Arc<Mutex<Option<ServerCapabilities>>>,
+   // Set once the server's output closes, after which requests fail instead of waiting
+   closed: Arc<AtomicBool>,
 }
 
 impl LspClient {
-   pub fn start(server_path: PathBuf, args: Vec<String>, _root_uri: Url) -> Result<(Self, Child)> {
+   pub fn start(
+      server_path: PathBuf,
+      args: Vec<String>,
+      _root_uri: Url,
+      on_message: ServerMessageHandler,
+      on_exit: ExitHandler,
+      trace: Arc<MessageTrace>,
+   ) -> Result<(Self, Child)> {
       log::info!("Starting language server: {:?} {:?}", server_path, args);
       let mut child = Command::new(server_path)
          .args(args)
@@ -43,8 +245,16 @@ impl LspClient {
       let stderr = child.stderr.take().context("Failed to get stderr")?;
 
       let (stdin_tx, stdin_rx) = bounded::<String>(100);
+      let outbox = Outbox {
+         tx: stdin_tx,
+         trace: Arc::clone(&trace),
+      };
       let pending_requests = Arc::new(Mutex::new(HashMap::new()));
       let pending_requests_clone = Arc::clone(&pending_requests);
+      let responder_outbox = outbox.clone();
+      let stderr_trace = Arc::clone(&trace);
+      let closed = Arc::new(AtomicBool::new(false));
+      let closed_clone = Arc::clone(&closed);
 
       // Stderr reader thread
       thread::spawn(move || {
@@ -57,6 +267,7 @@ impl LspClient {
                Ok(_) => {
                   if !line.trim().is_empty() {
                      log::error!("LSP stderr: {}", line.trim());
+                     stderr_trace.record_stderr(line.trim_end());
                   }
                }
                Err(e) => {
@@ -83,15 +294,15 @@ impl LspClient {
       // Stdout reader thread
       thread::spawn(move || {
          let mut reader = BufReader::new(stdout);
-         loop {
+         'messages: loop {
             let mut headers: HashMap<String, String> = HashMap::new();
             let mut line = String::new();
 
             // Read headers
             loop {
                line.clear();
-               if reader.read_line(&mut line).is_err() {
-                  return;
+               if !matches!(reader.read_line(&mut line), Ok(n) if n > 0) {
+                  break 'messages;
                }
 
                if line == "\r\n" || line == "\n" {
@@ -116,23 +327,37 @@ impl LspClient {
             // Read content
             let mut content = vec![0u8; content_length];
             if reader.read_exact(&mut content).is_err() {
-               return;
+               break 'messages;
             }
 
             if let Ok(content_str) = String::from_utf8(content)
-               && let Ok(response) = serde_json::from_str::<Value>(&content_str)
+               && let Ok(message) = serde_json::from_str::<Value>(&content_str)
             {
-               log::debug!("LSP Response: {}", content_str);
-               Self::handle_response(response, &pending_requests_clone);
+               trace.record(TraceDirection::Incoming, &message, &content_str);
+               Self::handle_message(
+                  message,
+                  &pending_requests_clone,
+                  &responder_outbox,
+                  &on_message,
+               );
             }
          }
+
+         // The server is gone: nothing will answer what's still pending
+         closed_clone.store(true, Ordering::SeqCst);
+         for (_, tx) in pending_requests_clone.lock().unwrap().drain() {
+            let _ = tx.send(Err(anyhow::anyhow!("Language server exited")));
+         }
+         log::warn!("Language server output closed");
+         on_exit();
       });
 
       let client = Self {
          request_counter: Arc::new(AtomicU64::new(1)),
-         stdin_tx,
+         outbox,
          pending_requests,
          capabilities: Arc::new(Mutex::new(None)),
+         closed,
       };
 
       // Don't initialize here - we'll do it separately to avoid runtime issues
@@ -141,14 +366,33 @@ impl LspClient {
       Ok((client, child))
    }
 
-   pub async fn initialize(&self, root_uri: Url) -> Result<()> {
+   pub async fn initialize(
+      &self,
+      root_uri: Url,
+      initialization_options: Option<Value>,
+   ) -> Result<()> {
       log::info!("Initializing LSP server with root_uri: {}", root_uri);
 
+      let workspace_name = root_uri
+         .path_segments()
+         .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+         .unwrap_or_default()
+         .to_string();
+
       let init_params = InitializeParams {
          process_id: Some(std::process::id()),
          #[allow(deprecated)]
-         root_uri: Some(root_uri),
-         capabilities: ClientCapabilities::default(),
+         root_uri: Some(root_uri.clone()),
+         capabilities: capabilities::client_capabilities(),
+         initialization_options,
+         workspace_folders: Some(vec![WorkspaceFolder {
+            uri: root_uri,
+            name: workspace_name,
+         }]),
+         client_info: Some(ClientInfo {
+            name: capabilities::CLIENT_NAME.to_string(),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+         }),
          ..Default::default()
       };
 
@@ -156,9 +400,7 @@ impl LspClient {
          self.request::<request::Initialize>(init_params).await?;
       log::info!("LSP initialized successfully");
 
-      if let Some(caps) = initialize_result.capabilities.into() {
-         *self.capabilities.lock().unwrap() = Some(caps);
-      }
+      *self.capabilities.lock().unwrap() = Some(initialize_result.capabilities);
 
       // Send initialized notification
       self.notify::<notification::Initialized>(InitializedParams {})?;
@@ -166,12 +408,69 @@ impl LspClient {
       Ok(())
    }
 
+   /// What the server announced in its `initialize` response, `None` until initialized.
+   pub fn capabilities(&self) -> Option<ServerCapabilities> {
+      self.capabilities.lock().unwrap().clone()
+   }
+
+   pub fn change_sync_kind(&self) -> TextDocumentSyncKind {
+      capabilities::change_sync_kind(self.capabilities.lock().unwrap().as_ref())
+   }
+
+   fn handle_message(
+      mut message: Value,
+      pending: &PendingRequests,
+      outbox: &Outbox,
+      on_message: &ServerMessageHandler,
+   ) {
+      // Messages carrying a method come from the server, with an id when it expects an answer;
+      // everything else is a response to one of our requests
+      let Some(method) = message
+         .get("method")
+         .and_then(|m| m.as_str())
+         .map(str::to_string)
+      else {
+         Self::handle_response(message, pending);
+         return;
+      };
+
+      let params = message
+         .get_mut("params")
+         .map(Value::take)
+         .unwrap_or(Value::Null);
+
+      match message.get_mut("id").map(Value::take) {
+         Some(id) => on_message(ServerMessage::Request {
+            method,
+            params,
+            responder: Responder {
+               id,
+               outbox: outbox.clone(),
+            },
+         }),
+         None => on_message(ServerMessage::Notification { method, params }),
+      }
+   }
+
    fn handle_response(response: Value, pending: &PendingRequests) {
       if let Some(id) = response.get("id").and_then(|id| id.as_u64())
          && let Some(tx) = pending.lock().unwrap().remove(&id)
       {
          if let Some(error) = response.get("error") {
-            let _ = tx.send(Err(anyhow::anyhow!("LSP error: {:?}", error)));
+            let _ = tx.send(Err(
+               RequestError::Server {
+                  code: error
+                     .get("code")
+                     .and_then(Value::as_i64)
+                     .unwrap_or_default(),
+                  message: error
+                     .get("message")
+                     .and_then(Value::as_str)
+                     .unwrap_or_default()
+                     .to_string(),
+               }
+               .into(),
+            ));
          } else if let Some(result) = response.get("result") {
             let _ = tx.send(Ok(result.clone()));
          }
@@ -184,30 +483,98 @@ impl LspClient {
       R::Params: serde::Serialize,
       R::Result: serde::de::DeserializeOwned,
    {
+      self.request_cancellable::<R>(params, None).await
+   }
+
+   /// Sends a request and waits for the answer, the method's timeout, or `token` being
+   /// cancelled. The latter two withdraw the request with `$/cancelRequest`.
+   pub async fn request_cancellable<R>(
+      &self,
+      params: R::Params,
+      token: Option<&CancellationToken>,
+   ) -> Result<R::Result>
+   where
+      R: lsp_types::request::Request,
+      R::Params: serde::Serialize,
+      R::Result: serde::de::DeserializeOwned,
+   {
+      let params = serde_json::to_value(params).context("Failed to serialize request")?;
+      let response = self
+         .send_request(R::METHOD, params, token, request_timeout(R::METHOD))
+         .await?;
+      serde_json::from_value(response).context("Failed to deserialize response")
+   }
+
+   async fn send_request(
+      &self,
+      method: &str,
+      params: Value,
+      token: Option<&CancellationToken>,
+      timeout: Duration,
+   ) -> Result<Value> {
       let id = self.request_counter.fetch_add(1, Ordering::SeqCst);
       let (tx, rx) = oneshot::channel();
 
       self.pending_requests.lock().unwrap().insert(id, tx);
+      // Withdraws the request however this returns, including the caller dropping the future
+      let mut pending = PendingRequest {
+         client: self,
+         id,
+         sent: false,
+      };
+      // Checked after registering so a request can't slip in after the reader drained the map
+      if self.closed.load(Ordering::SeqCst) {
+         bail!("Language server is not running");
+      }
 
       let request = json!({
           "jsonrpc": "2.0",
           "id": id,
-          "method": R::METHOD,
+          "method": method,
           "params": params,
       });
 
-      log::debug!("LSP Request {}: {}", id, R::METHOD);
+      log::debug!("LSP Request {}: {}", id, method);
 
-      let msg = format!(
-         "Content-Length: {}\r\n\r\n{}",
-         request.to_string().len(),
-         request
-      );
+      self
+         .outbox
+         .send(&request)
+         .context("Failed to send request")?;
+      pending.sent = true;
+
+      let cancelled = async {
+         match token {
+            Some(token) => token.cancelled().await,
+            None => std::future::pending().await,
+         }
+      };
 
-      self.stdin_tx.send(msg).context("Failed to send request")?;
+      tokio::select! {
+         response = rx => response.context("Request cancelled")?,
+         _ = tokio::time::sleep(timeout) => {
+            log::warn!("LSP request {} ({}) timed out", id, method);
+            Err(RequestError::TimedOut(timeout).into())
+         }
+         _ = cancelled => Err(RequestError::Cancelled.into()),
+      }
+   }
 
-      let response = rx.await.context("Request cancelled")??;
-      serde_json::from_value(response).context("Failed to deserialize response")
+   pub fn is_running(&self) -> bool {
+      !self.closed.load(Ordering::SeqCst)
+   }
+
+   /// The `shutdown` request followed by the `exit` notification. Servers that don't answer
+   /// in time are told to exit anyway; the caller kills the process if it lingers.
+   pub async fn shutdown(&self) -> Result<()> {
+      let acknowledged =
+         tokio::time::timeout(SHUTDOWN_TIMEOUT, self.request::<request::Shutdown>(())).await;
+      let exit = self.notify::<notification::Exit>(());
+
+      match acknowledged {
+         Ok(result) => result?,
+         Err(_) => bail!("Timed out waiting for the server to shut down"),
+      }
+      exit
    }
 
    pub fn notify<N>(&self, params: N::Params) -> Result<()>
@@ -221,15 +588,9 @@ impl LspClient {
           "params": params,
       });
 
-      let msg = format!(
-         "Content-Length: {}\r\n\r\n{}",
-         notification.to_string().len(),
-         notification
-      );
-
       self
-         .stdin_tx
-         .send(msg)
+         .outbox
+         .send(&notification)
          .context("Failed to send notification")?;
       Ok(())
    }
@@ -237,12 +598,15 @@ impl LspClient {
    pub async fn text_document_completion(
       &self,
       params: CompletionParams,
+      token: Option<&CancellationToken>,
    ) -> Result<Option<CompletionResponse>> {
       log::info!(
          "Sending completion request to LSP server: {:?}",
          params.text_document_position.position
       );
-      let result = self.request::<request::Completion>(params).await;
+      let result = self
+         .request_cancellable::<request::Completion>(params, token)
+         .await;
       match &result {
          Ok(Some(response)) => {
             let count = match response {
@@ -252,13 +616,251 @@ impl LspClient {
             log::info!("LSP server returned {} completions", count);
          }
          Ok(None) => log::warn!("LSP server returned None for completions"),
+         Err(e) if is_cancellation(e) => log::debug!("LSP completion request cancelled"),
          Err(e) => log::error!("LSP completion request failed: {}", e),
       }
       result
    }
 
-   pub async fn text_document_hover(&self, params: HoverParams) -> Result<Option<Hover>> {
-      self.request::<request::HoverRequest>(params).await
+   pub async fn completion_resolve(&self, item: CompletionItem) -> Result<CompletionItem> {
+      self.request::<request::ResolveCompletionItem>(item).await
+   }
+
+   pub async fn text_document_hover(
+      &self,
+      params: HoverParams,
+      token: Option<&CancellationToken>,
+   ) -> Result<Option<Hover>> {
+      self
+         .request_cancellable::<request::HoverRequest>(params, token)
+         .await
+   }
+
+   pub async fn text_document_signature_help(
+      &self,
+      params: SignatureHelpParams,
+   ) -> Result<Option<SignatureHelp>> {
+      self.request::<request::SignatureHelpRequest>(params).await
+   }
+
+   pub async fn text_document_inlay_hint(
+      &self,
+      params: InlayHintParams,
+   ) -> Result<Option<Vec<InlayHint>>> {
+      self.request::<request::InlayHintRequest>(params).await
+   }
+
+   pub async fn inlay_hint_resolve(&self, hint: InlayHint) -> Result<InlayHint> {
+      self.request::<request::InlayHintResolveRequest>(hint).await
+   }
+
+   pub async fn text_document_document_symbol(
+      &self,
+      params: DocumentSymbolParams,
+   ) -> Result<Option<DocumentSymbolResponse>> {
+      self.request::<request::DocumentSymbolRequest>(params).await
+   }
+
+   pub async fn workspace_symbol(
+      &self,
+      params: WorkspaceSymbolParams,
+   ) -> Result<Option<WorkspaceSymbolResponse>> {
+      self
+         .request::<request::WorkspaceSymbolRequest>(params)
+         .await
+   }
+
+   pub async fn workspace_symbol_resolve(
+      &self,
+      symbol: WorkspaceSymbol,
+   ) -> Result<WorkspaceSymbol> {
+      self
+         .request::<request::WorkspaceSymbolResolve>(symbol)
+         .await
+   }
+
+   pub async fn semantic_tokens_full(
+      &self,
+      params: SemanticTokensParams,
+   ) -> Result<Option<SemanticTokensResult>> {
+      self
+         .request::<request::SemanticTokensFullRequest>(params)
+         .await
+   }
+
+   pub async fn semantic_tokens_full_delta(
+      &self,
+      params: SemanticTokensDeltaParams,
+   ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+      self
+         .request::<request::SemanticTokensFullDeltaRequest>(params)
+         .await
+   }
+
+   pub async fn text_document_definition(
+      &self,
+      params: GotoDefinitionParams,
+   ) -> Result<Option<GotoDefinitionResponse>> {
+      self.request::<request::GotoDefinition>(params).await
+   }
+
+   pub async fn text_document_declaration(
+      &self,
+      params: request::GotoDeclarationParams,
+   ) -> Result<Option<request::GotoDeclarationResponse>> {
+      self.request::<request::GotoDeclaration>(params).await
+   }
+
+   pub async fn text_document_type_definition(
+      &self,
+      params: request::GotoTypeDefinitionParams,
+   ) -> Result<Option<request::GotoTypeDefinitionResponse>> {
+      self.request::<request::GotoTypeDefinition>(params).await
+   }
+
+   pub async fn text_document_implementation(
+      &self,
+      params: request::GotoImplementationParams,
+   ) -> Result<Option<request::GotoImplementationResponse>> {
+      self.request::<request::GotoImplementation>(params).await
+   }
+
+   pub async fn text_document_diagnostic(
+      &self,
+      params: DocumentDiagnosticParams,
+   ) -> Result<DocumentDiagnosticReportResult> {
+      self
+         .request::<request::DocumentDiagnosticRequest>(params)
+         .await
+   }
+
+   pub async fn workspace_diagnostic(
+      &self,
+      params: WorkspaceDiagnosticParams,
+   ) -> Result<WorkspaceDiagnosticReportResult> {
+      self
+         .request::<request::WorkspaceDiagnosticRequest>(params)
+         .await
+   }
+
+   pub async fn text_document_document_highlight(
+      &self,
+      params: DocumentHighlightParams,
+   ) -> Result<Option<Vec<DocumentHighlight>>> {
+      self
+         .request::<request::DocumentHighlightRequest>(params)
+         .await
+   }
+
+   pub async fn text_document_document_link(
+      &self,
+      params: DocumentLinkParams,
+   ) -> Result<Option<Vec<DocumentLink>>> {
+      self.request::<request::DocumentLinkRequest>(params).await
+   }
+
+   pub async fn document_link_resolve(&self, link: DocumentLink) -> Result<DocumentLink> {
+      self.request::<request::DocumentLinkResolve>(link).await
+   }
+
+   pub async fn text_document_folding_range(
+      &self,
+      params: FoldingRangeParams,
+   ) -> Result<Option<Vec<FoldingRange>>> {
+      self.request::<request::FoldingRangeRequest>(params).await
+   }
+
+   pub async fn text_document_selection_range(
+      &self,
+      params: SelectionRangeParams,
+   ) -> Result<Option<Vec<SelectionRange>>> {
+      self.request::<request::SelectionRangeRequest>(params).await
+   }
+
+   pub async fn prepare_call_hierarchy(
+      &self,
+      params: CallHierarchyPrepareParams,
+   ) -> Result<Option<Vec<CallHierarchyItem>>> {
+      self.request::<request::CallHierarchyPrepare>(params).await
+   }
+
+   pub async fn call_hierarchy_incoming_calls(
+      &self,
+      params: CallHierarchyIncomingCallsParams,
+   ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+      self
+         .request::<request::CallHierarchyIncomingCalls>(params)
+         .await
+   }
+
+   pub async fn call_hierarchy_outgoing_calls(
+      &self,
+      params: CallHierarchyOutgoingCallsParams,
+   ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+      self
+         .request::<request::CallHierarchyOutgoingCalls>(params)
+         .await
+   }
+
+   pub async fn prepare_type_hierarchy(
+      &self,
+      params: TypeHierarchyPrepareParams,
+   ) -> Result<Option<Vec<TypeHierarchyItem>>> {
+      self.request::<request::TypeHierarchyPrepare>(params).await
+   }
+
+   pub async fn type_hierarchy_supertypes(
+      &self,
+      params: TypeHierarchySupertypesParams,
+   ) -> Result<Option<Vec<TypeHierarchyItem>>> {
+      self
+         .request::<request::TypeHierarchySupertypes>(params)
+         .await
+   }
+
+   pub async fn type_hierarchy_subtypes(
+      &self,
+      params: TypeHierarchySubtypesParams,
+   ) -> Result<Option<Vec<TypeHierarchyItem>>> {
+      self.request::<request::TypeHierarchySubtypes>(params).await
+   }
+
+   pub async fn text_document_references(
+      &self,
+      params: ReferenceParams,
+   ) -> Result<Option<Vec<Location>>> {
+      self.request::<request::References>(params).await
+   }
+
+   pub async fn text_document_prepare_rename(
+      &self,
+      params: TextDocumentPositionParams,
+   ) -> Result<Option<PrepareRenameResponse>> {
+      self.request::<request::PrepareRenameRequest>(params).await
+   }
+
+   pub async fn text_document_rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+      self.request::<request::Rename>(params).await
+   }
+
+   pub async fn text_document_code_action(
+      &self,
+      params: CodeActionParams,
+   ) -> Result<Option<CodeActionResponse>> {
+      self.request::<request::CodeActionRequest>(params).await
+   }
+
+   pub async fn code_action_resolve(&self, action: CodeAction) -> Result<CodeAction> {
+      self
+         .request::<request::CodeActionResolveRequest>(action)
+         .await
+   }
+
+   pub async fn workspace_execute_command(
+      &self,
+      params: ExecuteCommandParams,
+   ) -> Result<Option<Value>> {
+      self.request::<request::ExecuteCommand>(params).await
    }
 
    pub fn text_document_did_open(&self, params: DidOpenTextDocumentParams) -> Result<()> {
@@ -273,3 +875,144 @@ impl LspClient {
       self.notify::<notification::DidCloseTextDocument>(params)
    }
 }
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crossbeam_channel::Receiver;
+
+   fn fake_client() -> (LspClient, Receiver<String>) {
+      let (tx, rx) = bounded(16);
+      let client = LspClient {
+         request_counter: Arc::new(AtomicU64::new(1)),
+         outbox: Outbox {
+            tx,
+            trace: Arc::new(MessageTrace::new()),
+         },
+         pending_requests: Arc::new(Mutex::new(HashMap::new())),
+         capabilities: Arc::new(Mutex::new(None)),
+         closed: Arc::new(AtomicBool::new(false)),
+      };
+      (client, rx)
+   }
+
+   fn sent_message(rx: &Receiver<String>) -> Value {
+      let frame = rx.try_recv().expect("a message was sent");
+      let (_, body) = frame.split_once("\r\n\r\n").unwrap();
+      serde_json::from_str(body).unwrap()
+   }
+
+   fn is_pending(client: &LspClient) -> bool {
+      !client.pending_requests.lock().unwrap().is_empty()
+   }
+
+   #[test]
+   fn test_request_timeout_depends_on_method() {
+      assert_eq!(
+         request_timeout("textDocument/hover"),
+         Duration::from_secs(10)
+      );
+      assert_eq!(
+         request_timeout("textDocument/rename"),
+         Duration::from_secs(120)
+      );
+      assert_eq!(
+         request_timeout("workspace/diagnostic"),
+         Duration::from_secs(300)
+      );
+      assert_eq!(
+         request_timeout("textDocument/definition"),
+         Duration::from_secs(30)
+      );
+   }
+
+   #[tokio::test]
+   async fn test_answered_request_is_not_cancelled() {
+      let (client, rx) = fake_client();
+      let request = client.send_request("test/answered", Value::Null, None, Duration::from_secs(5));
+      let answer = async {
+         while !is_pending(&client) {
+            tokio::task::yield_now().await;
+         }
+         let id = sent_message(&rx)["id"].clone();
+         LspClient::handle_response(json!({ "id": id, "result": 42 }), &client.pending_requests);
+      };
+
+      let (result, _) = tokio::join!(request, answer);
+      assert_eq!(result.unwrap(), json!(42));
+      assert!(!is_pending(&client));
+      assert!(rx.try_recv().is_err());
+   }
+
+   #[tokio::test]
+   async fn test_timed_out_request_is_withdrawn() {
+      let (client, rx) = fake_client();
+      let error = client
+         .send_request("test/slow", Value::Null, None, Duration::from_millis(10))
+         .await
+         .unwrap_err();
+
+      assert!(matches!(
+         error.downcast_ref::<RequestError>(),
+         Some(RequestError::TimedOut(_))
+      ));
+      assert!(!is_pending(&client));
+      let request = sent_message(&rx);
+      let cancel = sent_message(&rx);
+      assert_eq!(cancel["method"], "$/cancelRequest");
+      assert_eq!(cancel["params"]["id"], request["id"]);
+   }
+
+   #[tokio::test]
+   async fn test_cancelled_request_is_withdrawn() {
+      let (client, rx) = fake_client();
+      let token = CancellationToken::new();
+      token.cancel();
+      let error = client
+         .send_request(
+            "test/cancelled",
+            Value::Null,
+            Some(&token),
+            Duration::from_secs(5),
+         )
+         .await
+         .unwrap_err();
+
+      assert!(is_cancellation(&error));
+      assert!(!is_pending(&client));
+      sent_message(&rx);
+      assert_eq!(sent_message(&rx)["method"], "$/cancelRequest");
+   }
+
+   #[tokio::test]
+   async fn test_dropped_request_is_withdrawn() {
+      let (client, rx) = fake_client();
+      let request = client.send_request("test/dropped", Value::Null, None, Duration::from_secs(5));
+      let result = tokio::time::timeout(Duration::from_millis(10), request).await;
+
+      assert!(result.is_err());
+      assert!(!is_pending(&client));
+      sent_message(&rx);
+      assert_eq!(sent_message(&rx)["method"], "$/cancelRequest");
+   }
+
+   #[tokio::test]
+   async fn test_unsent_request_is_forgotten() {
+      let (client, rx) = fake_client();
+      drop(rx);
+      let result = client
+         .send_request("test/unsent", Value::Null, None, Duration::from_secs(5))
+         .await;
+      assert!(result.is_err());
+      assert!(!is_pending(&client));
+
+      let (client, rx) = fake_client();
+      client.closed.store(true, Ordering::SeqCst);
+      let result = client
+         .send_request("test/closed", Value::Null, None, Duration::from_secs(5))
+         .await;
+      assert!(result.is_err());
+      assert!(!is_pending(&client));
+      assert!(rx.try_recv().is_err());
+   }
+}
@@ -1,5 +1,15 @@
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use serde_json::Value;
+use std::{
+   collections::HashMap,
+   path::{Path, PathBuf},
+};
+
+/// Name of the per-workspace LSP config, relative to the workspace root.
+pub const WORKSPACE_CONFIG_PATH: &str = ".athas/lsp.json";
+/// Name of the user LSP config, relative to the app config directory.
+pub const USER_CONFIG_FILE: &str = "lsp.json";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LspSettings {
@@ -15,102 +25,542 @@ impl Default for LspSettings {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct LspServerConfig {
    pub name: String,
-   pub language_id: String,
    pub command: PathBuf,
    pub args: Vec<String>,
-   pub file_extensions: Vec<String>,
+   /// LSP language ids this server handles, e.g. `typescriptreact`
+   pub language_ids: Vec<String>,
+   /// Files whose presence marks a project root for this server, e.g. `Cargo.toml`
+   pub root_markers: Vec<String>,
+   pub initialization_options: Option<Value>,
+   /// Answers `workspace/configuration` requests, looked up by the requested section
+   pub settings: Option<Value>,
+   pub enabled: bool,
+   /// Shown when the server binary can't be found
+   pub install_hint: Option<String>,
 }
 
+impl LspServerConfig {
+   fn builtin(
+      name: &str,
+      command: &str,
+      args: &[&str],
+      language_ids: &[&str],
+      root_markers: &[&str],
+      install_hint: &str,
+   ) -> Self {
+      Self {
+         name: name.to_string(),
+         command: PathBuf::from(command),
+         args: args.iter().map(|s| s.to_string()).collect(),
+         language_ids: language_ids.iter().map(|s| s.to_string()).collect(),
+         root_markers: root_markers.iter().map(|s| s.to_string()).collect(),
+         initialization_options: None,
+         settings: None,
+         enabled: true,
+         install_hint: Some(install_hint.to_string()),
+      }
+   }
+
+   pub fn handles_language(&self, language_id: &str) -> bool {
+      self.language_ids.iter().any(|id| id == language_id)
+   }
+
+   /// The value of a dotted `workspace/configuration` section such as `rust-analyzer.check`.
+   /// No section means the whole settings object; anything missing is `null`.
+   pub fn settings_section(&self, section: Option<&str>) -> Value {
+      let Some(settings) = &self.settings else {
+         return Value::Null;
+      };
+
+      section
+         .filter(|s| !s.is_empty())
+         .map_or(Some(settings), |section| {
+            section
+               .split('.')
+               .try_fold(settings, |value, key| value.get(key))
+         })
+         .cloned()
+         .unwrap_or(Value::Null)
+   }
+
+   /// Nearest directory above `file` holding one of the root markers, without leaving
+   /// `workspace`. Servers without markers, and files with none above them, use the workspace.
+   pub fn find_root(&self, file: &Path, workspace: Option<&Path>) -> PathBuf {
+      let fallback = workspace
+         .or_else(|| file.parent())
+         .map(Path::to_path_buf)
+         .unwrap_or_default();
+
+      if self.root_markers.is_empty() {
+         return fallback;
+      }
+
+      for dir in file.ancestors().skip(1) {
+         if self.root_markers.iter().any(|m| dir.join(m).exists()) {
+            return dir.to_path_buf();
+         }
+         if workspace.is_some_and(|w| dir == w) {
+            break;
+         }
+      }
+
+      fallback
+   }
+
+   fn apply(&mut self, overrides: LspServerOverride) {
+      if let Some(command) = overrides.command {
+         self.command = command;
+      }
+      if let Some(args) = overrides.args {
+         self.args = args;
+      }
+      if let Some(language_ids) = overrides.language_ids {
+         self.language_ids = language_ids;
+      }
+      if let Some(root_markers) = overrides.root_markers {
+         self.root_markers = root_markers;
+      }
+      if overrides.initialization_options.is_some() {
+         self.initialization_options = overrides.initialization_options;
+      }
+      if overrides.settings.is_some() {
+         self.settings = overrides.settings;
+      }
+      if let Some(enabled) = overrides.enabled {
+         self.enabled = enabled;
+      }
+   }
+}
+
+/// A server entry from a config file. Fields left out keep the built-in value, so a config can
+/// tweak a single setting of a known server or describe a brand new one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LspServerOverride {
+   pub command: Option<PathBuf>,
+   pub args: Option<Vec<String>>,
+   pub language_ids: Option<Vec<String>>,
+   pub root_markers: Option<Vec<String>>,
+   pub initialization_options: Option<Value>,
+   pub settings: Option<Value>,
+   pub enabled: Option<bool>,
+}
+
+/// Contents of a user or workspace `lsp.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LspConfigFile {
+   pub servers: HashMap<String, LspServerOverride>,
+   /// Extra file extension (or file name) to language id mappings
+   pub languages: HashMap<String, String>,
+}
+
+impl LspConfigFile {
+   pub fn load(path: &Path) -> Result<Option<Self>> {
+      if !path.exists() {
+         return Ok(None);
+      }
+
+      let data = std::fs::read_to_string(path)
+         .with_context(|| format!("Failed to read LSP config {}", path.display()))?;
+      let config = serde_json::from_str(&data)
+         .with_context(|| format!("Failed to parse LSP config {}", path.display()))?;
+      Ok(Some(config))
+   }
+}
+
+#[derive(Debug, Clone)]
 pub struct LspRegistry {
    servers: Vec<LspServerConfig>,
+   languages: HashMap<String, String>,
 }
 
 impl LspRegistry {
    pub fn new() -> Self {
       let mut registry = Self {
          servers: Vec::new(),
+         languages: HashMap::new(),
       };
 
-      // Register TypeScript server
-      registry.register_typescript();
+      registry.register_builtin_servers();
 
       registry
    }
 
-   fn register_typescript(&mut self) {
-      self.servers.push(LspServerConfig {
-         name: "typescript".to_string(),
-         language_id: "typescript".to_string(),
-         command: PathBuf::from("typescript-language-server"),
-         args: vec!["--stdio".to_string()],
-         file_extensions: vec![
-            "ts".to_string(),
-            "tsx".to_string(),
-            "js".to_string(),
-            "jsx".to_string(),
-         ],
-      });
-   }
+   /// Built-in registry with the user config and, when given, the workspace config layered on
+   /// top. Broken config files are logged and skipped so a typo doesn't disable every server.
+   pub fn load(user_config: Option<&Path>, workspace: Option<&Path>) -> Self {
+      let mut registry = Self::new();
 
-   pub fn find_server_for_workspace(&self, workspace: &Path) -> Option<&LspServerConfig> {
-      // Always try TypeScript server for JS/TS projects - it handles both
-      if self.is_javascript_or_typescript_project(workspace) {
-         self.servers.iter().find(|s| s.name == "typescript")
-      } else {
-         // For now, default to TypeScript if no other server is found
-         // This ensures LSP functionality for most common file types
-         self.servers.iter().find(|s| s.name == "typescript")
+      let workspace_config = workspace.map(|w| w.join(WORKSPACE_CONFIG_PATH));
+      for path in [user_config.map(Path::to_path_buf), workspace_config]
+         .into_iter()
+         .flatten()
+      {
+         match LspConfigFile::load(&path) {
+            Ok(Some(config)) => {
+               log::info!("Applying LSP config from {:?}", path);
+               registry.apply_config(config);
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("{:#}", e),
+         }
       }
+
+      registry
    }
 
-   fn is_javascript_or_typescript_project(&self, workspace: &Path) -> bool {
-      // Check for TypeScript/JavaScript project indicators
-      let config_indicators = ["tsconfig.json", "package.json", "jsconfig.json"];
+   fn register_builtin_servers(&mut self) {
+      self.servers = vec![
+         LspServerConfig::builtin(
+            "typescript",
+            "typescript-language-server",
+            &["--stdio"],
+            &[
+               "typescript",
+               "typescriptreact",
+               "javascript",
+               "javascriptreact",
+            ],
+            &["tsconfig.json", "jsconfig.json", "package.json"],
+            "bun add -g typescript-language-server typescript",
+         ),
+         LspServerConfig::builtin(
+            "rust-analyzer",
+            "rust-analyzer",
+            &[],
+            &["rust"],
+            &["Cargo.toml", "rust-project.json"],
+            "rustup component add rust-analyzer",
+         ),
+         LspServerConfig::builtin(
+            "gopls",
+            "gopls",
+            &[],
+            &["go"],
+            &["go.work", "go.mod"],
+            "go install golang.org/x/tools/gopls@latest",
+         ),
+         LspServerConfig::builtin(
+            "pyright",
+            "pyright-langserver",
+            &["--stdio"],
+            &["python"],
+            &[
+               "pyrightconfig.json",
+               "pyproject.toml",
+               "setup.py",
+               "setup.cfg",
+               "requirements.txt",
+            ],
+            "bun add -g pyright",
+         ),
+         LspServerConfig {
+            // Drop-in alternative to pyright, enable it and disable pyright to switch
+            enabled: false,
+            ..LspServerConfig::builtin(
+               "basedpyright",
+               "basedpyright-langserver",
+               &["--stdio"],
+               &["python"],
+               &[
+                  "pyrightconfig.json",
+                  "pyproject.toml",
+                  "setup.py",
+                  "setup.cfg",
+                  "requirements.txt",
+               ],
+               "pip install basedpyright",
+            )
+         },
+         LspServerConfig::builtin(
+            "clangd",
+            "clangd",
+            &[],
+            &["c", "cpp", "objective-c", "objective-cpp"],
+            &[
+               "compile_commands.json",
+               "compile_flags.txt",
+               ".clangd",
+               "CMakeLists.txt",
+            ],
+            "install clangd from your system package manager or LLVM",
+         ),
+         LspServerConfig::builtin(
+            "ruby-lsp",
+            "ruby-lsp",
+            &[],
+            &["ruby"],
+            &["Gemfile", ".ruby-version"],
+            "gem install ruby-lsp",
+         ),
+         LspServerConfig::builtin(
+            "intelephense",
+            "intelephense",
+            &["--stdio"],
+            &["php"],
+            &["composer.json"],
+            "bun add -g intelephense",
+         ),
+         LspServerConfig::builtin(
+            "yaml",
+            "yaml-language-server",
+            &["--stdio"],
+            &["yaml"],
+            &[],
+            "bun add -g yaml-language-server",
+         ),
+         LspServerConfig::builtin(
+            "json",
+            "vscode-json-language-server",
+            &["--stdio"],
+            &["json", "jsonc"],
+            &[],
+            "bun add -g vscode-langservers-extracted",
+         ),
+         LspServerConfig::builtin(
+            "css",
+            "vscode-css-language-server",
+            &["--stdio"],
+            &["css", "scss", "less"],
+            &[],
+            "bun add -g vscode-langservers-extracted",
+         ),
+         LspServerConfig::builtin(
+            "html",
+            "vscode-html-language-server",
+            &["--stdio"],
+            &["html"],
+            &[],
+            "bun add -g vscode-langservers-extracted",
+         ),
+         LspServerConfig::builtin(
+            "taplo",
+            "taplo",
+            &["lsp", "stdio"],
+            &["toml"],
+            &[],
+            "cargo install taplo-cli --locked --features lsp",
+         ),
+      ];
+   }
 
-      let file_extensions = [".ts", ".tsx", ".js", ".jsx", ".mjs", ".cjs"];
+   pub fn apply_config(&mut self, config: LspConfigFile) {
+      let mut names: Vec<String> = config.servers.keys().cloned().collect();
+      names.sort();
 
-      // Check in workspace root for config files
-      for indicator in &config_indicators {
-         if workspace.join(indicator).exists() {
-            return true;
+      let mut servers = config.servers;
+      for name in names {
+         let overrides = servers.remove(&name).unwrap_or_default();
+         if let Some(server) = self.servers.iter_mut().find(|s| s.name == name) {
+            server.apply(overrides);
+            continue;
          }
-      }
 
-      // Check for source files in common directories and root
-      let source_dirs = [
-         "src",
-         "lib",
-         "app",
-         "pages",
-         "components",
-         "javascript",
-         "js",
-         ".",
-      ];
-      for dir in &source_dirs {
-         let dir_path = if *dir == "." {
-            workspace.to_path_buf()
-         } else {
-            workspace.join(dir)
+         let Some(command) = overrides.command.clone() else {
+            log::warn!("Ignoring LSP server '{}' without a command", name);
+            continue;
          };
-         if dir_path.exists() && dir_path.is_dir() {
-            // Walk through the directory looking for TS/JS files
-            if let Ok(entries) = std::fs::read_dir(&dir_path) {
-               for entry in entries.flatten() {
-                  if let Some(ext) = entry.path().extension() {
-                     let ext_str = format!(".{}", ext.to_str().unwrap_or(""));
-                     if file_extensions.contains(&ext_str.as_str()) {
-                        return true;
-                     }
-                  }
-               }
-            }
-         }
+
+         let mut server = LspServerConfig {
+            name: name.clone(),
+            command,
+            args: Vec::new(),
+            language_ids: Vec::new(),
+            root_markers: Vec::new(),
+            initialization_options: None,
+            settings: None,
+            enabled: true,
+            install_hint: None,
+         };
+         server.apply(overrides);
+         self.servers.push(server);
+      }
+
+      for (key, language_id) in config.languages {
+         self
+            .languages
+            .insert(key.trim_start_matches('.').to_string(), language_id);
+      }
+   }
+
+   pub fn servers(&self) -> impl Iterator<Item = &LspServerConfig> {
+      self.servers.iter().filter(|s| s.enabled)
+   }
+
+   /// Enabled servers that handle the language of `path`, in registration order.
+   pub fn servers_for_file(&self, path: &Path) -> Vec<&LspServerConfig> {
+      match self.language_id_for_path(path) {
+         Some(language_id) => self
+            .servers()
+            .filter(|s| s.handles_language(&language_id))
+            .collect(),
+         None => Vec::new(),
       }
+   }
+
+   pub fn is_file_supported(&self, path: &Path) -> bool {
+      !self.servers_for_file(path).is_empty()
+   }
+
+   /// Language id for a file, from the configured mappings first and then the built-in table.
+   pub fn language_id_for_path(&self, path: &Path) -> Option<String> {
+      let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+      let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+      if let Some(language_id) = self
+         .languages
+         .get(file_name)
+         .or_else(|| self.languages.get(extension))
+      {
+         return Some(language_id.clone());
+      }
+
+      builtin_language_id(file_name, extension).map(str::to_string)
+   }
+}
+
+fn builtin_language_id(file_name: &str, extension: &str) -> Option<&'static str> {
+   match file_name {
+      "Gemfile" | "Rakefile" | "Guardfile" => return Some("ruby"),
+      "tsconfig.json" | "jsconfig.json" | ".eslintrc.json" => return Some("jsonc"),
+      _ => {}
+   }
+
+   let language_id = match extension {
+      "ts" | "mts" | "cts" => "typescript",
+      "tsx" => "typescriptreact",
+      "js" | "mjs" | "cjs" => "javascript",
+      "jsx" => "javascriptreact",
+      "json" => "json",
+      "jsonc" => "jsonc",
+      "rs" => "rust",
+      "go" => "go",
+      "py" | "pyi" => "python",
+      "c" | "h" => "c",
+      "cc" | "cpp" | "cxx" | "c++" | "hh" | "hpp" | "hxx" | "h++" => "cpp",
+      "m" => "objective-c",
+      "mm" => "objective-cpp",
+      "rb" | "rake" | "gemspec" | "ru" => "ruby",
+      "php" => "php",
+      "yaml" | "yml" => "yaml",
+      "css" => "css",
+      "scss" => "scss",
+      "less" => "less",
+      "html" | "htm" => "html",
+      "toml" => "toml",
+      _ => return None,
+   };
+
+   Some(language_id)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_config_overrides_and_adds_servers() {
+      let mut registry = LspRegistry::new();
+      let config: LspConfigFile = serde_json::from_value(serde_json::json!({
+         "servers": {
+            "rust-analyzer": {
+               "initializationOptions": { "check": { "command": "clippy" } },
+               "settings": { "rust-analyzer": { "check": { "command": "clippy" } } }
+            },
+            "pyright": { "enabled": false },
+            "zls": { "command": "zls", "languageIds": ["zig"], "rootMarkers": ["build.zig"] }
+         },
+         "languages": { ".zig": "zig" }
+      }))
+      .unwrap();
+      registry.apply_config(config);
+
+      let rust = registry.servers_for_file(Path::new("/p/src/main.rs"));
+      assert_eq!(rust.len(), 1);
+      assert_eq!(rust[0].command, PathBuf::from("rust-analyzer"));
+      assert!(rust[0].initialization_options.is_some());
+      assert_eq!(
+         rust[0].settings_section(Some("rust-analyzer.check.command")),
+         Value::from("clippy")
+      );
+      assert_eq!(
+         rust[0].settings_section(Some("rust-analyzer.cargo")),
+         Value::Null
+      );
+
+      assert!(!registry.is_file_supported(Path::new("/p/main.py")));
+
+      let zig = registry.servers_for_file(Path::new("/p/build.zig"));
+      assert_eq!(zig.len(), 1);
+      assert_eq!(zig[0].name, "zls");
+
+      assert_eq!(
+         registry
+            .language_id_for_path(Path::new("/p/Gemfile"))
+            .as_deref(),
+         Some("ruby")
+      );
+   }
+
+   #[test]
+   fn test_settings_section_answers_configuration_requests() {
+      let mut server = LspServerConfig::builtin("zls", "zls", &[], &["zig"], &[], "");
+      assert_eq!(server.settings_section(None), Value::Null);
+      assert_eq!(server.settings_section(Some("zls")), Value::Null);
+
+      let settings = serde_json::json!({
+         "zls": { "enable_snippets": true, "semantic_tokens": "full" },
+         "files": { "exclude": ["zig-cache"] }
+      });
+      server.settings = Some(settings.clone());
+
+      assert_eq!(server.settings_section(None), settings);
+      assert_eq!(server.settings_section(Some("")), settings);
+      assert_eq!(server.settings_section(Some("zls")), settings["zls"]);
+      assert_eq!(
+         server.settings_section(Some("zls.semantic_tokens")),
+         Value::from("full")
+      );
+      assert_eq!(
+         server.settings_section(Some("files.exclude")),
+         serde_json::json!(["zig-cache"])
+      );
+      assert_eq!(server.settings_section(Some("zls.missing")), Value::Null);
+      assert_eq!(
+         server.settings_section(Some("zls.enable_snippets.nested")),
+         Value::Null
+      );
+   }
+
+   #[test]
+   fn test_find_root_uses_nearest_marker() {
+      let workspace = tempfile::tempdir().unwrap();
+      let crate_dir = workspace.path().join("crates/core");
+      std::fs::create_dir_all(crate_dir.join("src")).unwrap();
+      std::fs::write(crate_dir.join("Cargo.toml"), "").unwrap();
+      std::fs::write(workspace.path().join("package.json"), "{}").unwrap();
+
+      let registry = LspRegistry::new();
+      let file = crate_dir.join("src/lib.rs");
+      let rust = registry.servers_for_file(&file)[0];
+      assert_eq!(rust.find_root(&file, Some(workspace.path())), crate_dir);
+
+      let script = workspace.path().join("scripts/build.ts");
+      let typescript = registry.servers_for_file(&script)[0];
+      assert_eq!(
+         typescript.find_root(&script, Some(workspace.path())),
+         workspace.path()
+      );
 
-      // If we found any JS/TS files anywhere, consider it a JS/TS project
-      true // Default to yes for broader compatibility
+      let orphan = workspace.path().join("notes/main.go");
+      let gopls = registry.servers_for_file(&orphan)[0];
+      assert_eq!(
+         gopls.find_root(&orphan, Some(workspace.path())),
+         workspace.path()
+      );
    }
 }
@@ -0,0 +1,246 @@
+use super::types::DiagnosticItem;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url};
+use std::{
+   collections::HashMap,
+   path::{Path, PathBuf},
+   sync::{Arc, Mutex},
+};
+
+type ServerDiagnostics = HashMap<String, Vec<Diagnostic>>;
+
+/// Diagnostics published by language servers, keyed by file and then by the server that
+/// reported them so one server clearing its results doesn't wipe another's.
+#[derive(Clone, Default)]
+pub struct DiagnosticsStore {
+   entries: Arc<Mutex<HashMap<PathBuf, ServerDiagnostics>>>,
+}
+
+impl DiagnosticsStore {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   /// Replaces the diagnostics `server` reported for `uri` and returns the merged list for the
+   /// file, ready to be sent to the frontend.
+   pub fn update(
+      &self,
+      server: &str,
+      uri: &Url,
+      diagnostics: Vec<Diagnostic>,
+   ) -> Option<(PathBuf, Vec<DiagnosticItem>)> {
+      let path = uri.to_file_path().ok()?;
+      let mut entries = self.entries.lock().unwrap();
+
+      let per_server = entries.entry(path.clone()).or_default();
+      if diagnostics.is_empty() {
+         per_server.remove(server);
+      } else {
+         per_server.insert(server.to_string(), diagnostics);
+      }
+
+      let items = Self::items_for_file(&path, per_server);
+      if per_server.is_empty() {
+         entries.remove(&path);
+      }
+
+      Some((path, items))
+   }
+
+   /// Drops everything `server` reported for files under `root`, returning the files whose
+   /// diagnostics changed.
+   pub fn clear_server(&self, server: &str, root: &Path) -> Vec<PathBuf> {
+      let mut entries = self.entries.lock().unwrap();
+      let mut cleared = Vec::new();
+
+      entries.retain(|path, per_server| {
+         if path.starts_with(root) && per_server.remove(server).is_some() {
+            cleared.push(path.clone());
+         }
+         !per_server.is_empty()
+      });
+
+      cleared
+   }
+
+   /// Raw diagnostics `server` published for `path` that overlap `range`, as code action
+   /// requests expect them back.
+   pub fn in_range(&self, server: &str, path: &Path, range: Range) -> Vec<Diagnostic> {
+      let entries = self.entries.lock().unwrap();
+      entries
+         .get(path)
+         .and_then(|per_server| per_server.get(server))
+         .map(|diagnostics| {
+            diagnostics
+               .iter()
+               .filter(|d| d.range.start <= range.end && d.range.end >= range.start)
+               .cloned()
+               .collect()
+         })
+         .unwrap_or_default()
+   }
+
+   /// Returns diagnostics for files under `path` (a file or a directory), optionally restricted
+   /// to a single severity.
+   pub fn query(
+      &self,
+      path: Option<&Path>,
+      severity: Option<DiagnosticSeverity>,
+   ) -> Vec<DiagnosticItem> {
+      let entries = self.entries.lock().unwrap();
+
+      let mut files: Vec<&PathBuf> = entries
+         .keys()
+         .filter(|file| path.is_none_or(|p| file.starts_with(p)))
+         .collect();
+      files.sort();
+
+      files
+         .into_iter()
+         .flat_map(|file| Self::items_for_file(file, &entries[file]))
+         .filter(|item| severity.is_none_or(|s| item.severity == severity_name(Some(s))))
+         .collect()
+   }
+
+   fn items_for_file(path: &Path, per_server: &ServerDiagnostics) -> Vec<DiagnosticItem> {
+      let file = path.to_string_lossy().to_string();
+      let mut items: Vec<DiagnosticItem> = per_server
+         .values()
+         .flatten()
+         .map(|diagnostic| to_item(&file, diagnostic))
+         .collect();
+      items.sort_by_key(|item| (item.line, item.column));
+      items
+   }
+}
+
+fn to_item(file: &str, diagnostic: &Diagnostic) -> DiagnosticItem {
+   DiagnosticItem {
+      file: file.to_string(),
+      line: diagnostic.range.start.line + 1,
+      column: diagnostic.range.start.character + 1,
+      end_line: diagnostic.range.end.line + 1,
+      end_column: diagnostic.range.end.character + 1,
+      severity: severity_name(diagnostic.severity).to_string(),
+      message: diagnostic.message.clone(),
+      source: diagnostic.source.clone(),
+      code: diagnostic.code.as_ref().map(|code| match code {
+         NumberOrString::Number(n) => n.to_string(),
+         NumberOrString::String(s) => s.clone(),
+      }),
+   }
+}
+
+/// Servers may omit the severity, in which case the client decides; we treat it as an error
+/// like VS Code does.
+pub fn severity_name(severity: Option<DiagnosticSeverity>) -> &'static str {
+   match severity {
+      Some(DiagnosticSeverity::WARNING) => "warning",
+      Some(DiagnosticSeverity::INFORMATION) => "info",
+      Some(DiagnosticSeverity::HINT) => "hint",
+      _ => "error",
+   }
+}
+
+pub fn parse_severity(name: &str) -> Option<DiagnosticSeverity> {
+   match name.to_ascii_lowercase().as_str() {
+      "error" | "errors" => Some(DiagnosticSeverity::ERROR),
+      "warning" | "warnings" | "warn" => Some(DiagnosticSeverity::WARNING),
+      "information" | "info" => Some(DiagnosticSeverity::INFORMATION),
+      "hint" | "hints" => Some(DiagnosticSeverity::HINT),
+      _ => None,
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use lsp_types::{Position, Range};
+
+   fn diagnostic(line: u32, severity: DiagnosticSeverity, message: &str) -> Diagnostic {
+      Diagnostic {
+         range: Range::new(Position::new(line, 4), Position::new(line, 9)),
+         severity: Some(severity),
+         message: message.to_string(),
+         ..Default::default()
+      }
+   }
+
+   #[test]
+   fn test_merges_servers_and_filters() {
+      let store = DiagnosticsStore::new();
+      let uri = Url::parse("file:///project/src/main.rs").unwrap();
+
+      store.update(
+         "rust-analyzer",
+         &uri,
+         vec![diagnostic(3, DiagnosticSeverity::ERROR, "mismatched types")],
+      );
+      let (_, items) = store
+         .update(
+            "typos",
+            &uri,
+            vec![diagnostic(1, DiagnosticSeverity::WARNING, "typo")],
+         )
+         .unwrap();
+
+      assert_eq!(items.len(), 2);
+      assert_eq!(items[0].line, 2);
+      assert_eq!(items[0].column, 5);
+
+      let errors = store.query(Some(Path::new("/project")), Some(DiagnosticSeverity::ERROR));
+      assert_eq!(errors.len(), 1);
+      assert_eq!(errors[0].message, "mismatched types");
+      assert!(store.query(Some(Path::new("/other")), None).is_empty());
+
+      store.update("typos", &uri, Vec::new());
+      assert_eq!(
+         store
+            .query(Some(Path::new("/project/src/main.rs")), None)
+            .len(),
+         1
+      );
+
+      let cleared = store.clear_server("rust-analyzer", Path::new("/project"));
+      assert_eq!(cleared.len(), 1);
+      assert!(store.query(None, None).is_empty());
+   }
+
+   #[test]
+   fn test_in_range_returns_the_servers_overlapping_diagnostics() {
+      let store = DiagnosticsStore::new();
+      let uri = Url::parse("file:///project/src/main.rs").unwrap();
+      let path = Path::new("/project/src/main.rs");
+      store.update(
+         "rust-analyzer",
+         &uri,
+         vec![
+            diagnostic(1, DiagnosticSeverity::ERROR, "before"),
+            diagnostic(3, DiagnosticSeverity::ERROR, "inside"),
+            diagnostic(5, DiagnosticSeverity::WARNING, "touching"),
+            diagnostic(8, DiagnosticSeverity::ERROR, "after"),
+         ],
+      );
+      store.update(
+         "typos",
+         &uri,
+         vec![diagnostic(3, DiagnosticSeverity::WARNING, "typo")],
+      );
+
+      // Ranges sharing only an end point still overlap, as a cursor at the end of a word does
+      let range = Range::new(Position::new(2, 0), Position::new(5, 4));
+      let messages: Vec<String> = store
+         .in_range("rust-analyzer", path, range)
+         .into_iter()
+         .map(|d| d.message)
+         .collect();
+      assert_eq!(messages, vec!["inside", "touching"]);
+
+      assert_eq!(store.in_range("typos", path, range).len(), 1);
+      assert!(store.in_range("eslint", path, range).is_empty());
+      assert!(
+         store
+            .in_range("rust-analyzer", Path::new("/project/src/lib.rs"), range)
+            .is_empty()
+      );
+   }
+}
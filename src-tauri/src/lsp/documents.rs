@@ -0,0 +1,181 @@
+use anyhow::{Result, bail};
+use lsp_types::{Position, TextDocumentContentChangeEvent, TextDocumentItem, TextEdit, Url};
+
+/// A document the editor has open, mirrored so servers started later can be sent `didOpen`.
+#[derive(Debug, Clone)]
+pub struct OpenDocument {
+   pub uri: Url,
+   pub language_id: String,
+   pub version: i32,
+   pub text: String,
+}
+
+impl OpenDocument {
+   pub fn to_item(&self) -> TextDocumentItem {
+      TextDocumentItem {
+         uri: self.uri.clone(),
+         language_id: self.language_id.clone(),
+         version: self.version,
+         text: self.text.clone(),
+      }
+   }
+}
+
+/// Byte offset of an LSP position, whose `character` counts UTF-16 code units. Positions past the
+/// end of a line clamp to the line end, as the spec requires.
+pub fn position_to_offset(text: &str, position: Position) -> usize {
+   let mut line_start = 0;
+   for _ in 0..position.line {
+      match text[line_start..].find('\n') {
+         Some(i) => line_start += i + 1,
+         None => return text.len(),
+      }
+   }
+
+   let mut line_end = text[line_start..]
+      .find('\n')
+      .map_or(text.len(), |i| line_start + i);
+   if text[line_start..line_end].ends_with('\r') {
+      line_end -= 1;
+   }
+
+   let mut units = 0;
+   for (i, ch) in text[line_start..line_end].char_indices() {
+      if units >= position.character {
+         return line_start + i;
+      }
+      units += ch.len_utf16() as u32;
+   }
+
+   line_end
+}
+
+/// Applies a batch of edits whose ranges all refer to the original `text`. Edits starting at the
+/// same position are inserted in the order given.
+pub fn apply_text_edits(text: &str, edits: &[TextEdit]) -> Result<String> {
+   let mut resolved: Vec<(usize, usize, &str)> = edits
+      .iter()
+      .map(|edit| {
+         (
+            position_to_offset(text, edit.range.start),
+            position_to_offset(text, edit.range.end),
+            edit.new_text.as_str(),
+         )
+      })
+      .collect();
+   // Stable sort keeps same-position inserts in their original order
+   resolved.sort_by_key(|(start, _, _)| *start);
+
+   for pair in resolved.windows(2) {
+      if pair[0].1 > pair[1].0 {
+         bail!("Overlapping text edits");
+      }
+   }
+
+   let mut result = text.to_string();
+   for (start, end, new_text) in resolved.into_iter().rev() {
+      if start > end {
+         bail!("Invalid text edit range");
+      }
+      result.replace_range(start..end, new_text);
+   }
+
+   Ok(result)
+}
+
+/// Applies `didChange` content changes in order, each one against the result of the previous,
+/// as the protocol specifies. A change without a range replaces the whole text.
+pub fn apply_content_changes(
+   text: &str,
+   changes: &[TextDocumentContentChangeEvent],
+) -> Result<String> {
+   let mut result = text.to_string();
+   for change in changes {
+      let Some(range) = change.range else {
+         result = change.text.clone();
+         continue;
+      };
+
+      let start = position_to_offset(&result, range.start);
+      let end = position_to_offset(&result, range.end);
+      if start > end {
+         bail!("Invalid content change range");
+      }
+      result.replace_range(start..end, &change.text);
+   }
+   Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use lsp_types::Range;
+
+   fn edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> TextEdit {
+      TextEdit {
+         range: Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+         new_text: new_text.to_string(),
+      }
+   }
+
+   #[test]
+   fn test_position_to_offset_counts_utf16() {
+      let text = "let s = \"😀\";\r\nlet x = 1;\n";
+      // The emoji is two UTF-16 code units but four bytes
+      assert_eq!(position_to_offset(text, Position::new(0, 11)), 13);
+      assert_eq!(position_to_offset(text, Position::new(0, 100)), 15);
+      assert_eq!(position_to_offset(text, Position::new(1, 4)), 21);
+      assert_eq!(position_to_offset(text, Position::new(5, 0)), text.len());
+   }
+
+   #[test]
+   fn test_apply_text_edits() {
+      let text = "fn old() {}\nfn main() { old(); }\n";
+      let edits = vec![
+         edit((1, 12), (1, 15), "new"),
+         edit((0, 3), (0, 6), "new"),
+         edit((0, 0), (0, 0), "// a\n"),
+         edit((0, 0), (0, 0), "// b\n"),
+      ];
+      assert_eq!(
+         apply_text_edits(text, &edits).unwrap(),
+         "// a\n// b\nfn new() {}\nfn main() { new(); }\n"
+      );
+
+      let overlapping = vec![edit((0, 0), (0, 5), ""), edit((0, 3), (0, 6), "")];
+      assert!(apply_text_edits(text, &overlapping).is_err());
+   }
+
+   #[test]
+   fn test_apply_content_changes_in_sequence() {
+      let change = |range: Option<Range>, text: &str| TextDocumentContentChangeEvent {
+         range,
+         range_length: None,
+         text: text.to_string(),
+      };
+      let text = "😀 = 1;\nb = 2;\n";
+      let changes = vec![
+         // After the emoji, which is two UTF-16 units
+         change(
+            Some(Range::new(Position::new(0, 2), Position::new(0, 2))),
+            "a",
+         ),
+         // Refers to the text produced by the first change
+         change(
+            Some(Range::new(Position::new(0, 0), Position::new(0, 3))),
+            "x",
+         ),
+         change(
+            Some(Range::new(Position::new(1, 4), Position::new(1, 5))),
+            "3",
+         ),
+      ];
+      assert_eq!(
+         apply_content_changes(text, &changes).unwrap(),
+         "x = 1;\nb = 3;\n"
+      );
+
+      let full = vec![change(None, "new")];
+      assert_eq!(apply_content_changes(text, &full).unwrap(), "new");
+   }
+}
@@ -0,0 +1,146 @@
+use super::types::LocationItem;
+use lsp_types::{GotoDefinitionResponse, Location, Range};
+use std::{
+   collections::{HashMap, HashSet},
+   path::PathBuf,
+};
+
+/// Flattens the three shapes a goto response can take into plain locations. Links point at
+/// their `targetSelectionRange`, the identifier itself rather than the whole declaration.
+pub fn flatten_goto_response(response: GotoDefinitionResponse) -> Vec<Location> {
+   match response {
+      GotoDefinitionResponse::Scalar(location) => vec![location],
+      GotoDefinitionResponse::Array(locations) => locations,
+      GotoDefinitionResponse::Link(links) => links
+         .into_iter()
+         .map(|link| Location {
+            uri: link.target_uri,
+            range: link.target_selection_range,
+         })
+         .collect(),
+   }
+}
+
+/// Converts locations to items with a preview of the line each one starts on. `read_text`
+/// supplies file contents and is called once per file; locations outside the local file system
+/// are dropped.
+pub fn to_location_items(
+   locations: Vec<Location>,
+   mut read_text: impl FnMut(&PathBuf) -> Option<String>,
+) -> Vec<LocationItem> {
+   let mut texts: HashMap<PathBuf, Option<String>> = HashMap::new();
+   let mut items: Vec<LocationItem> = Vec::new();
+   let mut seen: HashSet<(PathBuf, Range)> = HashSet::new();
+
+   for location in locations {
+      let Ok(path) = location.uri.to_file_path() else {
+         continue;
+      };
+
+      if !seen.insert((path.clone(), location.range)) {
+         continue;
+      }
+
+      let text = texts
+         .entry(path.clone())
+         .or_insert_with(|| read_text(&path));
+      let preview = text
+         .as_deref()
+         .and_then(|text| preview_line(text, &location.range));
+
+      items.push(LocationItem {
+         path: path.to_string_lossy().to_string(),
+         range: location.range,
+         preview,
+      });
+   }
+
+   items
+}
+
+fn preview_line(text: &str, range: &Range) -> Option<String> {
+   text
+      .lines()
+      .nth(range.start.line as usize)
+      .map(|line| line.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use lsp_types::{LocationLink, Position, Url};
+
+   fn location(path: &str, line: u32) -> Location {
+      Location {
+         uri: Url::from_file_path(path).unwrap(),
+         range: Range::new(Position::new(line, 3), Position::new(line, 6)),
+      }
+   }
+
+   #[test]
+   fn test_flattens_goto_responses() {
+      assert_eq!(
+         flatten_goto_response(GotoDefinitionResponse::Scalar(location("/src/a.rs", 0))),
+         vec![location("/src/a.rs", 0)]
+      );
+      assert_eq!(
+         flatten_goto_response(GotoDefinitionResponse::Array(vec![
+            location("/src/a.rs", 0),
+            location("/src/b.rs", 1),
+         ]))
+         .len(),
+         2
+      );
+
+      // Links point at the name, not the whole declaration
+      let link = LocationLink {
+         origin_selection_range: None,
+         target_uri: Url::from_file_path("/src/a.rs").unwrap(),
+         target_range: Range::new(Position::new(0, 0), Position::new(2, 1)),
+         target_selection_range: Range::new(Position::new(0, 3), Position::new(0, 6)),
+      };
+      assert_eq!(
+         flatten_goto_response(GotoDefinitionResponse::Link(vec![link])),
+         vec![location("/src/a.rs", 0)]
+      );
+   }
+
+   #[test]
+   fn test_location_items_are_deduplicated_with_previews() {
+      let locations = vec![
+         location("/src/a.rs", 1),
+         location("/src/b.rs", 0),
+         location("/src/a.rs", 1),
+         location("/src/a.rs", 0),
+         Location {
+            uri: Url::parse("https://example.com/a.rs").unwrap(),
+            range: Range::default(),
+         },
+      ];
+      let mut reads = 0;
+      let items = to_location_items(locations, |path| {
+         reads += 1;
+         (path == &PathBuf::from("/src/a.rs")).then(|| "fn a() {}\nlet x = a();   \n".to_string())
+      });
+
+      let found: Vec<(&str, u32, Option<&str>)> = items
+         .iter()
+         .map(|item| {
+            (
+               item.path.as_str(),
+               item.range.start.line,
+               item.preview.as_deref(),
+            )
+         })
+         .collect();
+      assert_eq!(
+         found,
+         vec![
+            ("/src/a.rs", 1, Some("let x = a();")),
+            ("/src/b.rs", 0, None),
+            ("/src/a.rs", 0, Some("fn a() {}")),
+         ]
+      );
+      assert_eq!(reads, 2);
+   }
+}
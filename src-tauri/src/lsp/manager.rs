@@ -1,63 +1,182 @@
 use super::{
-   client::LspClient,
-   config::{LspRegistry, LspSettings},
+   client::{self, CancellationToken, LspClient, Responder},
+   config::{LspRegistry, LspServerConfig, LspSettings, USER_CONFIG_FILE},
+   diagnostics::DiagnosticsStore,
+   documents::{self, OpenDocument},
+   locations, snippet,
+   trace::MessageTrace,
+   types::{
+      CodeActionItem, CodeActionOutcome, CompletionEntry, CompletionListResult, DiagnosticItem,
+      DiagnosticsChangedEvent, GotoKind, LocationItem, PrepareRenameItem, ServerCapabilitiesItem,
+      ServerKey, ServerStatus, ServerStatusEvent,
+   },
    utils,
+   workspace_edit::{
+      self, EditOperation, FileEditKind, FileEditOutcome, WorkspaceEditPreview, WorkspaceEditResult,
+   },
 };
 use anyhow::{Context, Result, bail};
+use futures_util::future::join_all;
 use lsp_types::*;
+use serde_json::Value;
 use std::{
-   collections::HashMap,
-   path::PathBuf,
+   collections::{HashMap, HashSet},
+   path::{Path, PathBuf},
    process::Child,
-   sync::{Arc, Mutex},
+   sync::{
+      Arc, Mutex,
+      atomic::{AtomicU64, Ordering},
+   },
    time::Instant,
 };
-use tauri::{AppHandle, Manager as TauriManager};
+use tauri::{AppHandle, Emitter, Manager as TauriManager};
 
-type WorkspaceClients = Arc<Mutex<HashMap<PathBuf, (LspClient, Child, String)>>>;
+mod dispatch;
+mod hierarchy;
+mod hints;
+mod inspector;
+mod pull_diagnostics;
+mod ranges;
+mod semantic;
+mod supervisor;
+mod symbols;
+
+struct ServerInstance {
+   // Tells a crashed instance apart from the one that replaced it under the same key
+   id: u64,
+   client: LspClient,
+   child: Child,
+   config: LspServerConfig,
+   started_at: Instant,
+}
+
+type ServerInstances = Arc<Mutex<HashMap<ServerKey, ServerInstance>>>;
+type OpenDocuments = Arc<Mutex<HashMap<PathBuf, OpenDocument>>>;
 
 pub struct LspManager {
-   // Map workspace paths to their LSP clients
-   workspace_clients: WorkspaceClients,
-   registry: LspRegistry,
+   // Running servers, one per (project root, server) pair
+   servers: ServerInstances,
+   // Workspace folders registered by the frontend through `lsp_start`
+   workspaces: Mutex<Vec<PathBuf>>,
+   documents: OpenDocuments,
+   // Servers that failed to start, so they aren't respawned on every file open
+   failed_servers: Mutex<HashSet<ServerKey>>,
+   // Serializes startup of each server so concurrent opens don't spawn it twice. Per server, so
+   // a slow start doesn't hold up opening files other servers handle
+   start_locks: Mutex<HashMap<ServerKey, Arc<tokio::sync::Mutex<()>>>>,
    app_handle: AppHandle,
    settings: LspSettings,
+   diagnostics: DiagnosticsStore,
+   // `window/showMessageRequest`s waiting on the user, by the id sent to the frontend
+   message_requests: Mutex<HashMap<u64, (ServerKey, Responder)>>,
+   next_message_request: AtomicU64,
+   next_instance_id: AtomicU64,
+   // Consecutive crashes per server, reset once it stays up for a while
+   crash_counts: Mutex<HashMap<ServerKey, u32>>,
+   // Tokens of in-flight requests the frontend may cancel, by its own request id
+   cancellations: Mutex<HashMap<String, CancellationToken>>,
+   // The completion request in flight, superseded by the next one
+   latest_completion: Mutex<Option<CancellationToken>>,
+   // Signature help shown last and the file it's for, sent back to the server when it's
+   // retriggered in that file
+   last_signature_help: Mutex<Option<(String, SignatureHelp)>>,
+   // Last semantic tokens per server and file, the base for delta requests
+   semantic_cache: Mutex<HashMap<(ServerKey, PathBuf), semantic::CachedSemanticTokens>>,
+   // Result ids of pulled diagnostics, sent back so servers can answer "unchanged"
+   diagnostic_result_ids: Mutex<pull_diagnostics::DiagnosticResultIds>,
+   // JSON-RPC traffic per server, kept across restarts
+   traces: Mutex<HashMap<ServerKey, Arc<MessageTrace>>>,
+}
+
+/// A cancellable frontend request. Its token stays registered until the guard is dropped.
+struct TrackedRequest<'a> {
+   manager: &'a LspManager,
+   request_id: Option<String>,
+   token: CancellationToken,
+}
+
+impl Drop for TrackedRequest<'_> {
+   fn drop(&mut self) {
+      if let Some(request_id) = &self.request_id {
+         self
+            .manager
+            .cancellations
+            .lock()
+            .unwrap()
+            .remove(request_id);
+      }
+   }
 }
 
 impl LspManager {
    pub fn new(app_handle: AppHandle) -> Self {
       Self {
-         workspace_clients: Arc::new(Mutex::new(HashMap::new())),
-         registry: LspRegistry::new(),
+         servers: Arc::new(Mutex::new(HashMap::new())),
+         workspaces: Mutex::new(Vec::new()),
+         documents: Arc::new(Mutex::new(HashMap::new())),
+         failed_servers: Mutex::new(HashSet::new()),
+         start_locks: Mutex::new(HashMap::new()),
          app_handle,
          settings: LspSettings::default(),
+         diagnostics: DiagnosticsStore::new(),
+         message_requests: Mutex::new(HashMap::new()),
+         next_message_request: AtomicU64::new(1),
+         next_instance_id: AtomicU64::new(1),
+         crash_counts: Mutex::new(HashMap::new()),
+         cancellations: Mutex::new(HashMap::new()),
+         latest_completion: Mutex::new(None),
+         last_signature_help: Mutex::new(None),
+         semantic_cache: Mutex::new(HashMap::new()),
+         diagnostic_result_ids: Mutex::new(HashMap::new()),
+         traces: Mutex::new(HashMap::new()),
       }
    }
 
-   pub fn get_server_path(&self, server_name: &str) -> Result<PathBuf> {
-      // For TypeScript, try multiple detection strategies
-      if server_name == "typescript" {
-         // First try: globally installed server via package managers
-         if let Some(path) = utils::find_global_binary("typescript-language-server") {
-            log::info!("Using global TypeScript server: {:?}", path);
-            return Ok(path);
-         }
+   /// Registry with the user config and the workspace's `.athas/lsp.json` applied. Config
+   /// files are re-read every time so edits apply to the next server started.
+   pub fn registry(&self, workspace: Option<&Path>) -> LspRegistry {
+      let user_config = self
+         .app_handle
+         .path()
+         .app_config_dir()
+         .ok()
+         .map(|dir| dir.join(USER_CONFIG_FILE));
+      LspRegistry::load(user_config.as_deref(), workspace)
+   }
+
+   pub fn get_server_path(&self, server: &LspServerConfig, workspace: &Path) -> Result<PathBuf> {
+      let command = &server.command;
 
-         // Second try: check if it's in PATH
-         if let Some(path) = utils::find_in_path("typescript-language-server") {
-            log::info!("Using TypeScript server from PATH: {:?}", path);
-            return Ok(path);
+      // Explicit paths from config are used as-is
+      if command.components().count() > 1 {
+         if command.exists() {
+            return Ok(command.clone());
          }
+         bail!(
+            "Language server '{}' not found at {:?}",
+            server.name,
+            command
+         );
+      }
 
-         // Third try: local node_modules in current working directory
-         let local_path = std::env::current_dir()
-            .context("Failed to get current directory")?
-            .join("node_modules/.bin/typescript-language-server");
+      let binary_name = command.to_string_lossy();
 
-         if local_path.exists() {
-            log::info!("Using local TypeScript server: {:?}", local_path);
-            return Ok(local_path);
-         }
+      // Project-local node_modules take precedence so the workspace's version is used
+      let local_path = workspace.join("node_modules/.bin").join(command);
+      if local_path.exists() {
+         log::info!("Using local {} server: {:?}", server.name, local_path);
+         return Ok(local_path);
+      }
+
+      if let Some(path) = utils::find_in_path(&binary_name) {
+         log::info!("Using {} server from PATH: {:?}", server.name, path);
+         return Ok(path);
+      }
+
+      // Globally installed via bun/npm but not on PATH
+      if let Some(path) = utils::find_global_binary(&binary_name) {
+         log::info!("Using global {} server: {:?}", server.name, path);
+         return Ok(path);
       }
 
       // Look for bundled executable
@@ -67,134 +186,955 @@ impl LspManager {
          .app_data_dir()
          .context("Failed to get app dir")?;
 
-      let bundled_path = app_dir.join(format!("{}-language-server", server_name));
+      let bundled_path = app_dir.join(command);
 
       if bundled_path.exists() {
          log::info!("Using bundled language server: {:?}", bundled_path);
          Ok(bundled_path)
-      } else {
+      } else if let Some(hint) = &server.install_hint {
          bail!(
-            "Language server '{}' not found. Please install it globally using: bun add -g \
-             typescript-language-server",
-            server_name
+            "Language server '{}' not found. Please install it using: {}",
+            server.name,
+            hint
          )
+      } else {
+         bail!("Language server '{}' not found", server.name)
       }
    }
 
-   pub async fn start_lsp_for_workspace(&self, workspace_path: PathBuf) -> Result<()> {
-      log::info!("Starting LSP for workspace: {:?}", workspace_path);
+   /// Registers a workspace folder. Servers are started lazily when the first document of
+   /// their language is opened.
+   pub fn start_lsp_for_workspace(&self, workspace_path: PathBuf) {
+      log::info!("Registering LSP workspace: {:?}", workspace_path);
+
+      let mut workspaces = self.workspaces.lock().unwrap();
+      if !workspaces.contains(&workspace_path) {
+         workspaces.push(workspace_path);
+      }
+   }
 
-      // Check if LSP already running for this workspace
-      if self
-         .workspace_clients
+   /// Innermost registered workspace containing `path`.
+   fn workspace_for_file(&self, path: &Path) -> Option<PathBuf> {
+      self
+         .workspaces
          .lock()
          .unwrap()
-         .contains_key(&workspace_path)
+         .iter()
+         .filter(|workspace| path.starts_with(workspace))
+         .max_by_key(|workspace| workspace.components().count())
+         .cloned()
+   }
+
+   /// Starts every enabled server for the language of `path` that isn't already running for the
+   /// file's project root. Failures are logged so one missing server doesn't block the others.
+   async fn ensure_servers_for_file(&self, path: &Path, language_id: &str) {
+      let workspace = self.workspace_for_file(path);
+      let registry = self.registry(workspace.as_deref());
+
+      for config in registry
+         .servers()
+         .filter(|s| s.handles_language(language_id))
       {
-         log::info!("LSP already running for workspace: {:?}", workspace_path);
-         return Ok(());
+         let key = ServerKey {
+            root: config.find_root(path, workspace.as_deref()),
+            server: config.name.clone(),
+         };
+         let start_lock = self.start_lock(&key);
+         let _start_guard = start_lock.lock().await;
+
+         let running = self
+            .servers
+            .lock()
+            .unwrap()
+            .keys()
+            .any(|key| key.server == config.name && path.starts_with(&key.root));
+         if running || self.failed_servers.lock().unwrap().contains(&key) {
+            continue;
+         }
+
+         if self
+            .start_server(key.clone(), config.clone())
+            .await
+            .is_err()
+         {
+            self.failed_servers.lock().unwrap().insert(key);
+         }
       }
+   }
 
-      // Find appropriate LSP server for workspace
-      let server_config = self
-         .registry
-         .find_server_for_workspace(&workspace_path)
-         .context("No LSP server found for workspace")?;
+   /// The lock held while a server is started, stopped or restarted.
+   fn start_lock(&self, key: &ServerKey) -> Arc<tokio::sync::Mutex<()>> {
+      self
+         .start_locks
+         .lock()
+         .unwrap()
+         .entry(key.clone())
+         .or_default()
+         .clone()
+   }
+
+   /// Spawns and initializes a server, reporting its progress through status events.
+   async fn start_server(&self, key: ServerKey, config: LspServerConfig) -> Result<()> {
+      self.emit_status(&key, ServerStatus::Starting, None);
+
+      match self.launch_server(&key, config).await {
+         Ok(()) => {
+            self.emit_status(&key, ServerStatus::Running, None);
+            Ok(())
+         }
+         Err(e) => {
+            log::error!(
+               "Failed to start LSP '{}' for {:?}: {:#}",
+               key.server,
+               key.root,
+               e
+            );
+            self.emit_status(&key, ServerStatus::Failed, Some(format!("{:#}", e)));
+            Err(e)
+         }
+      }
+   }
 
-      log::info!("Using LSP server '{}' for workspace", server_config.name);
+   async fn launch_server(&self, key: &ServerKey, config: LspServerConfig) -> Result<()> {
+      log::info!("Starting LSP '{}' for root {:?}", key.server, key.root);
 
       // Get server executable path
-      let server_path = self.get_server_path(&server_config.name)?;
-      let root_uri = Url::from_file_path(&workspace_path)
-         .map_err(|_| anyhow::anyhow!("Invalid workspace path"))?;
+      let server_path = self.get_server_path(&config, &key.root)?;
+      let root_uri =
+         Url::from_file_path(&key.root).map_err(|_| anyhow::anyhow!("Invalid root path"))?;
 
-      let (client, child) =
-         LspClient::start(server_path, server_config.args.clone(), root_uri.clone())?;
+      let id = self.next_instance_id.fetch_add(1, Ordering::Relaxed);
+      let (client, mut child) = LspClient::start(
+         server_path,
+         config.args.clone(),
+         root_uri.clone(),
+         dispatch::message_handler(self.app_handle.clone(), key.clone(), config.clone()),
+         supervisor::exit_handler(self.app_handle.clone(), key.clone(), id),
+         self.trace_for(key),
+      )?;
 
       // Initialize the client
-      client.initialize(root_uri).await?;
+      if let Err(e) = client
+         .initialize(root_uri, config.initialization_options.clone())
+         .await
+      {
+         let _ = child.kill();
+         return Err(e);
+      }
 
+      let capabilities = client.capabilities();
+
+      // Hold the document lock while registering so no open or change slips in between the
+      // server becoming visible and it receiving the current documents
+      let documents = self.documents.lock().unwrap();
+      for (path, document) in documents.iter() {
+         if path.starts_with(&key.root)
+            && config.handles_language(&document.language_id)
+            && let Err(e) = client.text_document_did_open(DidOpenTextDocumentParams {
+               text_document: document.to_item(),
+            })
+         {
+            let _ = child.kill();
+            return Err(e);
+         }
+      }
+
+      self.servers.lock().unwrap().insert(
+         key.clone(),
+         ServerInstance {
+            id,
+            client,
+            child,
+            config,
+            started_at: Instant::now(),
+         },
+      );
+      drop(documents);
+
+      // Lets the UI show the actions this server supports
+      if let Some(capabilities) = capabilities {
+         let event = ServerCapabilitiesItem {
+            server: key.clone(),
+            capabilities,
+         };
+         let _ = self.app_handle.emit("lsp-server-started", &event);
+      }
+      self.refresh_pulled_diagnostics(key);
+
+      log::info!("LSP '{}' started and initialized successfully", key.server);
+      Ok(())
+   }
+
+   fn emit_diagnostics_changed(app_handle: &AppHandle, path: &Path, items: Vec<DiagnosticItem>) {
+      let event = DiagnosticsChangedEvent {
+         file: path.to_string_lossy().to_string(),
+         diagnostics: items,
+      };
+      let _ = app_handle.emit("diagnostics-changed", &event);
+   }
+
+   fn emit_status(&self, key: &ServerKey, status: ServerStatus, message: Option<String>) {
+      let event = ServerStatusEvent {
+         server: key.clone(),
+         status,
+         message,
+      };
+      let _ = self.app_handle.emit("lsp-server-status", &event);
+   }
+
+   pub fn get_diagnostics(
+      &self,
+      path: Option<&Path>,
+      severity: Option<DiagnosticSeverity>,
+   ) -> Vec<DiagnosticItem> {
+      self.diagnostics.query(path, severity)
+   }
+
+   /// Clients of every running server that handles `file_path`.
+   pub fn clients_for_file(&self, file_path: &str) -> Vec<LspClient> {
+      let language_id = self.get_language_id_for_file(file_path);
+      self.clients_for_language(Path::new(file_path), &language_id)
+   }
+
+   /// Like `clients_for_file` but with the language already known, for callers holding the
+   /// documents lock.
+   fn clients_for_language(&self, path: &Path, language_id: &str) -> Vec<LspClient> {
       self
-         .workspace_clients
+         .servers_for_language(path, language_id)
+         .into_iter()
+         .map(|(_, client)| client)
+         .collect()
+   }
+
+   fn servers_for_language(&self, path: &Path, language_id: &str) -> Vec<(ServerKey, LspClient)> {
+      self
+         .servers
          .lock()
          .unwrap()
-         .insert(workspace_path, (client, child, server_config.name.clone()));
+         .iter()
+         .filter(|(key, instance)| {
+            path.starts_with(&key.root) && instance.config.handles_language(language_id)
+         })
+         .map(|(key, instance)| (key.clone(), instance.client.clone()))
+         .collect()
+   }
 
-      log::info!(
-         "LSP '{}' started and initialized successfully",
-         server_config.name
-      );
-      Ok(())
+   /// Capabilities of every initialized server that handles `file_path`.
+   pub fn server_capabilities(&self, file_path: &str) -> Vec<ServerCapabilitiesItem> {
+      let language_id = self.get_language_id_for_file(file_path);
+      self
+         .servers_for_language(Path::new(file_path), &language_id)
+         .into_iter()
+         .filter_map(|(server, client)| {
+            client
+               .capabilities()
+               .map(|capabilities| ServerCapabilitiesItem {
+                  server,
+                  capabilities,
+               })
+         })
+         .collect()
    }
 
-   pub fn get_client_for_file(&self, file_path: &str) -> Option<LspClient> {
-      let path = PathBuf::from(file_path);
-      let clients = self.workspace_clients.lock().unwrap();
+   fn client_for_server(&self, key: &ServerKey) -> Result<LspClient> {
+      self
+         .servers
+         .lock()
+         .unwrap()
+         .get(key)
+         .map(|instance| instance.client.clone())
+         .with_context(|| format!("LSP '{}' is not running for {:?}", key.server, key.root))
+   }
 
-      // Find workspace that contains this file
-      for (workspace_path, (client, _, _)) in clients.iter() {
-         if path.starts_with(workspace_path) {
-            return Some(client.clone());
+   fn require_clients_for_file(&self, file_path: &str) -> Result<Vec<LspClient>> {
+      let clients = self.clients_for_file(file_path);
+      if clients.is_empty() {
+         bail!("No LSP client for this file");
+      }
+      Ok(clients)
+   }
+
+   fn text_document_position(
+      &self,
+      file_path: &str,
+      line: u32,
+      character: u32,
+   ) -> Result<TextDocumentPositionParams> {
+      Ok(TextDocumentPositionParams {
+         text_document: TextDocumentIdentifier {
+            uri: Url::from_file_path(file_path)
+               .map_err(|_| anyhow::anyhow!("Invalid file path"))?,
+         },
+         position: Position { line, character },
+      })
+   }
+
+   /// Current text of a file, from the editor's copy when it's open and from disk otherwise.
+   fn read_document_text(&self, path: &Path) -> Option<String> {
+      if let Some(document) = self.documents.lock().unwrap().get(path) {
+         return Some(document.text.clone());
+      }
+      std::fs::read_to_string(path).ok()
+   }
+
+   pub async fn goto(
+      &self,
+      kind: GotoKind,
+      file_path: &str,
+      line: u32,
+      character: u32,
+   ) -> Result<Vec<LocationItem>> {
+      let clients = self.require_clients_for_file(file_path)?;
+      let params = GotoDefinitionParams {
+         text_document_position_params: self.text_document_position(file_path, line, character)?,
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
+
+      let responses = join_all(clients.iter().map(|client| {
+         let params = params.clone();
+         async move {
+            match kind {
+               GotoKind::Definition => client.text_document_definition(params).await,
+               GotoKind::Declaration => client.text_document_declaration(params).await,
+               GotoKind::TypeDefinition => client.text_document_type_definition(params).await,
+               GotoKind::Implementation => client.text_document_implementation(params).await,
+            }
+         }
+      }))
+      .await;
+
+      let mut locations = Vec::new();
+      for response in responses {
+         match response {
+            Ok(Some(response)) => locations.extend(locations::flatten_goto_response(response)),
+            Ok(None) => {}
+            Err(e) => log::warn!("{:?} request failed: {}", kind, e),
          }
       }
 
-      None
+      Ok(locations::to_location_items(locations, |path| {
+         self.read_document_text(path)
+      }))
    }
 
-   pub async fn get_completions(
+   pub async fn find_references(
       &self,
       file_path: &str,
       line: u32,
       character: u32,
-   ) -> Result<Vec<CompletionItem>> {
-      let start_time = Instant::now();
+      include_declaration: bool,
+   ) -> Result<Vec<LocationItem>> {
+      let clients = self.require_clients_for_file(file_path)?;
+      let params = ReferenceParams {
+         text_document_position: self.text_document_position(file_path, line, character)?,
+         context: ReferenceContext {
+            include_declaration,
+         },
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
 
-      let client = self
-         .get_client_for_file(file_path)
-         .context("No LSP client for this file")?;
+      let responses = join_all(
+         clients
+            .iter()
+            .map(|client| client.text_document_references(params.clone())),
+      )
+      .await;
 
-      let params = CompletionParams {
-         text_document_position: TextDocumentPositionParams {
-            text_document: TextDocumentIdentifier {
-               uri: Url::from_file_path(file_path)
-                  .map_err(|_| anyhow::anyhow!("Invalid file path"))?,
+      let mut locations = Vec::new();
+      for response in responses {
+         match response {
+            Ok(Some(response)) => locations.extend(response),
+            Ok(None) => {}
+            Err(e) => log::warn!("References request failed: {}", e),
+         }
+      }
+
+      Ok(locations::to_location_items(locations, |path| {
+         self.read_document_text(path)
+      }))
+   }
+
+   pub async fn prepare_rename(
+      &self,
+      file_path: &str,
+      line: u32,
+      character: u32,
+   ) -> Result<Option<PrepareRenameItem>> {
+      let clients = self.require_clients_for_file(file_path)?;
+      let params = self.text_document_position(file_path, line, character)?;
+
+      for client in clients {
+         let response = match client.text_document_prepare_rename(params.clone()).await {
+            Ok(Some(response)) => response,
+            Ok(None) => continue,
+            Err(e) => {
+               log::warn!("prepareRename request failed: {}", e);
+               continue;
+            }
+         };
+
+         let item = match response {
+            PrepareRenameResponse::Range(range) => {
+               let placeholder = self.read_document_text(Path::new(file_path)).map(|text| {
+                  let start = documents::position_to_offset(&text, range.start);
+                  let end = documents::position_to_offset(&text, range.end);
+                  text[start..end.max(start)].to_string()
+               });
+               PrepareRenameItem {
+                  range: Some(range),
+                  placeholder,
+               }
+            }
+            PrepareRenameResponse::RangeWithPlaceholder { range, placeholder } => {
+               PrepareRenameItem {
+                  range: Some(range),
+                  placeholder: Some(placeholder),
+               }
+            }
+            PrepareRenameResponse::DefaultBehavior { .. } => PrepareRenameItem {
+               range: None,
+               placeholder: None,
             },
-            position: Position { line, character },
+         };
+         return Ok(Some(item));
+      }
+
+      Ok(None)
+   }
+
+   /// Asks the servers for a rename and returns the resulting edit with a per-file summary,
+   /// without touching any file. Apply it with `apply_workspace_edit`.
+   pub async fn rename(
+      &self,
+      file_path: &str,
+      line: u32,
+      character: u32,
+      new_name: String,
+   ) -> Result<Option<WorkspaceEditPreview>> {
+      let clients = self.require_clients_for_file(file_path)?;
+      let params = RenameParams {
+         text_document_position: self.text_document_position(file_path, line, character)?,
+         new_name,
+         work_done_progress_params: Default::default(),
+      };
+
+      // Renames from different servers would conflict, so the first one that answers wins
+      let mut last_error = None;
+      for client in clients {
+         match client.text_document_rename(params.clone()).await {
+            Ok(Some(edit)) => return Ok(Some(self.preview_workspace_edit(edit))),
+            Ok(None) => {}
+            Err(e) => last_error = Some(e),
+         }
+      }
+
+      match last_error {
+         Some(e) => Err(e),
+         None => Ok(None),
+      }
+   }
+
+   pub fn preview_workspace_edit(&self, edit: WorkspaceEdit) -> WorkspaceEditPreview {
+      let operations = workspace_edit::collect_operations(edit.clone());
+      let files = {
+         let documents = self.documents.lock().unwrap();
+         workspace_edit::summarize(&operations, |uri| {
+            uri.to_file_path()
+               .is_ok_and(|path| documents.contains_key(&path))
+         })
+      };
+      let total_edits = files.iter().map(|file| file.edit_count).sum();
+
+      WorkspaceEditPreview {
+         edit,
+         files,
+         total_edits,
+      }
+   }
+
+   /// Applies a workspace edit operation by operation. Open documents are edited in the mirror
+   /// and synced to their servers; everything else is changed on disk. A failing file doesn't
+   /// stop the rest, each outcome is reported separately.
+   pub fn apply_workspace_edit(&self, edit: WorkspaceEdit) -> WorkspaceEditResult {
+      let files: Vec<FileEditOutcome> = workspace_edit::collect_operations(edit)
+         .into_iter()
+         .map(|operation| self.apply_edit_operation(operation))
+         .collect();
+
+      let result = WorkspaceEditResult {
+         applied: files.iter().all(|file| file.success),
+         files,
+      };
+      let _ = self.app_handle.emit("workspace-edit-applied", &result);
+      result
+   }
+
+   fn apply_edit_operation(&self, operation: EditOperation) -> FileEditOutcome {
+      let path = workspace_edit::uri_to_display_path(operation.uri());
+      let mut outcome = FileEditOutcome {
+         path,
+         kind: FileEditKind::Edit,
+         success: true,
+         error: None,
+         new_path: None,
+         text: None,
+         version: None,
+      };
+
+      let result = match operation {
+         EditOperation::Edit {
+            uri,
+            version,
+            edits,
+         } => self
+            .apply_text_edits_to_file(&uri, version, &edits)
+            .map(|updated| {
+               if let Some((text, version)) = updated {
+                  outcome.text = Some(text);
+                  outcome.version = Some(version);
+               }
+            }),
+         EditOperation::Create(op) => {
+            outcome.kind = FileEditKind::Create;
+            workspace_edit::create_file(&op)
+         }
+         EditOperation::Rename(op) => {
+            outcome.kind = FileEditKind::Rename;
+            outcome.new_path = Some(workspace_edit::uri_to_display_path(&op.new_uri));
+            self.rename_file(&op)
+         }
+         EditOperation::Delete(op) => {
+            outcome.kind = FileEditKind::Delete;
+            self.delete_file(&op)
+         }
+      };
+
+      if let Err(e) = result {
+         log::warn!("Failed to apply edit to {}: {:#}", outcome.path, e);
+         outcome.success = false;
+         outcome.error = Some(format!("{:#}", e));
+      }
+      outcome
+   }
+
+   /// Returns the new text and version when the file is an open document.
+   fn apply_text_edits_to_file(
+      &self,
+      uri: &Url,
+      version: Option<i32>,
+      edits: &[TextEdit],
+   ) -> Result<Option<(String, i32)>> {
+      let path = uri
+         .to_file_path()
+         .map_err(|_| anyhow::anyhow!("Not a file URI: {}", uri))?;
+
+      let mut documents = self.documents.lock().unwrap();
+      let Some(document) = documents.get_mut(&path) else {
+         drop(documents);
+         workspace_edit::edit_file(&path, edits)?;
+         return Ok(None);
+      };
+
+      workspace_edit::check_version(version, document.version)?;
+
+      document.text = documents::apply_text_edits(&document.text, edits)?;
+      document.version += 1;
+
+      let params = DidChangeTextDocumentParams {
+         text_document: VersionedTextDocumentIdentifier {
+            uri: document.uri.clone(),
+            version: document.version,
          },
-         context: Some(CompletionContext {
-            trigger_kind: CompletionTriggerKind::INVOKED,
-            trigger_character: None,
-         }),
+         content_changes: vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: document.text.clone(),
+         }],
+      };
+      for client in self.clients_for_language(&path, &document.language_id) {
+         client.text_document_did_change(params.clone())?;
+      }
+
+      Ok(Some((document.text.clone(), document.version)))
+   }
+
+   fn rename_file(&self, op: &RenameFile) -> Result<()> {
+      let Some((old_path, new_path)) = workspace_edit::rename_file(op)? else {
+         return Ok(());
+      };
+
+      // Move the open document along with the file, servers see a close and a fresh open
+      let moved = self.documents.lock().unwrap().remove(&old_path);
+      if let Some(mut document) = moved {
+         for client in self.clients_for_language(&old_path, &document.language_id) {
+            client.text_document_did_close(DidCloseTextDocumentParams {
+               text_document: TextDocumentIdentifier {
+                  uri: document.uri.clone(),
+               },
+            })?;
+         }
+
+         document.uri = op.new_uri.clone();
+         document.language_id = self
+            .registry(self.workspace_for_file(&new_path).as_deref())
+            .language_id_for_path(&new_path)
+            .unwrap_or_else(|| "plaintext".to_string());
+
+         let mut documents = self.documents.lock().unwrap();
+         for client in self.clients_for_language(&new_path, &document.language_id) {
+            client.text_document_did_open(DidOpenTextDocumentParams {
+               text_document: document.to_item(),
+            })?;
+         }
+         documents.insert(new_path, document);
+      }
+
+      Ok(())
+   }
+
+   fn delete_file(&self, op: &DeleteFile) -> Result<()> {
+      let Some(path) = workspace_edit::delete_file(op)? else {
+         return Ok(());
+      };
+
+      let removed: Vec<(PathBuf, OpenDocument)> = {
+         let mut documents = self.documents.lock().unwrap();
+         let paths: Vec<PathBuf> = documents
+            .keys()
+            .filter(|open| open.starts_with(&path))
+            .cloned()
+            .collect();
+         paths
+            .into_iter()
+            .filter_map(|open| documents.remove(&open).map(|document| (open, document)))
+            .collect()
+      };
+      for (open, document) in removed {
+         for client in self.clients_for_language(&open, &document.language_id) {
+            client.text_document_did_close(DidCloseTextDocumentParams {
+               text_document: TextDocumentIdentifier {
+                  uri: document.uri.clone(),
+               },
+            })?;
+         }
+      }
+
+      Ok(())
+   }
+
+   /// Code actions from every server for `range`, each server receiving the diagnostics it
+   /// published that overlap the range.
+   pub async fn code_actions(
+      &self,
+      file_path: &str,
+      range: Range,
+      only: Option<Vec<String>>,
+   ) -> Result<Vec<CodeActionItem>> {
+      let path = Path::new(file_path);
+      let language_id = self.get_language_id_for_file(file_path);
+      let servers = self.servers_for_language(path, &language_id);
+      if servers.is_empty() {
+         bail!("No LSP client for this file");
+      }
+
+      let uri = Url::from_file_path(file_path).map_err(|_| anyhow::anyhow!("Invalid file path"))?;
+      let only: Option<Vec<CodeActionKind>> =
+         only.map(|kinds| kinds.into_iter().map(CodeActionKind::from).collect());
+
+      let requests = servers.into_iter().map(|(key, client)| {
+         let params = CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range,
+            context: CodeActionContext {
+               diagnostics: self.diagnostics.in_range(&key.server, path, range),
+               only: only.clone(),
+               trigger_kind: Some(CodeActionTriggerKind::INVOKED),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+         };
+         async move { (key, client.text_document_code_action(params).await) }
+      });
+
+      let mut items = Vec::new();
+      for (key, response) in join_all(requests).await {
+         match response {
+            Ok(Some(actions)) => items.extend(actions.into_iter().map(|action| CodeActionItem {
+               server: key.clone(),
+               action,
+            })),
+            Ok(None) => {}
+            Err(e) => log::warn!("Code action request to '{}' failed: {}", key.server, e),
+         }
+      }
+
+      // Preferred quick fixes first, otherwise keep the servers' order
+      items.sort_by_key(|item| match &item.action {
+         CodeActionOrCommand::CodeAction(action) => !action.is_preferred.unwrap_or(false),
+         CodeActionOrCommand::Command(_) => true,
+      });
+
+      Ok(items)
+   }
+
+   pub async fn resolve_code_action(
+      &self,
+      server: &ServerKey,
+      action: CodeAction,
+   ) -> Result<CodeAction> {
+      self
+         .client_for_server(server)?
+         .code_action_resolve(action)
+         .await
+   }
+
+   /// Runs a code action the way the server intends: resolve it if it came back without an
+   /// edit, apply the edit, then run its command.
+   pub async fn apply_code_action(
+      &self,
+      server: &ServerKey,
+      action: CodeActionOrCommand,
+   ) -> Result<CodeActionOutcome> {
+      let client = self.client_for_server(server)?;
+
+      let (edit, command) = match action {
+         CodeActionOrCommand::Command(command) => (None, Some(command)),
+         CodeActionOrCommand::CodeAction(mut action) => {
+            if action.edit.is_none() && action.command.is_none() {
+               action = client.code_action_resolve(action).await?;
+            }
+            (action.edit, action.command)
+         }
+      };
+
+      let edit = edit.map(|edit| self.apply_workspace_edit(edit));
+      if let Some(result) = &edit
+         && !result.applied
+      {
+         // Running the command on top of a half-applied edit would only make things worse
+         return Ok(CodeActionOutcome {
+            edit: edit.clone(),
+            command_result: None,
+         });
+      }
+
+      let command_result = match command {
+         Some(command) => {
+            client
+               .workspace_execute_command(ExecuteCommandParams {
+                  command: command.command,
+                  arguments: command.arguments.unwrap_or_default(),
+                  work_done_progress_params: Default::default(),
+               })
+               .await?
+         }
+         None => None,
+      };
+
+      Ok(CodeActionOutcome {
+         edit,
+         command_result,
+      })
+   }
+
+   pub async fn execute_command(
+      &self,
+      server: &ServerKey,
+      command: String,
+      arguments: Vec<Value>,
+   ) -> Result<Option<Value>> {
+      self
+         .client_for_server(server)?
+         .workspace_execute_command(ExecuteCommandParams {
+            command,
+            arguments,
+            work_done_progress_params: Default::default(),
+         })
+         .await
+   }
+
+   fn track_request(&self, request_id: Option<String>) -> TrackedRequest<'_> {
+      let token = CancellationToken::new();
+      if let Some(request_id) = &request_id {
+         self
+            .cancellations
+            .lock()
+            .unwrap()
+            .insert(request_id.clone(), token.clone());
+      }
+      TrackedRequest {
+         manager: self,
+         request_id,
+         token,
+      }
+   }
+
+   /// Cancels a request started with `request_id`. Requests that already finished are ignored.
+   pub fn cancel_request(&self, request_id: &str) {
+      if let Some(token) = self.cancellations.lock().unwrap().remove(request_id) {
+         token.cancel();
+      }
+   }
+
+   /// Completions from every server for the file, filtered by the word before the cursor and
+   /// then capped; a capped list is marked incomplete so the editor asks again as the user
+   /// types. A new request cancels the one before it, and a cancelled request returns nothing.
+   pub async fn get_completions(
+      &self,
+      file_path: &str,
+      position: Position,
+      context: CompletionContext,
+      request_id: Option<String>,
+   ) -> Result<CompletionListResult> {
+      let start_time = Instant::now();
+
+      let path = Path::new(file_path);
+      let language_id = self.get_language_id_for_file(file_path);
+      let servers = self.servers_for_language(path, &language_id);
+      if servers.is_empty() {
+         bail!("No LSP client for this file type");
+      }
+
+      let request = self.track_request(request_id);
+      if let Some(previous) = self
+         .latest_completion
+         .lock()
+         .unwrap()
+         .replace(request.token.clone())
+      {
+         previous.cancel();
+      }
+
+      let params = CompletionParams {
+         text_document_position: self.text_document_position(
+            file_path,
+            position.line,
+            position.character,
+         )?,
+         context: Some(context),
          work_done_progress_params: Default::default(),
          partial_result_params: Default::default(),
       };
 
-      let response = client.text_document_completion(params).await?;
-      let max_completions = self.settings.max_completion_items;
+      let responses =
+         join_all(servers.iter().map(|(_, client)| {
+            client.text_document_completion(params.clone(), Some(&request.token))
+         }))
+         .await;
+      if request.token.is_cancelled() {
+         return Ok(CompletionListResult {
+            is_incomplete: true,
+            items: Vec::new(),
+         });
+      }
 
-      let mut items = match response {
-         Some(CompletionResponse::Array(items)) => items,
-         Some(CompletionResponse::List(list)) => list.items,
-         None => vec![],
-      };
+      let mut is_incomplete = false;
+      let mut items = Vec::new();
+      for ((key, _), response) in servers.iter().zip(responses) {
+         let list = match response {
+            Ok(Some(CompletionResponse::Array(items))) => items,
+            Ok(Some(CompletionResponse::List(list))) => {
+               is_incomplete |= list.is_incomplete;
+               list.items
+            }
+            Ok(None) => continue,
+            Err(e) if client::is_cancellation(&e) => continue,
+            Err(e) => {
+               log::warn!("Completion request to '{}' failed: {}", key.server, e);
+               continue;
+            }
+         };
+         items.extend(list.into_iter().map(|item| (key, item)));
+      }
 
+      let prefix = self
+         .read_document_text(path)
+         .map(|text| word_before(&text, position))
+         .unwrap_or_default();
+      if !prefix.is_empty() {
+         items.retain(|(_, item)| {
+            fuzzy_matches(item.filter_text.as_deref().unwrap_or(&item.label), &prefix)
+         });
+      }
+      items.sort_by(|(_, a), (_, b)| {
+         let a = a.sort_text.as_deref().unwrap_or(&a.label);
+         let b = b.sort_text.as_deref().unwrap_or(&b.label);
+         a.cmp(b)
+      });
+
+      let max_completions = self.settings.max_completion_items;
       if items.len() > max_completions {
          log::debug!(
-            "LSP returned {} completions, limiting to {}",
+            "LSP returned {} matching completions, limiting to {}",
             items.len(),
             max_completions
          );
          items.truncate(max_completions);
+         is_incomplete = true;
       }
 
-      let elapsed = start_time.elapsed();
+      let items: Vec<CompletionEntry> = items
+         .into_iter()
+         .map(|(key, item)| self.completion_entry(key.clone(), item, path))
+         .collect();
+
       log::debug!(
          "LSP completion request completed in {:?} with {} items",
-         elapsed,
+         start_time.elapsed(),
          items.len()
       );
 
-      Ok(items)
+      Ok(CompletionListResult {
+         is_incomplete,
+         items,
+      })
+   }
+
+   /// Fills in the documentation, detail and additional edits (such as auto-imports) servers
+   /// leave out of the initial list.
+   pub async fn resolve_completion(
+      &self,
+      server: &ServerKey,
+      item: CompletionItem,
+      file_path: &str,
+   ) -> Result<CompletionEntry> {
+      let client = self.client_for_server(server)?;
+      let supported = client.capabilities().is_some_and(|caps| {
+         caps
+            .completion_provider
+            .is_some_and(|provider| provider.resolve_provider == Some(true))
+      });
+
+      let item = if supported {
+         client.completion_resolve(item).await?
+      } else {
+         item
+      };
+      Ok(self.completion_entry(server.clone(), item, Path::new(file_path)))
+   }
+
+   fn completion_entry(
+      &self,
+      server: ServerKey,
+      item: CompletionItem,
+      path: &Path,
+   ) -> CompletionEntry {
+      let snippet = (item.insert_text_format == Some(InsertTextFormat::SNIPPET)).then(|| {
+         let text = match &item.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => &edit.new_text,
+            Some(CompletionTextEdit::InsertAndReplace(edit)) => &edit.new_text,
+            None => item.insert_text.as_ref().unwrap_or(&item.label),
+         };
+         snippet::parse(text, |name| snippet_variable(path, name))
+      });
+
+      CompletionEntry {
+         item,
+         server,
+         snippet,
+      }
    }
 
    pub async fn get_hover(
@@ -202,128 +1142,219 @@ impl LspManager {
       file_path: &str,
       line: u32,
       character: u32,
+      request_id: Option<String>,
    ) -> Result<Option<Hover>> {
-      let client = self
-         .get_client_for_file(file_path)
-         .context("No LSP client for this file")?;
-
-      let text_document = TextDocumentIdentifier {
-         uri: Url::from_file_path(file_path).map_err(|_| anyhow::anyhow!("Invalid file path"))?,
-      };
+      let clients = self.require_clients_for_file(file_path)?;
+      let request = self.track_request(request_id);
 
       let params = HoverParams {
-         text_document_position_params: TextDocumentPositionParams {
-            text_document,
-            position: Position { line, character },
-         },
+         text_document_position_params: self.text_document_position(file_path, line, character)?,
          work_done_progress_params: Default::default(),
       };
 
-      client.text_document_hover(params).await
+      // First server with something to say wins
+      for client in clients {
+         match client
+            .text_document_hover(params.clone(), Some(&request.token))
+            .await
+         {
+            Ok(Some(hover)) => return Ok(Some(hover)),
+            Ok(None) => {}
+            Err(e) if client::is_cancellation(&e) => {
+               if request.token.is_cancelled() {
+                  return Ok(None);
+               }
+            }
+            Err(e) => log::warn!("Hover request failed: {}", e),
+         }
+      }
+
+      Ok(None)
    }
 
-   pub fn notify_document_open(&self, file_path: &str, content: String) -> Result<()> {
+   pub async fn notify_document_open(&self, file_path: &str, content: String) -> Result<()> {
       let path = PathBuf::from(file_path);
-      let _extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
-
-      let client = self
-         .get_client_for_file(file_path)
-         .context("No LSP client for this file")?;
-
-      let params = DidOpenTextDocumentParams {
-         text_document: TextDocumentItem {
-            uri: Url::from_file_path(file_path)
-               .map_err(|_| anyhow::anyhow!("Invalid file path"))?,
-            language_id: self.get_language_id_for_file(file_path),
-            version: 1,
-            text: content,
-         },
+      let document = OpenDocument {
+         uri: Url::from_file_path(file_path).map_err(|_| anyhow::anyhow!("Invalid file path"))?,
+         language_id: self.get_language_id_for_file(file_path),
+         version: 1,
+         text: content,
       };
+      let language_id = document.language_id.clone();
 
-      client.text_document_did_open(params)
+      // Servers already running get the document now, servers started below receive it along
+      // with every other open document they handle. Servers register under the documents lock,
+      // so each one gets the document exactly once.
+      {
+         let mut documents = self.documents.lock().unwrap();
+         documents.insert(path.clone(), document.clone());
+         for client in self.clients_for_language(&path, &language_id) {
+            client.text_document_did_open(DidOpenTextDocumentParams {
+               text_document: document.to_item(),
+            })?;
+         }
+      }
+
+      self.ensure_servers_for_file(&path, &language_id).await;
+      self.schedule_document_diagnostics(path);
+      Ok(())
    }
 
+   /// Syncs an edit to the mirror and to every server for the file. The frontend sends
+   /// incremental `changes` (UTF-16 ranges), the full `content`, or both; with both, the content
+   /// is authoritative and servers get the full text if the changes don't reproduce it.
    pub fn notify_document_change(
       &self,
       file_path: &str,
-      content: String,
+      content: Option<String>,
+      changes: Vec<TextDocumentContentChangeEvent>,
       version: i32,
    ) -> Result<()> {
-      let path = PathBuf::from(file_path);
-      let _extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+      let mut documents = self.documents.lock().unwrap();
+      let document = documents
+         .get_mut(Path::new(file_path))
+         .context("Document is not open")?;
 
-      let client = self
-         .get_client_for_file(file_path)
-         .context("No LSP client for this file")?;
+      let (text, incremental) = if changes.is_empty() {
+         (
+            content.context("A document change needs either content or changes")?,
+            false,
+         )
+      } else {
+         let applied = documents::apply_content_changes(&document.text, &changes);
+         match (applied, content) {
+            (Ok(applied), Some(content)) => {
+               let in_sync = applied == content;
+               if !in_sync {
+                  log::warn!(
+                     "Incremental changes for {} diverged, sending full text",
+                     file_path
+                  );
+               }
+               (content, in_sync)
+            }
+            (Err(e), Some(content)) => {
+               log::warn!(
+                  "Invalid changes for {}: {}, sending full text",
+                  file_path,
+                  e
+               );
+               (content, false)
+            }
+            (Ok(applied), None) => (applied, true),
+            (Err(e), None) => {
+               return Err(e.context("Document is out of sync, the full content is needed"));
+            }
+         }
+      };
 
-      let params = DidChangeTextDocumentParams {
-         text_document: VersionedTextDocumentIdentifier {
-            uri: Url::from_file_path(file_path)
-               .map_err(|_| anyhow::anyhow!("Invalid file path"))?,
-            version,
-         },
-         content_changes: vec![TextDocumentContentChangeEvent {
-            range: None,
-            range_length: None,
-            text: content,
-         }],
+      // Versions must increase even if the frontend doesn't track them
+      document.version = version.max(document.version + 1);
+      document.text = text;
+
+      let identifier = VersionedTextDocumentIdentifier {
+         uri: document.uri.clone(),
+         version: document.version,
       };
+      let full_change = || TextDocumentContentChangeEvent {
+         range: None,
+         range_length: None,
+         text: document.text.clone(),
+      };
+
+      for client in self.clients_for_language(Path::new(file_path), &document.language_id) {
+         let content_changes = match client.change_sync_kind() {
+            TextDocumentSyncKind::INCREMENTAL if incremental => changes.clone(),
+            TextDocumentSyncKind::NONE => continue,
+            _ => vec![full_change()],
+         };
+         client.text_document_did_change(DidChangeTextDocumentParams {
+            text_document: identifier.clone(),
+            content_changes,
+         })?;
+      }
+      drop(documents);
 
-      client.text_document_did_change(params)
+      self.schedule_document_diagnostics(PathBuf::from(file_path));
+      Ok(())
    }
 
    pub fn notify_document_close(&self, file_path: &str) -> Result<()> {
-      let path = PathBuf::from(file_path);
-      let _extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
-
-      let client = self
-         .get_client_for_file(file_path)
-         .context("No LSP client for this file")?;
+      let clients = self.clients_for_file(file_path);
+      let Some(document) = self.documents.lock().unwrap().remove(Path::new(file_path)) else {
+         return Ok(());
+      };
+      self.clear_semantic_cache(Some(Path::new(file_path)), None);
 
       let params = DidCloseTextDocumentParams {
-         text_document: TextDocumentIdentifier {
-            uri: Url::from_file_path(file_path)
-               .map_err(|_| anyhow::anyhow!("Invalid file path"))?,
-         },
+         text_document: TextDocumentIdentifier { uri: document.uri },
       };
 
-      client.text_document_did_close(params)
+      for client in clients {
+         client.text_document_did_close(params.clone())?;
+      }
+      Ok(())
    }
 
+   /// Stops every server, each with the shutdown handshake. Blocks, since it runs when the app
+   /// goes away.
    pub fn shutdown(&self) {
-      let mut clients = self.workspace_clients.lock().unwrap();
-      for (workspace, (_, mut child, name)) in clients.drain() {
-         log::info!("Shutting down LSP '{}' for workspace {:?}", name, workspace);
-         let _ = child.kill();
-      }
+      let stopped: Vec<(ServerKey, ServerInstance)> =
+         self.servers.lock().unwrap().drain().collect();
+
+      std::thread::scope(|scope| {
+         for (key, instance) in stopped {
+            scope.spawn(move || {
+               tauri::async_runtime::block_on(self.stop_instance(&key, instance));
+            });
+         }
+      });
    }
 
-   pub fn shutdown_workspace(&self, workspace_path: &PathBuf) -> Result<()> {
-      let mut clients = self.workspace_clients.lock().unwrap();
-      if let Some((_, mut child, name)) = clients.remove(workspace_path) {
-         log::info!(
-            "Shutting down LSP '{}' for workspace {:?}",
-            name,
-            workspace_path
-         );
-         child.kill()?;
-      }
-      Ok(())
+   /// Stops every server whose root lies inside `workspace_path` and forgets the workspace.
+   pub async fn shutdown_workspace(&self, workspace_path: &Path) {
+      self
+         .workspaces
+         .lock()
+         .unwrap()
+         .retain(|workspace| workspace != workspace_path);
+      self
+         .failed_servers
+         .lock()
+         .unwrap()
+         .retain(|key| !key.root.starts_with(workspace_path));
+
+      let stopped: Vec<(ServerKey, ServerInstance)> = {
+         let mut servers = self.servers.lock().unwrap();
+         let keys: Vec<ServerKey> = servers
+            .keys()
+            .filter(|key| key.root.starts_with(workspace_path))
+            .cloned()
+            .collect();
+         keys
+            .into_iter()
+            .filter_map(|key| servers.remove(&key).map(|instance| (key, instance)))
+            .collect()
+      };
+
+      join_all(
+         stopped
+            .into_iter()
+            .map(|(key, instance)| async move { self.stop_instance(&key, instance).await }),
+      )
+      .await;
    }
 
    fn get_language_id_for_file(&self, file_path: &str) -> String {
-      let path = PathBuf::from(file_path);
-      let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
-
-      match extension {
-         "ts" => "typescript",
-         "tsx" => "typescriptreact",
-         "js" | "mjs" | "cjs" => "javascript",
-         "jsx" => "javascriptreact",
-         "json" => "json",
-         _ => "plaintext",
+      let path = Path::new(file_path);
+      if let Some(document) = self.documents.lock().unwrap().get(path) {
+         return document.language_id.clone();
       }
-      .to_string()
+
+      self
+         .registry(self.workspace_for_file(path).as_deref())
+         .language_id_for_path(path)
+         .unwrap_or_else(|| "plaintext".to_string())
    }
 }
 
@@ -332,3 +1363,37 @@ impl Drop for LspManager {
       self.shutdown();
    }
 }
+
+/// Identifier characters directly before `position`, what the user has typed of the word.
+fn word_before(text: &str, position: Position) -> String {
+   let offset = documents::position_to_offset(text, position);
+   let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+   let word: Vec<char> = text[line_start..offset]
+      .chars()
+      .rev()
+      .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '$')
+      .collect();
+   word.into_iter().rev().collect()
+}
+
+/// Case-insensitive subsequence match, loose enough not to drop anything the editor's own
+/// fuzzy filter would keep.
+fn fuzzy_matches(candidate: &str, query: &str) -> bool {
+   let mut candidate = candidate.chars().flat_map(char::to_lowercase);
+   query
+      .chars()
+      .flat_map(char::to_lowercase)
+      .all(|q| candidate.any(|c| c == q))
+}
+
+/// Values of the snippet variables that depend only on the file being edited.
+fn snippet_variable(path: &Path, name: &str) -> Option<String> {
+   let lossy = |s: &std::ffi::OsStr| s.to_string_lossy().to_string();
+   match name {
+      "TM_FILENAME" => path.file_name().map(lossy),
+      "TM_FILENAME_BASE" => path.file_stem().map(lossy),
+      "TM_DIRECTORY" => path.parent().map(|p| lossy(p.as_os_str())),
+      "TM_FILEPATH" => Some(lossy(path.as_os_str())),
+      _ => None,
+   }
+}
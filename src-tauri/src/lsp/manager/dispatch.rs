@@ -0,0 +1,297 @@
+use super::LspManager;
+use crate::lsp::{
+   client::{METHOD_NOT_FOUND, Responder, ServerMessage, ServerMessageHandler},
+   config::LspServerConfig,
+   types::{MessageRequestEvent, ProgressEvent, ServerKey, ServerMessageEvent, ShowDocumentEvent},
+};
+use lsp_types::{
+   ApplyWorkspaceEditParams, ApplyWorkspaceEditResponse, ConfigurationParams, LogMessageParams,
+   MessageActionItem, MessageType, NumberOrString, ProgressParams, ProgressParamsValue,
+   PublishDiagnosticsParams, ShowDocumentParams, ShowDocumentResult, ShowMessageParams,
+   ShowMessageRequestParams, WorkDoneProgress, WorkspaceFolder,
+   notification::{self, Notification},
+   request::{self, Request},
+};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::sync::atomic::Ordering;
+use tauri::{AppHandle, Emitter, Manager as TauriManager};
+
+const INVALID_PARAMS: i64 = -32602;
+
+/// Builds the handler for everything `key`'s server sends on its own. It runs on the client's
+/// reader thread and looks the manager up through the app state, since the manager owns the
+/// clients the handler is installed on.
+pub(super) fn message_handler(
+   app_handle: AppHandle,
+   key: ServerKey,
+   config: LspServerConfig,
+) -> ServerMessageHandler {
+   std::sync::Arc::new(move |message: ServerMessage| {
+      let Some(manager) = app_handle.try_state::<LspManager>() else {
+         if let ServerMessage::Request { responder, .. } = message {
+            responder.respond_error(METHOD_NOT_FOUND, "Client is shutting down");
+         }
+         return;
+      };
+
+      match message {
+         ServerMessage::Notification { method, params } => {
+            manager.handle_server_notification(&key, &method, params)
+         }
+         ServerMessage::Request {
+            method,
+            params,
+            responder,
+         } => manager.handle_server_request(&key, &config, &method, params, responder),
+      }
+   })
+}
+
+fn parse_params<P: DeserializeOwned>(key: &ServerKey, method: &str, params: Value) -> Option<P> {
+   serde_json::from_value(params)
+      .inspect_err(|e| log::warn!("Invalid {} from '{}': {}", method, key.server, e))
+      .ok()
+}
+
+fn message_kind(typ: MessageType) -> &'static str {
+   match typ {
+      MessageType::ERROR => "error",
+      MessageType::WARNING => "warning",
+      MessageType::INFO => "info",
+      _ => "log",
+   }
+}
+
+impl LspManager {
+   fn handle_server_notification(&self, key: &ServerKey, method: &str, params: Value) {
+      match method {
+         notification::PublishDiagnostics::METHOD => {
+            let Some(params) = parse_params::<PublishDiagnosticsParams>(key, method, params) else {
+               return;
+            };
+            if let Some((path, items)) =
+               self
+                  .diagnostics
+                  .update(&key.server, &params.uri, params.diagnostics)
+            {
+               Self::emit_diagnostics_changed(&self.app_handle, &path, items);
+            }
+         }
+         notification::Progress::METHOD => {
+            if let Some(params) = parse_params::<ProgressParams>(key, method, params) {
+               self.emit_progress(key, params);
+            }
+         }
+         notification::ShowMessage::METHOD => {
+            if let Some(params) = parse_params::<ShowMessageParams>(key, method, params) {
+               let event = ServerMessageEvent {
+                  server: key.clone(),
+                  kind: message_kind(params.typ).to_string(),
+                  message: params.message,
+               };
+               let _ = self.app_handle.emit("lsp-show-message", &event);
+            }
+         }
+         notification::LogMessage::METHOD => {
+            if let Some(params) = parse_params::<LogMessageParams>(key, method, params) {
+               match params.typ {
+                  MessageType::ERROR => log::error!("[{}] {}", key.server, params.message),
+                  MessageType::WARNING => log::warn!("[{}] {}", key.server, params.message),
+                  MessageType::INFO => log::info!("[{}] {}", key.server, params.message),
+                  _ => log::debug!("[{}] {}", key.server, params.message),
+               }
+               let event = ServerMessageEvent {
+                  server: key.clone(),
+                  kind: message_kind(params.typ).to_string(),
+                  message: params.message,
+               };
+               let _ = self.app_handle.emit("lsp-log-message", &event);
+            }
+         }
+         _ => log::debug!(
+            "Unhandled LSP notification from '{}': {}",
+            key.server,
+            method
+         ),
+      }
+   }
+
+   fn emit_progress(&self, key: &ServerKey, params: ProgressParams) {
+      let token = match params.token {
+         NumberOrString::Number(n) => n.to_string(),
+         NumberOrString::String(s) => s,
+      };
+      let ProgressParamsValue::WorkDone(progress) = params.value;
+
+      let mut event = ProgressEvent {
+         server: key.clone(),
+         token,
+         kind: String::new(),
+         title: None,
+         message: None,
+         percentage: None,
+         cancellable: None,
+      };
+      match progress {
+         WorkDoneProgress::Begin(begin) => {
+            event.kind = "begin".to_string();
+            event.title = Some(begin.title);
+            event.message = begin.message;
+            event.percentage = begin.percentage;
+            event.cancellable = begin.cancellable;
+         }
+         WorkDoneProgress::Report(report) => {
+            event.kind = "report".to_string();
+            event.message = report.message;
+            event.percentage = report.percentage;
+            event.cancellable = report.cancellable;
+         }
+         WorkDoneProgress::End(end) => {
+            event.kind = "end".to_string();
+            event.message = end.message;
+         }
+      }
+
+      let _ = self.app_handle.emit("lsp-progress", &event);
+   }
+
+   fn handle_server_request(
+      &self,
+      key: &ServerKey,
+      config: &LspServerConfig,
+      method: &str,
+      params: Value,
+      responder: Responder,
+   ) {
+      log::debug!("LSP request from '{}': {}", key.server, method);
+
+      match method {
+         request::WorkspaceConfiguration::METHOD => {
+            let Some(params) = parse_params::<ConfigurationParams>(key, method, params) else {
+               return responder.respond_error(INVALID_PARAMS, "Invalid configuration params");
+            };
+            let items: Vec<Value> = params
+               .items
+               .iter()
+               .map(|item| config.settings_section(item.section.as_deref()))
+               .collect();
+            responder.respond(Value::Array(items));
+         }
+         request::WorkspaceFoldersRequest::METHOD => {
+            let folders = lsp_types::Url::from_file_path(&key.root)
+               .map(|uri| {
+                  vec![WorkspaceFolder {
+                     uri,
+                     name: key
+                        .root
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                  }]
+               })
+               .unwrap_or_default();
+            responder.respond(json!(folders));
+         }
+         request::ApplyWorkspaceEdit::METHOD => {
+            let Some(params) = parse_params::<ApplyWorkspaceEditParams>(key, method, params) else {
+               return responder.respond_error(INVALID_PARAMS, "Invalid applyEdit params");
+            };
+            let result = self.apply_workspace_edit(params.edit);
+            let response = ApplyWorkspaceEditResponse {
+               applied: result.applied,
+               failure_reason: result.failure_reason(),
+               failed_change: None,
+            };
+            responder.respond(json!(response));
+         }
+         request::ShowMessageRequest::METHOD => {
+            let Some(params) = parse_params::<ShowMessageRequestParams>(key, method, params) else {
+               return responder.respond_error(INVALID_PARAMS, "Invalid showMessageRequest params");
+            };
+            let id = self.next_message_request.fetch_add(1, Ordering::Relaxed);
+            let event = MessageRequestEvent {
+               id,
+               server: key.clone(),
+               kind: message_kind(params.typ).to_string(),
+               message: params.message,
+               actions: params
+                  .actions
+                  .unwrap_or_default()
+                  .into_iter()
+                  .map(|action| action.title)
+                  .collect(),
+            };
+            self
+               .message_requests
+               .lock()
+               .unwrap()
+               .insert(id, (key.clone(), responder));
+            let _ = self.app_handle.emit("lsp-show-message-request", &event);
+         }
+         request::ShowDocument::METHOD => {
+            let Some(params) = parse_params::<ShowDocumentParams>(key, method, params) else {
+               return responder.respond_error(INVALID_PARAMS, "Invalid showDocument params");
+            };
+            let event = ShowDocumentEvent {
+               server: key.clone(),
+               path: params
+                  .uri
+                  .to_file_path()
+                  .ok()
+                  .map(|path| path.to_string_lossy().to_string()),
+               uri: params.uri.to_string(),
+               external: params.external.unwrap_or(false),
+               take_focus: params.take_focus.unwrap_or(true),
+               selection: params.selection,
+            };
+            let success = self.app_handle.emit("lsp-show-document", &event).is_ok();
+            responder.respond(json!(ShowDocumentResult { success }));
+         }
+         // The editor re-queries hints and tokens for visible files when told to
+         request::InlayHintRefreshRequest::METHOD => {
+            let _ = self.app_handle.emit("lsp-inlay-hints-refresh", key);
+            responder.respond(Value::Null);
+         }
+         // Pulled diagnostics are stale, e.g. after a configuration or dependency change
+         request::WorkspaceDiagnosticRefresh::METHOD => {
+            responder.respond(Value::Null);
+            self.refresh_pulled_diagnostics(key);
+         }
+         request::SemanticTokensRefresh::METHOD => {
+            let _ = self.app_handle.emit("lsp-semantic-tokens-refresh", key);
+            responder.respond(Value::Null);
+         }
+         // Nothing to set up on our side for these, acknowledging is enough
+         request::RegisterCapability::METHOD
+         | request::UnregisterCapability::METHOD
+         | request::WorkDoneProgressCreate::METHOD
+         | request::InlineValueRefreshRequest::METHOD
+         | request::CodeLensRefresh::METHOD => responder.respond(Value::Null),
+         _ => {
+            log::debug!("Unhandled LSP request from '{}': {}", key.server, method);
+            responder.respond_error(METHOD_NOT_FOUND, &format!("Unhandled method {}", method));
+         }
+      }
+   }
+
+   /// Answers a pending `window/showMessageRequest` with the title of the chosen action, or with
+   /// no action when the user dismissed it.
+   pub fn respond_to_message_request(&self, id: u64, action: Option<String>) -> anyhow::Result<()> {
+      let (_, responder) = self
+         .message_requests
+         .lock()
+         .unwrap()
+         .remove(&id)
+         .ok_or_else(|| anyhow::anyhow!("No pending message request {}", id))?;
+
+      match action {
+         Some(title) => responder.respond(json!(MessageActionItem {
+            title,
+            properties: Default::default(),
+         })),
+         None => responder.respond(Value::Null),
+      }
+      Ok(())
+   }
+}
@@ -0,0 +1,251 @@
+use super::{LspManager, symbols::symbol_kind_name};
+use crate::lsp::{
+   client,
+   types::{CallHierarchyDirection, HierarchyNode, ServerKey, TypeHierarchyDirection},
+   workspace_edit,
+};
+use anyhow::Result;
+use lsp_types::*;
+use std::path::Path;
+
+impl LspManager {
+   /// Roots of the call hierarchy at a position: usually the function under the cursor. Each
+   /// node is expanded later with `call_hierarchy_calls`.
+   pub async fn prepare_call_hierarchy(
+      &self,
+      file_path: &str,
+      line: u32,
+      character: u32,
+   ) -> Result<Vec<HierarchyNode>> {
+      let params = CallHierarchyPrepareParams {
+         text_document_position_params: self.text_document_position(file_path, line, character)?,
+         work_done_progress_params: Default::default(),
+      };
+      let language_id = self.get_language_id_for_file(file_path);
+
+      for (key, client) in self.servers_for_language(Path::new(file_path), &language_id) {
+         if client
+            .capabilities()
+            .is_none_or(|caps| caps.call_hierarchy_provider.is_none())
+         {
+            continue;
+         }
+         match client.prepare_call_hierarchy(params.clone()).await {
+            Ok(Some(items)) if !items.is_empty() => {
+               return Ok(items
+                  .into_iter()
+                  .map(|item| call_node(key.clone(), item, Vec::new()))
+                  .collect());
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Prepare call hierarchy failed: {}", e),
+         }
+      }
+
+      Ok(Vec::new())
+   }
+
+   /// Children of a call hierarchy node: its callers or the functions it calls.
+   pub async fn call_hierarchy_calls(
+      &self,
+      server: &ServerKey,
+      item: CallHierarchyItem,
+      direction: CallHierarchyDirection,
+   ) -> Result<Vec<HierarchyNode>> {
+      let client = self.client_for_server(server)?;
+
+      let nodes = match direction {
+         CallHierarchyDirection::Incoming => client
+            .call_hierarchy_incoming_calls(CallHierarchyIncomingCallsParams {
+               item,
+               work_done_progress_params: Default::default(),
+               partial_result_params: Default::default(),
+            })
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|call| call_node(server.clone(), call.from, call.from_ranges))
+            .collect(),
+         CallHierarchyDirection::Outgoing => client
+            .call_hierarchy_outgoing_calls(CallHierarchyOutgoingCallsParams {
+               item,
+               work_done_progress_params: Default::default(),
+               partial_result_params: Default::default(),
+            })
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|call| call_node(server.clone(), call.to, call.from_ranges))
+            .collect(),
+      };
+      Ok(nodes)
+   }
+
+   /// Roots of the type hierarchy at a position. lsp-types doesn't parse
+   /// `typeHierarchyProvider`, so servers are asked in turn and those without support skipped.
+   pub async fn prepare_type_hierarchy(
+      &self,
+      file_path: &str,
+      line: u32,
+      character: u32,
+   ) -> Result<Vec<HierarchyNode>> {
+      let params = TypeHierarchyPrepareParams {
+         text_document_position_params: self.text_document_position(file_path, line, character)?,
+         work_done_progress_params: Default::default(),
+      };
+      let language_id = self.get_language_id_for_file(file_path);
+
+      for (key, client) in self.servers_for_language(Path::new(file_path), &language_id) {
+         match client.prepare_type_hierarchy(params.clone()).await {
+            Ok(Some(items)) if !items.is_empty() => {
+               return Ok(items
+                  .into_iter()
+                  .map(|item| type_node(key.clone(), item))
+                  .collect());
+            }
+            Ok(_) => {}
+            Err(e) if client::is_method_not_found(&e) => {}
+            Err(e) => log::warn!("Prepare type hierarchy failed: {}", e),
+         }
+      }
+
+      Ok(Vec::new())
+   }
+
+   /// Children of a type hierarchy node: its supertypes or subtypes.
+   pub async fn type_hierarchy_types(
+      &self,
+      server: &ServerKey,
+      item: TypeHierarchyItem,
+      direction: TypeHierarchyDirection,
+   ) -> Result<Vec<HierarchyNode>> {
+      let client = self.client_for_server(server)?;
+
+      let items = match direction {
+         TypeHierarchyDirection::Supertypes => {
+            client
+               .type_hierarchy_supertypes(TypeHierarchySupertypesParams {
+                  item,
+                  work_done_progress_params: Default::default(),
+                  partial_result_params: Default::default(),
+               })
+               .await?
+         }
+         TypeHierarchyDirection::Subtypes => {
+            client
+               .type_hierarchy_subtypes(TypeHierarchySubtypesParams {
+                  item,
+                  work_done_progress_params: Default::default(),
+                  partial_result_params: Default::default(),
+               })
+               .await?
+         }
+      };
+
+      Ok(items
+         .unwrap_or_default()
+         .into_iter()
+         .map(|item| type_node(server.clone(), item))
+         .collect())
+   }
+}
+
+fn call_node(server: ServerKey, item: CallHierarchyItem, call_ranges: Vec<Range>) -> HierarchyNode {
+   HierarchyNode {
+      server,
+      name: item.name.clone(),
+      kind: symbol_kind_name(item.kind).to_string(),
+      detail: item.detail.clone(),
+      path: workspace_edit::uri_to_display_path(&item.uri),
+      range: item.range,
+      selection_range: item.selection_range,
+      call_ranges,
+      item: serde_json::to_value(&item).unwrap_or_default(),
+   }
+}
+
+fn type_node(server: ServerKey, item: TypeHierarchyItem) -> HierarchyNode {
+   HierarchyNode {
+      server,
+      name: item.name.clone(),
+      kind: symbol_kind_name(item.kind).to_string(),
+      detail: item.detail.clone(),
+      path: workspace_edit::uri_to_display_path(&item.uri),
+      range: item.range,
+      selection_range: item.selection_range,
+      call_ranges: Vec::new(),
+      item: serde_json::to_value(&item).unwrap_or_default(),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   fn key() -> ServerKey {
+      ServerKey {
+         root: "/p".into(),
+         server: "rust-analyzer".to_string(),
+      }
+   }
+
+   fn range(line: u32) -> Range {
+      Range::new(Position::new(line, 0), Position::new(line, 4))
+   }
+
+   #[test]
+   fn test_call_node_keeps_item_for_expanding() {
+      let item = CallHierarchyItem {
+         name: "parse".to_string(),
+         kind: SymbolKind::FUNCTION,
+         tags: None,
+         detail: Some("fn parse(input: &str)".to_string()),
+         uri: Url::from_file_path("/p/src/lib.rs").unwrap(),
+         range: range(3),
+         selection_range: range(4),
+         data: Some(json!({ "id": 7 })),
+      };
+
+      let node = call_node(key(), item.clone(), vec![range(10), range(12)]);
+      assert_eq!(node.server, key());
+      assert_eq!(node.name, "parse");
+      assert_eq!(node.kind, "function");
+      assert_eq!(node.detail.as_deref(), Some("fn parse(input: &str)"));
+      assert_eq!(node.path, "/p/src/lib.rs");
+      assert_eq!(node.range, range(3));
+      assert_eq!(node.selection_range, range(4));
+      assert_eq!(node.call_ranges, vec![range(10), range(12)]);
+      // Sent back as is when the node is expanded, including the server's `data`
+      assert_eq!(
+         serde_json::from_value::<CallHierarchyItem>(node.item).unwrap(),
+         item
+      );
+   }
+
+   #[test]
+   fn test_type_node_has_no_call_ranges() {
+      let item = TypeHierarchyItem {
+         name: "Parser".to_string(),
+         kind: SymbolKind::STRUCT,
+         tags: None,
+         detail: None,
+         uri: Url::parse("jdt://contents/rt.jar/java/lang/Object.class").unwrap(),
+         range: range(1),
+         selection_range: range(1),
+         data: None,
+      };
+
+      let node = type_node(key(), item.clone());
+      assert_eq!(node.name, "Parser");
+      assert_eq!(node.kind, "struct");
+      assert_eq!(node.detail, None);
+      // Non-file URIs are shown as they are
+      assert_eq!(node.path, "jdt://contents/rt.jar/java/lang/Object.class");
+      assert!(node.call_ranges.is_empty());
+      assert_eq!(
+         serde_json::from_value::<TypeHierarchyItem>(node.item).unwrap(),
+         item
+      );
+   }
+}
@@ -0,0 +1,274 @@
+use super::LspManager;
+use crate::lsp::types::{
+   InlayHintItem, ParameterItem, ServerKey, SignatureHelpItem, SignatureItem,
+};
+use anyhow::Result;
+use futures_util::future::join_all;
+use lsp_types::*;
+use std::path::Path;
+
+impl LspManager {
+   /// Signature help from the first server with an answer. A retrigger passes back the help
+   /// shown last, so servers can keep the signature the user picked.
+   pub async fn signature_help(
+      &self,
+      file_path: &str,
+      position: Position,
+      trigger_kind: SignatureHelpTriggerKind,
+      trigger_character: Option<String>,
+      is_retrigger: bool,
+   ) -> Result<Option<SignatureHelpItem>> {
+      let clients: Vec<_> = self
+         .require_clients_for_file(file_path)?
+         .into_iter()
+         .filter(|client| {
+            client
+               .capabilities()
+               .is_some_and(|caps| caps.signature_help_provider.is_some())
+         })
+         .collect();
+
+      let active_signature_help = if is_retrigger {
+         match &*self.last_signature_help.lock().unwrap() {
+            Some((path, help)) if path == file_path => Some(help.clone()),
+            _ => None,
+         }
+      } else {
+         None
+      };
+      let params = SignatureHelpParams {
+         context: Some(SignatureHelpContext {
+            trigger_kind,
+            trigger_character,
+            is_retrigger,
+            active_signature_help,
+         }),
+         text_document_position_params: self.text_document_position(
+            file_path,
+            position.line,
+            position.character,
+         )?,
+         work_done_progress_params: Default::default(),
+      };
+
+      let mut help = None;
+      for client in clients {
+         match client.text_document_signature_help(params.clone()).await {
+            Ok(Some(result)) if !result.signatures.is_empty() => {
+               help = Some(result);
+               break;
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Signature help request failed: {}", e),
+         }
+      }
+
+      *self.last_signature_help.lock().unwrap() =
+         help.clone().map(|help| (file_path.to_string(), help));
+      Ok(help.map(to_signature_help_item))
+   }
+
+   /// Inlay hints in `range` from every server for the file that provides them.
+   pub async fn inlay_hints(&self, file_path: &str, range: Range) -> Result<Vec<InlayHintItem>> {
+      let path = Path::new(file_path);
+      let language_id = self.get_language_id_for_file(file_path);
+      let servers: Vec<_> = self
+         .servers_for_language(path, &language_id)
+         .into_iter()
+         .filter(|(_, client)| {
+            client
+               .capabilities()
+               .is_some_and(|caps| caps.inlay_hint_provider.is_some())
+         })
+         .collect();
+
+      let params = InlayHintParams {
+         text_document: TextDocumentIdentifier {
+            uri: Url::from_file_path(path).map_err(|_| anyhow::anyhow!("Invalid file path"))?,
+         },
+         range,
+         work_done_progress_params: Default::default(),
+      };
+
+      let responses = join_all(
+         servers
+            .iter()
+            .map(|(_, client)| client.text_document_inlay_hint(params.clone())),
+      )
+      .await;
+
+      let mut hints = Vec::new();
+      for ((key, _), response) in servers.iter().zip(responses) {
+         match response {
+            Ok(Some(list)) => hints.extend(
+               list
+                  .into_iter()
+                  .map(|hint| to_inlay_hint_item(key.clone(), hint)),
+            ),
+            Ok(None) => {}
+            Err(e) => log::warn!("Inlay hint request to '{}' failed: {}", key.server, e),
+         }
+      }
+      hints.sort_by_key(|hint| hint.position);
+      Ok(hints)
+   }
+
+   /// Fills in the tooltip of a hint, for servers that compute it lazily.
+   pub async fn resolve_inlay_hint(
+      &self,
+      server: &ServerKey,
+      hint: InlayHint,
+   ) -> Result<InlayHintItem> {
+      let client = self.client_for_server(server)?;
+      let supported = client.capabilities().is_some_and(|caps| {
+         matches!(
+            caps.inlay_hint_provider,
+            Some(OneOf::Right(InlayHintServerCapabilities::Options(
+               InlayHintOptions {
+                  resolve_provider: Some(true),
+                  ..
+               }
+            )))
+         )
+      });
+
+      let hint = if supported {
+         client.inlay_hint_resolve(hint).await?
+      } else {
+         hint
+      };
+      Ok(to_inlay_hint_item(server.clone(), hint))
+   }
+}
+
+fn documentation_text(documentation: Documentation) -> String {
+   match documentation {
+      Documentation::String(text) => text,
+      Documentation::MarkupContent(content) => content.value,
+   }
+}
+
+fn to_signature_help_item(help: SignatureHelp) -> SignatureHelpItem {
+   let signatures = help
+      .signatures
+      .into_iter()
+      .map(|signature| {
+         let parameters = signature
+            .parameters
+            .unwrap_or_default()
+            .into_iter()
+            .map(|parameter| {
+               let (label, offsets) = match parameter.label {
+                  ParameterLabel::Simple(label) => {
+                     let offsets = utf16_find(&signature.label, &label);
+                     (label, offsets)
+                  }
+                  ParameterLabel::LabelOffsets([start, end]) => (
+                     utf16_slice(&signature.label, start, end),
+                     Some((start, end)),
+                  ),
+               };
+               ParameterItem {
+                  label,
+                  start: offsets.map(|(start, _)| start),
+                  end: offsets.map(|(_, end)| end),
+                  documentation: parameter.documentation.map(documentation_text),
+               }
+            })
+            .collect();
+
+         SignatureItem {
+            label: signature.label,
+            documentation: signature.documentation.map(documentation_text),
+            parameters,
+            // A per-signature value overrides the one for the whole help
+            active_parameter: signature.active_parameter.or(help.active_parameter),
+         }
+      })
+      .collect();
+
+   SignatureHelpItem {
+      signatures,
+      active_signature: help.active_signature.unwrap_or(0),
+   }
+}
+
+fn to_inlay_hint_item(server: ServerKey, hint: InlayHint) -> InlayHintItem {
+   let text = match &hint.label {
+      InlayHintLabel::String(text) => text.clone(),
+      InlayHintLabel::LabelParts(parts) => parts.iter().map(|part| part.value.as_str()).collect(),
+   };
+   let kind = match hint.kind {
+      Some(InlayHintKind::TYPE) => Some("type".to_string()),
+      Some(InlayHintKind::PARAMETER) => Some("parameter".to_string()),
+      _ => None,
+   };
+   let tooltip = hint.tooltip.clone().map(|tooltip| match tooltip {
+      InlayHintTooltip::String(text) => text,
+      InlayHintTooltip::MarkupContent(content) => content.value,
+   });
+
+   InlayHintItem {
+      server,
+      position: hint.position,
+      text,
+      kind,
+      padding_left: hint.padding_left.unwrap_or(false),
+      padding_right: hint.padding_right.unwrap_or(false),
+      tooltip,
+      hint,
+   }
+}
+
+/// UTF-16 offsets of the first occurrence of `needle` in `haystack`.
+fn utf16_find(haystack: &str, needle: &str) -> Option<(u32, u32)> {
+   let byte_start = haystack.find(needle)?;
+   let start = haystack[..byte_start].encode_utf16().count() as u32;
+   Some((start, start + needle.encode_utf16().count() as u32))
+}
+
+fn utf16_slice(text: &str, start: u32, end: u32) -> String {
+   let units: Vec<u16> = text
+      .encode_utf16()
+      .skip(start as usize)
+      .take(end.saturating_sub(start) as usize)
+      .collect();
+   String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_signature_parameters_get_label_offsets() {
+      let help = SignatureHelp {
+         signatures: vec![SignatureInformation {
+            label: "fn greet(名前: &str, times: u32)".to_string(),
+            documentation: None,
+            parameters: Some(vec![
+               ParameterInformation {
+                  label: ParameterLabel::Simple("名前: &str".to_string()),
+                  documentation: None,
+               },
+               ParameterInformation {
+                  label: ParameterLabel::LabelOffsets([19, 29]),
+                  documentation: None,
+               },
+            ]),
+            active_parameter: None,
+         }],
+         active_signature: None,
+         active_parameter: Some(1),
+      };
+
+      let item = to_signature_help_item(help);
+      let parameters = &item.signatures[0].parameters;
+      assert_eq!(
+         (parameters[0].start, parameters[0].end),
+         (Some(9), Some(17))
+      );
+      assert_eq!(parameters[1].label, "times: u32");
+      assert_eq!(item.signatures[0].active_parameter, Some(1));
+   }
+}
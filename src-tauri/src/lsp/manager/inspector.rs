@@ -0,0 +1,77 @@
+use super::LspManager;
+use crate::lsp::{
+   trace::{MessageTrace, TraceEntry},
+   types::{ServerKey, TraceEvent},
+};
+use anyhow::{Context, Result};
+use lsp_types::*;
+use serde_json::json;
+use std::{path::Path, sync::Arc};
+use tauri::Emitter;
+
+impl LspManager {
+   /// The trace of `key`'s server, created the first time it starts and kept across restarts.
+   pub(super) fn trace_for(&self, key: &ServerKey) -> Arc<MessageTrace> {
+      self
+         .traces
+         .lock()
+         .unwrap()
+         .entry(key.clone())
+         .or_insert_with(|| Arc::new(MessageTrace::new()))
+         .clone()
+   }
+
+   fn existing_trace(&self, key: &ServerKey) -> Result<Arc<MessageTrace>> {
+      self
+         .traces
+         .lock()
+         .unwrap()
+         .get(key)
+         .cloned()
+         .with_context(|| format!("No trace for LSP '{}' in {:?}", key.server, key.root))
+   }
+
+   /// Recorded messages of a server, from `since` on when given.
+   pub fn trace_entries(&self, key: &ServerKey, since: Option<u64>) -> Result<Vec<TraceEntry>> {
+      Ok(self.existing_trace(key)?.entries(since))
+   }
+
+   pub fn clear_trace(&self, key: &ServerKey) -> Result<()> {
+      self.existing_trace(key)?.clear();
+      Ok(())
+   }
+
+   /// Streams new entries of a server's trace as `lsp-trace` events while enabled.
+   pub fn watch_trace(&self, key: &ServerKey, enabled: bool) {
+      let listener = enabled.then(|| {
+         let app_handle = self.app_handle.clone();
+         let server = key.clone();
+         Box::new(move |entry: &TraceEntry| {
+            let event = TraceEvent {
+               server: server.clone(),
+               entry: entry.clone(),
+            };
+            let _ = app_handle.emit("lsp-trace", &event);
+         }) as _
+      });
+      self.trace_for(key).set_listener(listener);
+   }
+
+   /// Writes a server's trace to `path` as JSON, for attaching to bug reports.
+   pub fn export_trace(&self, key: &ServerKey, path: &Path) -> Result<()> {
+      let export = json!({
+         "server": key,
+         "entries": self.existing_trace(key)?.entries(None),
+      });
+      let text = serde_json::to_string_pretty(&export)?;
+      std::fs::write(path, text).with_context(|| format!("Failed to write {:?}", path))
+   }
+
+   /// Asks a running server to log its own activity through `$/logTrace`, which then shows up
+   /// in the trace.
+   pub fn set_server_trace(&self, key: &ServerKey, value: TraceValue) -> Result<()> {
+      self
+         .client_for_server(key)?
+         .notify::<notification::SetTrace>(SetTraceParams { value })
+   }
+}
@@ -0,0 +1,425 @@
+use super::LspManager;
+use crate::lsp::{client::LspClient, types::ServerKey};
+use futures_util::future::join_all;
+use lsp_types::*;
+use std::{
+   collections::HashMap,
+   path::{Path, PathBuf},
+   time::Duration,
+};
+use tauri::Manager as TauriManager;
+
+/// How long a document has to stay unchanged before its diagnostics are pulled, so typing
+/// doesn't send a request per keystroke.
+const PULL_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// The pull options of a server, `None` if it only pushes diagnostics.
+fn diagnostic_options(client: &LspClient) -> Option<DiagnosticOptions> {
+   match client.capabilities()?.diagnostic_provider? {
+      DiagnosticServerCapabilities::Options(options) => Some(options),
+      DiagnosticServerCapabilities::RegistrationOptions(options) => {
+         Some(options.diagnostic_options)
+      }
+   }
+}
+
+impl LspManager {
+   /// Pulls diagnostics for a document from the servers that support pulling, once it has
+   /// settled. Servers that only push keep doing so on their own.
+   pub(super) fn schedule_document_diagnostics(&self, path: PathBuf) {
+      let Some(version) = self
+         .documents
+         .lock()
+         .unwrap()
+         .get(&path)
+         .map(|document| document.version)
+      else {
+         return;
+      };
+
+      let app_handle = self.app_handle.clone();
+      tauri::async_runtime::spawn(async move {
+         tokio::time::sleep(PULL_DEBOUNCE).await;
+         let Some(manager) = app_handle.try_state::<LspManager>() else {
+            return;
+         };
+         // A later change scheduled its own pull
+         if manager.document_version(&path) != Some(version) {
+            return;
+         }
+
+         let language_id = manager.get_language_id_for_file(&path.to_string_lossy());
+         let pulls = manager
+            .servers_for_language(&path, &language_id)
+            .into_iter()
+            .filter_map(|(key, client)| {
+               diagnostic_options(&client).map(|options| (key, client, options))
+            })
+            .map(|(key, client, options)| {
+               let manager = &manager;
+               let path = &path;
+               async move { manager.pull_document(&key, &client, &options, path).await }
+            });
+         join_all(pulls).await;
+      });
+   }
+
+   /// Re-pulls everything `key`'s server reported, after it starts or asks for a refresh:
+   /// open documents one by one and, if it supports that, the whole workspace.
+   pub(super) fn refresh_pulled_diagnostics(&self, key: &ServerKey) {
+      let app_handle = self.app_handle.clone();
+      let key = key.clone();
+      tauri::async_runtime::spawn(async move {
+         let Some(manager) = app_handle.try_state::<LspManager>() else {
+            return;
+         };
+         let Ok(client) = manager.client_for_server(&key) else {
+            return;
+         };
+         let Some(options) = diagnostic_options(&client) else {
+            return;
+         };
+
+         let paths: Vec<(PathBuf, String)> = manager
+            .documents
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(path, _)| path.starts_with(&key.root))
+            .map(|(path, document)| (path.clone(), document.language_id.clone()))
+            .collect();
+         let handles = |language_id: &str| {
+            manager
+               .servers
+               .lock()
+               .unwrap()
+               .get(&key)
+               .is_some_and(|instance| instance.config.handles_language(language_id))
+         };
+         for (path, language_id) in paths {
+            if handles(&language_id) {
+               manager.pull_document(&key, &client, &options, &path).await;
+            }
+         }
+
+         if options.workspace_diagnostics {
+            manager
+               .pull_server_workspace_diagnostics(&key, &client, &options)
+               .await;
+         }
+      });
+   }
+
+   /// Pulls project-wide diagnostics from every server in `workspace` that supports it, so
+   /// the problems list covers files that aren't open.
+   pub async fn pull_workspace_diagnostics(&self, workspace: Option<&Path>) {
+      let servers: Vec<_> = self
+         .servers
+         .lock()
+         .unwrap()
+         .iter()
+         .filter(|(key, _)| {
+            workspace.is_none_or(|w| key.root.starts_with(w) || w.starts_with(&key.root))
+         })
+         .map(|(key, instance)| (key.clone(), instance.client.clone()))
+         .collect();
+
+      let pulls = servers.iter().filter_map(|(key, client)| {
+         let options = diagnostic_options(client).filter(|o| o.workspace_diagnostics)?;
+         Some(async move {
+            self
+               .pull_server_workspace_diagnostics(key, client, &options)
+               .await
+         })
+      });
+      join_all(pulls).await;
+   }
+
+   async fn pull_document(
+      &self,
+      key: &ServerKey,
+      client: &LspClient,
+      options: &DiagnosticOptions,
+      path: &Path,
+   ) {
+      let Ok(uri) = Url::from_file_path(path) else {
+         return;
+      };
+      let Some(version) = self.document_version(path) else {
+         return;
+      };
+      let previous_result_id = self.previous_result_id(key, &uri);
+
+      let params = DocumentDiagnosticParams {
+         text_document: TextDocumentIdentifier { uri: uri.clone() },
+         identifier: options.identifier.clone(),
+         previous_result_id,
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
+      let result = match client.text_document_diagnostic(params).await {
+         Ok(result) => result,
+         Err(e) => {
+            log::debug!("Diagnostic pull from '{}' failed: {}", key.server, e);
+            return;
+         }
+      };
+
+      // Diagnostics for an older version would point at the wrong places
+      if self.document_version(path) != Some(version) {
+         return;
+      }
+
+      let related = match result {
+         DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) => {
+            self.apply_report(
+               key,
+               &uri,
+               DocumentDiagnosticReportKind::Full(report.full_document_diagnostic_report),
+            );
+            report.related_documents
+         }
+         DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(report)) => {
+            self.apply_report(
+               key,
+               &uri,
+               DocumentDiagnosticReportKind::Unchanged(report.unchanged_document_diagnostic_report),
+            );
+            report.related_documents
+         }
+         DocumentDiagnosticReportResult::Partial(partial) => partial.related_documents,
+      };
+      for (uri, report) in related.unwrap_or_default() {
+         self.apply_report(key, &uri, report);
+      }
+   }
+
+   async fn pull_server_workspace_diagnostics(
+      &self,
+      key: &ServerKey,
+      client: &LspClient,
+      options: &DiagnosticOptions,
+   ) {
+      let previous_result_ids = self
+         .diagnostic_result_ids
+         .lock()
+         .unwrap()
+         .get(key)
+         .map(|ids| {
+            ids.iter()
+               .map(|(uri, value)| PreviousResultId {
+                  uri: uri.clone(),
+                  value: value.clone(),
+               })
+               .collect()
+         })
+         .unwrap_or_default();
+
+      let params = WorkspaceDiagnosticParams {
+         identifier: options.identifier.clone(),
+         previous_result_ids,
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
+      let items = match client.workspace_diagnostic(params).await {
+         Ok(WorkspaceDiagnosticReportResult::Report(report)) => report.items,
+         Ok(WorkspaceDiagnosticReportResult::Partial(partial)) => partial.items,
+         Err(e) => {
+            log::debug!(
+               "Workspace diagnostic pull from '{}' failed: {}",
+               key.server,
+               e
+            );
+            return;
+         }
+      };
+
+      let current_version = |uri: &Url| {
+         uri.to_file_path()
+            .ok()
+            .and_then(|path| self.document_version(&path))
+      };
+      for item in items {
+         if let Some((uri, report)) = current_report(item, current_version) {
+            self.apply_report(key, &uri, report);
+         }
+      }
+   }
+
+   /// Records a report's result id and, for full reports, replaces the server's diagnostics
+   /// for the file. Unchanged reports keep what was there.
+   fn apply_report(&self, key: &ServerKey, uri: &Url, report: DocumentDiagnosticReportKind) {
+      let items = {
+         let mut result_ids = self.diagnostic_result_ids.lock().unwrap();
+         record_result_id(result_ids.entry(key.clone()).or_default(), uri, report)
+      };
+
+      if let Some(items) = items
+         && let Some((path, items)) = self.diagnostics.update(&key.server, uri, items)
+      {
+         Self::emit_diagnostics_changed(&self.app_handle, &path, items);
+      }
+   }
+
+   fn previous_result_id(&self, key: &ServerKey, uri: &Url) -> Option<String> {
+      self
+         .diagnostic_result_ids
+         .lock()
+         .unwrap()
+         .get(key)
+         .and_then(|ids| ids.get(uri))
+         .cloned()
+   }
+
+   fn document_version(&self, path: &Path) -> Option<i32> {
+      self
+         .documents
+         .lock()
+         .unwrap()
+         .get(path)
+         .map(|document| document.version)
+   }
+
+   /// Forgets result ids of a server that stopped; a new instance starts from scratch.
+   pub(super) fn clear_diagnostic_result_ids(&self, key: &ServerKey) {
+      self.diagnostic_result_ids.lock().unwrap().remove(key);
+   }
+}
+
+/// Stores the result id a report carries for `uri`, forgetting the old one if it has none.
+/// Returns the diagnostics of full reports.
+fn record_result_id(
+   ids: &mut HashMap<Url, String>,
+   uri: &Url,
+   report: DocumentDiagnosticReportKind,
+) -> Option<Vec<Diagnostic>> {
+   let (result_id, items) = match report {
+      DocumentDiagnosticReportKind::Full(report) => (report.result_id, Some(report.items)),
+      DocumentDiagnosticReportKind::Unchanged(report) => (Some(report.result_id), None),
+   };
+   match result_id {
+      Some(result_id) => ids.insert(uri.clone(), result_id),
+      None => ids.remove(uri),
+   };
+   items
+}
+
+/// A workspace report as a document report, or `None` if it's for an older version of a
+/// document that's open. Open documents are pulled individually, so those are skipped.
+fn current_report(
+   item: WorkspaceDocumentDiagnosticReport,
+   current_version: impl Fn(&Url) -> Option<i32>,
+) -> Option<(Url, DocumentDiagnosticReportKind)> {
+   let (uri, version, report) = match item {
+      WorkspaceDocumentDiagnosticReport::Full(report) => (
+         report.uri,
+         report.version,
+         DocumentDiagnosticReportKind::Full(report.full_document_diagnostic_report),
+      ),
+      WorkspaceDocumentDiagnosticReport::Unchanged(report) => (
+         report.uri,
+         report.version,
+         DocumentDiagnosticReportKind::Unchanged(report.unchanged_document_diagnostic_report),
+      ),
+   };
+
+   if let (Some(reported), Some(current)) = (version, current_version(&uri))
+      && reported < i64::from(current)
+   {
+      return None;
+   }
+   Some((uri, report))
+}
+
+/// Result ids from the last report of each file, per server.
+pub(super) type DiagnosticResultIds = HashMap<ServerKey, HashMap<Url, String>>;
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn uri(name: &str) -> Url {
+      Url::parse(&format!("file:///p/{}", name)).unwrap()
+   }
+
+   fn diagnostic(message: &str) -> Diagnostic {
+      Diagnostic {
+         message: message.to_string(),
+         ..Default::default()
+      }
+   }
+
+   fn full(result_id: Option<&str>, items: Vec<Diagnostic>) -> FullDocumentDiagnosticReport {
+      FullDocumentDiagnosticReport {
+         result_id: result_id.map(str::to_string),
+         items,
+      }
+   }
+
+   fn unchanged(result_id: &str) -> UnchangedDocumentDiagnosticReport {
+      UnchangedDocumentDiagnosticReport {
+         result_id: result_id.to_string(),
+      }
+   }
+
+   #[test]
+   fn test_record_result_id_tracks_the_latest_report() {
+      let mut ids = HashMap::new();
+      let main = uri("main.rs");
+
+      let items = record_result_id(
+         &mut ids,
+         &main,
+         DocumentDiagnosticReportKind::Full(full(Some("1"), vec![diagnostic("unused")])),
+      );
+      assert_eq!(items, Some(vec![diagnostic("unused")]));
+      assert_eq!(ids.get(&main).map(String::as_str), Some("1"));
+
+      // Unchanged reports keep the diagnostics but may move the id on
+      let items = record_result_id(
+         &mut ids,
+         &main,
+         DocumentDiagnosticReportKind::Unchanged(unchanged("2")),
+      );
+      assert_eq!(items, None);
+      assert_eq!(ids.get(&main).map(String::as_str), Some("2"));
+
+      // A full report without an id leaves nothing to send back next time
+      let items = record_result_id(
+         &mut ids,
+         &main,
+         DocumentDiagnosticReportKind::Full(full(None, Vec::new())),
+      );
+      assert_eq!(items, Some(Vec::new()));
+      assert!(ids.is_empty());
+   }
+
+   #[test]
+   fn test_current_report_skips_stale_versions_of_open_documents() {
+      let open = |uri: &Url| (*uri == self::uri("open.rs")).then_some(5);
+      let report = |name: &str, version: Option<i64>| {
+         WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+            uri: uri(name),
+            version,
+            full_document_diagnostic_report: full(Some("1"), vec![diagnostic("unused")]),
+         })
+      };
+
+      assert!(current_report(report("open.rs", Some(4)), open).is_none());
+      assert!(current_report(report("open.rs", Some(5)), open).is_some());
+      assert!(current_report(report("open.rs", None), open).is_some());
+      assert!(current_report(report("closed.rs", Some(1)), open).is_some());
+
+      let stale =
+         WorkspaceDocumentDiagnosticReport::Unchanged(WorkspaceUnchangedDocumentDiagnosticReport {
+            uri: uri("open.rs"),
+            version: Some(2),
+            unchanged_document_diagnostic_report: unchanged("1"),
+         });
+      assert!(current_report(stale, open).is_none());
+
+      let (reported, kind) = current_report(report("closed.rs", None), open).unwrap();
+      assert_eq!(reported, uri("closed.rs"));
+      assert!(matches!(kind, DocumentDiagnosticReportKind::Full(_)));
+   }
+}
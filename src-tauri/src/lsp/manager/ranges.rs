@@ -0,0 +1,257 @@
+use super::LspManager;
+use crate::lsp::{
+   client::LspClient,
+   types::{DocumentHighlightItem, DocumentLinkItem, FoldingRangeItem, ServerKey},
+   workspace_edit,
+};
+use anyhow::Result;
+use lsp_types::*;
+use std::path::Path;
+
+impl LspManager {
+   /// Servers for a file that pass `supports`, in registry order.
+   fn servers_supporting(
+      &self,
+      file_path: &str,
+      supports: impl Fn(&ServerCapabilities) -> bool,
+   ) -> Vec<(ServerKey, LspClient)> {
+      let language_id = self.get_language_id_for_file(file_path);
+      self
+         .servers_for_language(Path::new(file_path), &language_id)
+         .into_iter()
+         .filter(|(_, client)| client.capabilities().is_some_and(|caps| supports(&caps)))
+         .collect()
+   }
+
+   /// Other occurrences of the symbol at a position in the same file, for same-symbol
+   /// highlighting.
+   pub async fn document_highlights(
+      &self,
+      file_path: &str,
+      line: u32,
+      character: u32,
+   ) -> Result<Vec<DocumentHighlightItem>> {
+      let params = DocumentHighlightParams {
+         text_document_position_params: self.text_document_position(file_path, line, character)?,
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
+
+      for (_, client) in
+         self.servers_supporting(file_path, |caps| caps.document_highlight_provider.is_some())
+      {
+         match client
+            .text_document_document_highlight(params.clone())
+            .await
+         {
+            Ok(Some(highlights)) if !highlights.is_empty() => {
+               return Ok(highlights
+                  .into_iter()
+                  .map(|highlight| DocumentHighlightItem {
+                     range: highlight.range,
+                     kind: match highlight.kind {
+                        Some(DocumentHighlightKind::READ) => "read",
+                        Some(DocumentHighlightKind::WRITE) => "write",
+                        _ => "text",
+                     }
+                     .to_string(),
+                  })
+                  .collect());
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Document highlight request failed: {}", e),
+         }
+      }
+
+      Ok(Vec::new())
+   }
+
+   /// Links in a file, such as import paths, from every server that provides them. Targets
+   /// may be missing until the link is resolved.
+   pub async fn document_links(&self, file_path: &str) -> Result<Vec<DocumentLinkItem>> {
+      let params = DocumentLinkParams {
+         text_document: TextDocumentIdentifier {
+            uri: Url::from_file_path(file_path)
+               .map_err(|_| anyhow::anyhow!("Invalid file path"))?,
+         },
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
+
+      let mut links = Vec::new();
+      for (key, client) in
+         self.servers_supporting(file_path, |caps| caps.document_link_provider.is_some())
+      {
+         match client.text_document_document_link(params.clone()).await {
+            Ok(Some(response)) => links.extend(
+               response
+                  .into_iter()
+                  .map(|link| to_document_link_item(key.clone(), link)),
+            ),
+            Ok(None) => {}
+            Err(e) => log::warn!("Document link request to '{}' failed: {}", key.server, e),
+         }
+      }
+      links.sort_by_key(|link| link.range.start);
+      Ok(links)
+   }
+
+   /// Fills in the target of a link, for servers that compute it lazily.
+   pub async fn resolve_document_link(
+      &self,
+      server: &ServerKey,
+      link: DocumentLink,
+   ) -> Result<DocumentLinkItem> {
+      let client = self.client_for_server(server)?;
+      let supported = client.capabilities().is_some_and(|caps| {
+         caps
+            .document_link_provider
+            .is_some_and(|options| options.resolve_provider == Some(true))
+      });
+
+      let link = if supported && link.target.is_none() {
+         client.document_link_resolve(link).await?
+      } else {
+         link
+      };
+      Ok(to_document_link_item(server.clone(), link))
+   }
+
+   /// Foldable regions of a file from the first server that provides them, by start line.
+   pub async fn folding_ranges(&self, file_path: &str) -> Result<Vec<FoldingRangeItem>> {
+      let params = FoldingRangeParams {
+         text_document: TextDocumentIdentifier {
+            uri: Url::from_file_path(file_path)
+               .map_err(|_| anyhow::anyhow!("Invalid file path"))?,
+         },
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
+
+      for (_, client) in
+         self.servers_supporting(file_path, |caps| caps.folding_range_provider.is_some())
+      {
+         match client.text_document_folding_range(params.clone()).await {
+            Ok(Some(ranges)) if !ranges.is_empty() => {
+               let mut items: Vec<FoldingRangeItem> = ranges
+                  .into_iter()
+                  // Single-line ranges have nothing to fold
+                  .filter(|range| range.end_line > range.start_line)
+                  .map(|range| FoldingRangeItem {
+                     start_line: range.start_line,
+                     start_character: range.start_character,
+                     end_line: range.end_line,
+                     end_character: range.end_character,
+                     kind: range.kind.map(|kind| {
+                        match kind {
+                           FoldingRangeKind::Comment => "comment",
+                           FoldingRangeKind::Imports => "imports",
+                           FoldingRangeKind::Region => "region",
+                        }
+                        .to_string()
+                     }),
+                     collapsed_text: range.collapsed_text,
+                  })
+                  .collect();
+               items.sort_by_key(|item| (item.start_line, std::cmp::Reverse(item.end_line)));
+               return Ok(items);
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Folding range request failed: {}", e),
+         }
+      }
+
+      Ok(Vec::new())
+   }
+
+   /// For each position, the ranges smart expand-selection steps through, innermost first.
+   pub async fn selection_ranges(
+      &self,
+      file_path: &str,
+      positions: Vec<Position>,
+   ) -> Result<Vec<Vec<Range>>> {
+      let params = SelectionRangeParams {
+         text_document: TextDocumentIdentifier {
+            uri: Url::from_file_path(file_path)
+               .map_err(|_| anyhow::anyhow!("Invalid file path"))?,
+         },
+         positions,
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
+
+      for (_, client) in
+         self.servers_supporting(file_path, |caps| caps.selection_range_provider.is_some())
+      {
+         match client.text_document_selection_range(params.clone()).await {
+            Ok(Some(ranges)) if !ranges.is_empty() => {
+               return Ok(ranges.into_iter().map(flatten_selection_range).collect());
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Selection range request failed: {}", e),
+         }
+      }
+
+      Ok(Vec::new())
+   }
+}
+
+fn to_document_link_item(server: ServerKey, link: DocumentLink) -> DocumentLinkItem {
+   DocumentLinkItem {
+      server,
+      range: link.range,
+      target: link
+         .target
+         .as_ref()
+         .map(workspace_edit::uri_to_display_path),
+      tooltip: link.tooltip.clone(),
+      link,
+   }
+}
+
+/// Unrolls the parent chain, skipping parents that don't grow the selection.
+fn flatten_selection_range(selection: SelectionRange) -> Vec<Range> {
+   let mut ranges: Vec<Range> = Vec::new();
+   let mut current = Some(Box::new(selection));
+   while let Some(selection) = current {
+      if ranges.last() != Some(&selection.range) {
+         ranges.push(selection.range);
+      }
+      current = selection.parent;
+   }
+   ranges
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+      Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+   }
+
+   #[test]
+   fn test_flatten_selection_range() {
+      let chain = [
+         range((1, 8), (1, 12)),
+         range((1, 8), (1, 12)),
+         range((1, 4), (1, 20)),
+         range((0, 0), (3, 1)),
+      ];
+      let selection = chain
+         .iter()
+         .rev()
+         .fold(None, |parent, range| {
+            Some(Box::new(SelectionRange {
+               range: *range,
+               parent,
+            }))
+         })
+         .unwrap();
+
+      assert_eq!(
+         flatten_selection_range(*selection),
+         vec![chain[0], chain[2], chain[3]]
+      );
+   }
+}
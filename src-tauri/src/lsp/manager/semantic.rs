@@ -0,0 +1,169 @@
+use super::LspManager;
+use crate::lsp::{
+   client::LspClient,
+   semantic_tokens::{self, SemanticTokenSpan},
+   types::ServerKey,
+};
+use anyhow::Result;
+use lsp_types::*;
+use std::path::{Path, PathBuf};
+
+/// The last tokens a server sent for a file, kept so the next request can be a delta.
+#[derive(Clone)]
+pub(super) struct CachedSemanticTokens {
+   result_id: Option<String>,
+   data: Vec<u32>,
+}
+
+/// What a server's `semanticTokensProvider` allows.
+fn semantic_tokens_support(client: &LspClient) -> Option<(SemanticTokensLegend, bool)> {
+   let provider = client.capabilities()?.semantic_tokens_provider?;
+   let (legend, full) = match provider {
+      SemanticTokensServerCapabilities::SemanticTokensOptions(options) => {
+         (options.legend, options.full)
+      }
+      SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(options) => (
+         options.semantic_tokens_options.legend,
+         options.semantic_tokens_options.full,
+      ),
+   };
+
+   match full? {
+      SemanticTokensFullOptions::Bool(false) => None,
+      SemanticTokensFullOptions::Bool(true) => Some((legend, false)),
+      SemanticTokensFullOptions::Delta { delta } => Some((legend, delta.unwrap_or(false))),
+   }
+}
+
+impl LspManager {
+   /// Semantic tokens for an open document from the first server that provides them, or
+   /// `None` when no server does or `content` isn't what servers were last sent, in which case
+   /// the offsets wouldn't line up.
+   pub async fn semantic_tokens(
+      &self,
+      file_path: &str,
+      content: &str,
+   ) -> Result<Option<Vec<SemanticTokenSpan>>> {
+      let path = PathBuf::from(file_path);
+      let (uri, language_id, version) = {
+         let documents = self.documents.lock().unwrap();
+         match documents.get(&path) {
+            Some(document) if document.text == content => (
+               document.uri.clone(),
+               document.language_id.clone(),
+               document.version,
+            ),
+            _ => return Ok(None),
+         }
+      };
+
+      let Some((key, client, legend, delta)) = self
+         .servers_for_language(&path, &language_id)
+         .into_iter()
+         .find_map(|(key, client)| {
+            semantic_tokens_support(&client).map(|(legend, delta)| (key, client, legend, delta))
+         })
+      else {
+         return Ok(None);
+      };
+
+      let cache_key = (key.clone(), path.clone());
+      let cached = self.semantic_cache.lock().unwrap().get(&cache_key).cloned();
+      let tokens = self
+         .request_semantic_tokens(&client, uri, cached.filter(|_| delta))
+         .await?;
+
+      // Edits that arrived meanwhile make the answer stale
+      let current = self
+         .documents
+         .lock()
+         .unwrap()
+         .get(&path)
+         .map(|document| document.version);
+      if current != Some(version) {
+         return Ok(None);
+      }
+
+      let spans = semantic_tokens::decode(content, &tokens.data, &legend);
+      self
+         .semantic_cache
+         .lock()
+         .unwrap()
+         .insert(cache_key, tokens);
+      Ok(Some(spans))
+   }
+
+   /// Asks for a delta against `previous` when there is one, falling back to the full tokens
+   /// if the server refuses or the delta doesn't apply.
+   async fn request_semantic_tokens(
+      &self,
+      client: &LspClient,
+      uri: Url,
+      previous: Option<CachedSemanticTokens>,
+   ) -> Result<CachedSemanticTokens> {
+      let text_document = TextDocumentIdentifier { uri };
+
+      if let Some(mut previous) = previous
+         && let Some(previous_result_id) = previous.result_id.clone()
+      {
+         let params = SemanticTokensDeltaParams {
+            text_document: text_document.clone(),
+            previous_result_id,
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+         };
+         match client.semantic_tokens_full_delta(params).await {
+            Ok(Some(SemanticTokensFullDeltaResult::Tokens(tokens))) => {
+               return Ok(CachedSemanticTokens {
+                  result_id: tokens.result_id,
+                  data: semantic_tokens::flatten(&tokens.data),
+               });
+            }
+            Ok(Some(SemanticTokensFullDeltaResult::TokensDelta(delta))) => {
+               if semantic_tokens::apply_edits(&mut previous.data, &delta.edits).is_ok() {
+                  previous.result_id = delta.result_id;
+                  return Ok(previous);
+               }
+            }
+            Ok(Some(SemanticTokensFullDeltaResult::PartialTokensDelta { edits })) => {
+               if semantic_tokens::apply_edits(&mut previous.data, &edits).is_ok() {
+                  return Ok(previous);
+               }
+            }
+            Ok(None) => {}
+            Err(e) => log::debug!("Semantic tokens delta failed, requesting full: {}", e),
+         }
+      }
+
+      let params = SemanticTokensParams {
+         text_document,
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
+      Ok(match client.semantic_tokens_full(params).await? {
+         Some(SemanticTokensResult::Tokens(tokens)) => CachedSemanticTokens {
+            result_id: tokens.result_id,
+            data: semantic_tokens::flatten(&tokens.data),
+         },
+         Some(SemanticTokensResult::Partial(partial)) => CachedSemanticTokens {
+            result_id: None,
+            data: semantic_tokens::flatten(&partial.data),
+         },
+         None => CachedSemanticTokens {
+            result_id: None,
+            data: Vec::new(),
+         },
+      })
+   }
+
+   /// Forgets cached tokens for a closed file, or for everything a stopped server sent.
+   pub(super) fn clear_semantic_cache(&self, path: Option<&Path>, server: Option<&ServerKey>) {
+      self
+         .semantic_cache
+         .lock()
+         .unwrap()
+         .retain(|(key, file), _| {
+            !(path.is_none_or(|p| p == file) && server.is_none_or(|s| s == key))
+         });
+   }
+}
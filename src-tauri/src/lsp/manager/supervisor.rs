@@ -0,0 +1,212 @@
+use super::{LspManager, ServerInstance};
+use crate::lsp::{
+   client::ExitHandler,
+   config::LspServerConfig,
+   types::{ServerKey, ServerStatus},
+};
+use anyhow::{Context, Result};
+use std::time::Duration;
+use tauri::{AppHandle, Manager as TauriManager};
+
+/// Delay before the first restart, doubled for every further crash in a row.
+const RESTART_BASE_DELAY: Duration = Duration::from_secs(1);
+const RESTART_MAX_DELAY: Duration = Duration::from_secs(30);
+/// Crashes in a row after which a server is given up on until restarted by hand.
+const MAX_CRASHES: u32 = 5;
+/// A server that stayed up this long has its crash count reset.
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+/// How long a server gets to exit on its own after `exit` before it's killed.
+const EXIT_GRACE: Duration = Duration::from_millis(500);
+
+/// Builds the callback the client runs when the server's output closes. Instance `id` is
+/// compared against the running one, so servers stopped on purpose are left alone.
+pub(super) fn exit_handler(app_handle: AppHandle, key: ServerKey, id: u64) -> ExitHandler {
+   Box::new(move || {
+      if let Some(manager) = app_handle.try_state::<LspManager>() {
+         manager.handle_server_exit(&key, id);
+      }
+   })
+}
+
+/// Crashes in a row including the one that just happened, starting over when the server had been
+/// up long enough to count as stable.
+fn crash_count(previous: u32, uptime: Duration) -> u32 {
+   if uptime >= STABLE_UPTIME {
+      1
+   } else {
+      previous + 1
+   }
+}
+
+fn restart_delay(crashes: u32) -> Duration {
+   RESTART_BASE_DELAY
+      .saturating_mul(2u32.saturating_pow(crashes.saturating_sub(1)))
+      .min(RESTART_MAX_DELAY)
+}
+
+impl LspManager {
+   fn handle_server_exit(&self, key: &ServerKey, id: u64) {
+      let instance = {
+         let mut servers = self.servers.lock().unwrap();
+         match servers.get(key) {
+            Some(instance) if instance.id == id => servers.remove(key),
+            _ => None,
+         }
+      };
+      let Some(mut instance) = instance else {
+         return;
+      };
+
+      let _ = instance.child.kill();
+      let exit_status = instance.child.wait().ok();
+      log::error!(
+         "LSP '{}' for {:?} exited unexpectedly ({:?})",
+         key.server,
+         key.root,
+         exit_status
+      );
+      self.clear_server_diagnostics(key);
+      self.clear_semantic_cache(None, Some(key));
+      self.clear_diagnostic_result_ids(key);
+
+      let crashes = {
+         let mut crash_counts = self.crash_counts.lock().unwrap();
+         let count = crash_counts.entry(key.clone()).or_default();
+         *count = crash_count(*count, instance.started_at.elapsed());
+         *count
+      };
+
+      if crashes >= MAX_CRASHES {
+         self.failed_servers.lock().unwrap().insert(key.clone());
+         self.emit_status(
+            key,
+            ServerStatus::Failed,
+            Some(format!("Crashed {} times in a row", crashes)),
+         );
+         return;
+      }
+
+      let delay = restart_delay(crashes);
+      self.emit_status(
+         key,
+         ServerStatus::Crashed,
+         Some(format!("Restarting in {}s", delay.as_secs())),
+      );
+
+      let app_handle = self.app_handle.clone();
+      let key = key.clone();
+      let config = instance.config;
+      tauri::async_runtime::spawn(async move {
+         tokio::time::sleep(delay).await;
+         if let Some(manager) = app_handle.try_state::<LspManager>() {
+            manager.restart_after_crash(key, config).await;
+         }
+      });
+   }
+
+   async fn restart_after_crash(&self, key: ServerKey, config: LspServerConfig) {
+      // Answers to the crashed instance's message requests have nowhere to go
+      self
+         .message_requests
+         .lock()
+         .unwrap()
+         .retain(|_, (server, _)| *server != key);
+
+      let start_lock = self.start_lock(&key);
+      let _guard = start_lock.lock().await;
+
+      // Opening a file during the backoff may have started it already
+      if self.servers.lock().unwrap().contains_key(&key) {
+         return;
+      }
+
+      // Without open documents there's nothing to serve; the next open starts it lazily
+      let has_documents = self.documents.lock().unwrap().iter().any(|(path, doc)| {
+         path.starts_with(&key.root) && config.handles_language(&doc.language_id)
+      });
+      if !has_documents {
+         self.emit_status(&key, ServerStatus::Stopped, None);
+         return;
+      }
+
+      if self.start_server(key.clone(), config).await.is_err() {
+         self.failed_servers.lock().unwrap().insert(key);
+      }
+   }
+
+   /// Stops a server if it's running and starts it again with a freshly read config, clearing
+   /// any earlier failures. Open documents are re-sent as part of the start.
+   pub async fn restart_server(&self, key: &ServerKey) -> Result<()> {
+      let start_lock = self.start_lock(key);
+      let _guard = start_lock.lock().await;
+
+      let instance = self.servers.lock().unwrap().remove(key);
+      if let Some(instance) = instance {
+         self.stop_instance(key, instance).await;
+      }
+      self.failed_servers.lock().unwrap().remove(key);
+      self.crash_counts.lock().unwrap().remove(key);
+
+      let config = self
+         .registry(self.workspace_for_file(&key.root).as_deref())
+         .servers()
+         .find(|server| server.name == key.server)
+         .cloned()
+         .with_context(|| format!("LSP '{}' is not configured", key.server))?;
+
+      self.start_server(key.clone(), config).await
+   }
+
+   /// Shuts an instance down with the `shutdown`/`exit` handshake, killing it if it doesn't
+   /// comply. The instance must already be out of the server map.
+   pub(super) async fn stop_instance(&self, key: &ServerKey, mut instance: ServerInstance) {
+      log::info!("Shutting down LSP '{}' for root {:?}", key.server, key.root);
+
+      if instance.client.is_running()
+         && let Err(e) = instance.client.shutdown().await
+      {
+         log::warn!("LSP '{}' didn't shut down cleanly: {}", key.server, e);
+      }
+
+      let deadline = std::time::Instant::now() + EXIT_GRACE;
+      while matches!(instance.child.try_wait(), Ok(None)) && std::time::Instant::now() < deadline {
+         tokio::time::sleep(Duration::from_millis(50)).await;
+      }
+      let _ = instance.child.kill();
+      let _ = instance.child.wait();
+
+      self.clear_server_diagnostics(key);
+      self.clear_semantic_cache(None, Some(key));
+      self.clear_diagnostic_result_ids(key);
+      self.emit_status(key, ServerStatus::Stopped, None);
+   }
+
+   fn clear_server_diagnostics(&self, key: &ServerKey) {
+      for path in self.diagnostics.clear_server(&key.server, &key.root) {
+         Self::emit_diagnostics_changed(&self.app_handle, &path, Vec::new());
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_restart_delay_doubles_up_to_the_cap() {
+      let delays: Vec<u64> = (1..=7)
+         .map(|crashes| restart_delay(crashes).as_secs())
+         .collect();
+      assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+      assert_eq!(restart_delay(u32::MAX), RESTART_MAX_DELAY);
+   }
+
+   #[test]
+   fn test_crash_count_resets_after_stable_uptime() {
+      assert_eq!(crash_count(0, Duration::from_secs(1)), 1);
+      assert_eq!(crash_count(3, Duration::from_secs(1)), 4);
+      assert_eq!(crash_count(3, STABLE_UPTIME - Duration::from_millis(1)), 4);
+      assert_eq!(crash_count(3, STABLE_UPTIME), 1);
+      assert_eq!(crash_count(MAX_CRASHES - 1, Duration::ZERO), MAX_CRASHES);
+   }
+}
@@ -0,0 +1,285 @@
+use super::LspManager;
+use crate::lsp::{
+   client,
+   types::{DocumentSymbolItem, ServerKey, WorkspaceSymbolItem},
+   workspace_edit,
+};
+use anyhow::Result;
+use futures_util::future::join_all;
+use lsp_types::*;
+use std::path::Path;
+
+impl LspManager {
+   /// Outline of a file from the first server that provides one. Flat answers from older
+   /// servers are nested by range containment.
+   pub async fn document_symbols(&self, file_path: &str) -> Result<Vec<DocumentSymbolItem>> {
+      let clients = self.require_clients_for_file(file_path)?;
+      let params = DocumentSymbolParams {
+         text_document: TextDocumentIdentifier {
+            uri: Url::from_file_path(file_path)
+               .map_err(|_| anyhow::anyhow!("Invalid file path"))?,
+         },
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
+
+      for client in clients {
+         if client
+            .capabilities()
+            .is_none_or(|caps| caps.document_symbol_provider.is_none())
+         {
+            continue;
+         }
+         match client.text_document_document_symbol(params.clone()).await {
+            Ok(Some(DocumentSymbolResponse::Nested(symbols))) => {
+               return Ok(symbols.into_iter().map(to_document_symbol_item).collect());
+            }
+            Ok(Some(DocumentSymbolResponse::Flat(symbols))) => return Ok(nest_symbols(symbols)),
+            Ok(None) => {}
+            Err(e) => log::warn!("Document symbol request failed: {}", e),
+         }
+      }
+
+      Ok(Vec::new())
+   }
+
+   /// Symbols matching `query` from every running server in `workspace`, or in all workspaces.
+   pub async fn workspace_symbols(
+      &self,
+      query: &str,
+      workspace: Option<&Path>,
+   ) -> Result<Vec<WorkspaceSymbolItem>> {
+      let servers: Vec<_> = self
+         .servers
+         .lock()
+         .unwrap()
+         .iter()
+         .filter(|(key, instance)| {
+            workspace.is_none_or(|w| key.root.starts_with(w) || w.starts_with(&key.root))
+               && instance
+                  .client
+                  .capabilities()
+                  .is_some_and(|caps| caps.workspace_symbol_provider.is_some())
+         })
+         .map(|(key, instance)| (key.clone(), instance.client.clone()))
+         .collect();
+
+      let params = WorkspaceSymbolParams {
+         query: query.to_string(),
+         work_done_progress_params: Default::default(),
+         partial_result_params: Default::default(),
+      };
+      let responses = join_all(
+         servers
+            .iter()
+            .map(|(_, client)| client.workspace_symbol(params.clone())),
+      )
+      .await;
+
+      let mut items: Vec<WorkspaceSymbolItem> = Vec::new();
+      for ((key, _), response) in servers.iter().zip(responses) {
+         let symbols = match response {
+            Ok(Some(WorkspaceSymbolResponse::Nested(symbols))) => symbols,
+            Ok(Some(WorkspaceSymbolResponse::Flat(symbols))) => {
+               symbols.into_iter().map(flat_to_workspace_symbol).collect()
+            }
+            Ok(None) => continue,
+            Err(e) if client::is_cancellation(&e) => continue,
+            Err(e) => {
+               log::warn!("Workspace symbol request to '{}' failed: {}", key.server, e);
+               continue;
+            }
+         };
+
+         for symbol in symbols {
+            let item = to_workspace_symbol_item(key.clone(), symbol);
+            // Several servers for the same files report the same symbols
+            if !items.iter().any(|other| {
+               other.name == item.name && other.path == item.path && other.range == item.range
+            }) {
+               items.push(item);
+            }
+         }
+      }
+
+      Ok(items)
+   }
+
+   /// Fills in the range of a symbol that was returned with only its file.
+   pub async fn resolve_workspace_symbol(
+      &self,
+      server: &ServerKey,
+      symbol: WorkspaceSymbol,
+   ) -> Result<WorkspaceSymbolItem> {
+      let client = self.client_for_server(server)?;
+      let supported = client.capabilities().is_some_and(|caps| {
+         matches!(
+            caps.workspace_symbol_provider,
+            Some(OneOf::Right(WorkspaceSymbolOptions {
+               resolve_provider: Some(true),
+               ..
+            }))
+         )
+      });
+
+      let symbol = if supported && matches!(symbol.location, OneOf::Right(_)) {
+         client.workspace_symbol_resolve(symbol).await?
+      } else {
+         symbol
+      };
+      Ok(to_workspace_symbol_item(server.clone(), symbol))
+   }
+}
+
+pub(super) fn symbol_kind_name(kind: SymbolKind) -> &'static str {
+   match kind {
+      SymbolKind::FILE => "file",
+      SymbolKind::MODULE => "module",
+      SymbolKind::NAMESPACE => "namespace",
+      SymbolKind::PACKAGE => "package",
+      SymbolKind::CLASS => "class",
+      SymbolKind::METHOD => "method",
+      SymbolKind::PROPERTY => "property",
+      SymbolKind::FIELD => "field",
+      SymbolKind::CONSTRUCTOR => "constructor",
+      SymbolKind::ENUM => "enum",
+      SymbolKind::INTERFACE => "interface",
+      SymbolKind::FUNCTION => "function",
+      SymbolKind::VARIABLE => "variable",
+      SymbolKind::CONSTANT => "constant",
+      SymbolKind::STRING => "string",
+      SymbolKind::NUMBER => "number",
+      SymbolKind::BOOLEAN => "boolean",
+      SymbolKind::ARRAY => "array",
+      SymbolKind::OBJECT => "object",
+      SymbolKind::KEY => "key",
+      SymbolKind::NULL => "null",
+      SymbolKind::ENUM_MEMBER => "enum_member",
+      SymbolKind::STRUCT => "struct",
+      SymbolKind::EVENT => "event",
+      SymbolKind::OPERATOR => "operator",
+      SymbolKind::TYPE_PARAMETER => "type_parameter",
+      _ => "unknown",
+   }
+}
+
+fn to_document_symbol_item(symbol: DocumentSymbol) -> DocumentSymbolItem {
+   DocumentSymbolItem {
+      name: symbol.name,
+      detail: symbol.detail,
+      kind: symbol_kind_name(symbol.kind).to_string(),
+      range: symbol.range,
+      selection_range: symbol.selection_range,
+      children: symbol
+         .children
+         .unwrap_or_default()
+         .into_iter()
+         .map(to_document_symbol_item)
+         .collect(),
+   }
+}
+
+fn contains(outer: &Range, inner: &Range) -> bool {
+   outer.start <= inner.start && inner.end <= outer.end
+}
+
+/// Builds an outline from flat symbols: each symbol goes under the innermost earlier one whose
+/// range contains it.
+fn nest_symbols(mut symbols: Vec<SymbolInformation>) -> Vec<DocumentSymbolItem> {
+   symbols.sort_by(|a, b| {
+      let (a, b) = (a.location.range, b.location.range);
+      a.start.cmp(&b.start).then(b.end.cmp(&a.end))
+   });
+
+   let mut roots: Vec<DocumentSymbolItem> = Vec::new();
+   for symbol in symbols {
+      let item = DocumentSymbolItem {
+         name: symbol.name,
+         detail: symbol.container_name,
+         kind: symbol_kind_name(symbol.kind).to_string(),
+         range: symbol.location.range,
+         selection_range: symbol.location.range,
+         children: Vec::new(),
+      };
+
+      let mut siblings = &mut roots;
+      while siblings
+         .last()
+         .is_some_and(|last| contains(&last.range, &item.range))
+      {
+         siblings = &mut siblings.last_mut().unwrap().children;
+      }
+      siblings.push(item);
+   }
+   roots
+}
+
+#[allow(deprecated)]
+fn flat_to_workspace_symbol(symbol: SymbolInformation) -> WorkspaceSymbol {
+   WorkspaceSymbol {
+      name: symbol.name,
+      kind: symbol.kind,
+      tags: symbol.tags,
+      container_name: symbol.container_name,
+      location: OneOf::Left(symbol.location),
+      data: None,
+   }
+}
+
+fn to_workspace_symbol_item(server: ServerKey, symbol: WorkspaceSymbol) -> WorkspaceSymbolItem {
+   let (uri, range) = match &symbol.location {
+      OneOf::Left(location) => (&location.uri, Some(location.range)),
+      OneOf::Right(location) => (&location.uri, None),
+   };
+
+   WorkspaceSymbolItem {
+      server,
+      name: symbol.name.clone(),
+      kind: symbol_kind_name(symbol.kind).to_string(),
+      container_name: symbol.container_name.clone(),
+      path: workspace_edit::uri_to_display_path(uri),
+      range,
+      symbol,
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[allow(deprecated)]
+   fn symbol(name: &str, kind: SymbolKind, start: u32, end: u32) -> SymbolInformation {
+      SymbolInformation {
+         name: name.to_string(),
+         kind,
+         tags: None,
+         deprecated: None,
+         location: Location {
+            uri: Url::parse("file:///p/lib.rs").unwrap(),
+            range: Range::new(Position::new(start, 0), Position::new(end, 1)),
+         },
+         container_name: None,
+      }
+   }
+
+   #[test]
+   fn test_nest_flat_symbols_by_range() {
+      let outline = nest_symbols(vec![
+         symbol("method", SymbolKind::METHOD, 2, 4),
+         symbol("Impl", SymbolKind::CLASS, 1, 10),
+         symbol("free", SymbolKind::FUNCTION, 12, 14),
+         symbol("other", SymbolKind::METHOD, 5, 8),
+      ]);
+
+      assert_eq!(outline.len(), 2);
+      assert_eq!(outline[0].name, "Impl");
+      assert_eq!(outline[0].kind, "class");
+      let children: Vec<&str> = outline[0]
+         .children
+         .iter()
+         .map(|c| c.name.as_str())
+         .collect();
+      assert_eq!(children, vec!["method", "other"]);
+      assert_eq!(outline[1].name, "free");
+   }
+}
@@ -1,7 +1,15 @@
+pub mod capabilities;
 pub mod client;
 pub mod config;
+pub mod diagnostics;
+pub mod documents;
+pub mod locations;
 pub mod manager;
+pub mod semantic_tokens;
+pub mod snippet;
+pub mod trace;
 pub mod types;
 pub mod utils;
+pub mod workspace_edit;
 
 pub use manager::LspManager;
@@ -0,0 +1,152 @@
+use anyhow::{Result, bail};
+use lsp_types::{SemanticToken, SemanticTokensEdit, SemanticTokensLegend};
+use serde::Serialize;
+
+/// A semantic token resolved against the document text and the server's legend. Offsets are
+/// bytes, like tree-sitter tokens.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SemanticTokenSpan {
+   pub start: usize,
+   pub end: usize,
+   pub token_type: String,
+   pub modifiers: Vec<String>,
+}
+
+/// The wire form of the tokens, five integers per token, which delta edits index into.
+pub fn flatten(tokens: &[SemanticToken]) -> Vec<u32> {
+   tokens
+      .iter()
+      .flat_map(|t| {
+         [
+            t.delta_line,
+            t.delta_start,
+            t.length,
+            t.token_type,
+            t.token_modifiers_bitset,
+         ]
+      })
+      .collect()
+}
+
+/// Applies `full/delta` edits to the previous data. Edits refer to the previous data, so they
+/// are applied from the back.
+pub fn apply_edits(data: &mut Vec<u32>, edits: &[SemanticTokensEdit]) -> Result<()> {
+   let mut edits: Vec<&SemanticTokensEdit> = edits.iter().collect();
+   edits.sort_by_key(|edit| edit.start);
+
+   for edit in edits.into_iter().rev() {
+      let start = edit.start as usize;
+      let end = start + edit.delete_count as usize;
+      if end > data.len() {
+         bail!("Semantic tokens edit out of bounds");
+      }
+      let inserted = edit.data.as_deref().map(flatten).unwrap_or_default();
+      data.splice(start..end, inserted);
+   }
+   Ok(())
+}
+
+/// Turns relative token data into spans over `text`. Token columns and lengths count UTF-16
+/// code units. Tokens past the end of a line or of the text are dropped.
+pub fn decode(text: &str, data: &[u32], legend: &SemanticTokensLegend) -> Vec<SemanticTokenSpan> {
+   let line_starts: Vec<usize> = std::iter::once(0)
+      .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+      .collect();
+
+   let mut spans = Vec::with_capacity(data.len() / 5);
+   let mut line = 0usize;
+   let mut column = 0u32;
+
+   for token in data.chunks_exact(5) {
+      let [delta_line, delta_start, length, token_type, modifiers] =
+         [token[0], token[1], token[2], token[3], token[4]];
+
+      if delta_line > 0 {
+         line += delta_line as usize;
+         column = delta_start;
+      } else {
+         column += delta_start;
+      }
+
+      let Some(&line_start) = line_starts.get(line) else {
+         break;
+      };
+      let line_end = line_starts
+         .get(line + 1)
+         .map_or(text.len(), |next| next - 1);
+      let line_text = &text[line_start..line_end];
+
+      let (Some(start), Some(end)) = (
+         utf16_to_byte(line_text, column),
+         utf16_to_byte(line_text, column + length),
+      ) else {
+         continue;
+      };
+      let Some(token_type) = legend.token_types.get(token_type as usize) else {
+         continue;
+      };
+
+      spans.push(SemanticTokenSpan {
+         start: line_start + start,
+         end: line_start + end,
+         token_type: token_type.as_str().to_string(),
+         modifiers: legend
+            .token_modifiers
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| *bit < 32 && modifiers & (1 << bit) != 0)
+            .map(|(_, modifier)| modifier.as_str().to_string())
+            .collect(),
+      });
+   }
+
+   spans
+}
+
+fn utf16_to_byte(line: &str, units: u32) -> Option<usize> {
+   let mut count = 0;
+   for (i, ch) in line.char_indices() {
+      if count >= units {
+         return Some(i);
+      }
+      count += ch.len_utf16() as u32;
+   }
+   (count >= units).then_some(line.len())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use lsp_types::{SemanticTokenModifier, SemanticTokenType};
+
+   #[test]
+   fn test_decode_and_apply_delta() {
+      let legend = SemanticTokensLegend {
+         token_types: vec![SemanticTokenType::VARIABLE, SemanticTokenType::MACRO],
+         token_modifiers: vec![SemanticTokenModifier::DECLARATION, "mutable".into()],
+      };
+      let text = "let mut é = 1;\nprintln!(\"{}\", é);\n";
+      let mut data = vec![
+         0, 8, 1, 0, 0b11, // é, declared and mutable
+         1, 0, 7, 1, 0, // println
+         0, 15, 1, 0, 0b10, // é
+      ];
+
+      let spans = decode(text, &data, &legend);
+      assert_eq!(spans.len(), 3);
+      assert_eq!(&text[spans[0].start..spans[0].end], "é");
+      assert_eq!(spans[0].modifiers, vec!["declaration", "mutable"]);
+      assert_eq!(&text[spans[1].start..spans[1].end], "println");
+      assert_eq!(spans[1].token_type, "macro");
+      assert_eq!(&text[spans[2].start..spans[2].end], "é");
+
+      // The server drops the macro token
+      let edits = vec![SemanticTokensEdit {
+         start: 5,
+         delete_count: 5,
+         data: None,
+      }];
+      apply_edits(&mut data, &edits).unwrap();
+      assert_eq!(data.len(), 10);
+   }
+}
@@ -0,0 +1,385 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// A snippet expanded to the text it inserts, with the tab stops the editor walks through.
+/// Offsets count UTF-16 code units into `text`, matching JavaScript strings.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Snippet {
+   pub text: String,
+   /// In visiting order: `$1`, `$2`, ... and `$0`, the final cursor position, last. Snippets
+   /// without `$0` end at the end of the text.
+   pub tabstops: Vec<SnippetTabstop>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnippetTabstop {
+   pub index: u32,
+   /// Every place the tab stop appears; edits to one are mirrored to the others
+   pub ranges: Vec<SnippetRange>,
+   pub choices: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SnippetRange {
+   pub start: u32,
+   pub end: u32,
+}
+
+/// Parses LSP snippet syntax: `$1`, `${1:default}`, `${1|a,b|}`, `$VAR` and `${VAR:default}`.
+/// Variables come from `variables`; unknown ones expand to their default or nothing. Transforms
+/// are accepted but not applied. Anything malformed is kept as literal text.
+pub fn parse(input: &str, variables: impl Fn(&str) -> Option<String>) -> Snippet {
+   let mut parser = Parser {
+      chars: input.chars().collect(),
+      pos: 0,
+      text: String::new(),
+      len: 0,
+      tabstops: BTreeMap::new(),
+      variables: &variables,
+      failed: HashSet::new(),
+   };
+   parser.parse_until(None);
+
+   let mut tabstops: Vec<SnippetTabstop> = parser.tabstops.into_values().collect();
+   // BTreeMap order puts $0 first, but it's visited last
+   if tabstops.first().is_some_and(|t| t.index == 0) {
+      tabstops.rotate_left(1);
+   }
+
+   Snippet {
+      text: parser.text,
+      tabstops,
+   }
+}
+
+struct Parser<'a> {
+   chars: Vec<char>,
+   pos: usize,
+   text: String,
+   // Length of `text` in UTF-16 code units
+   len: u32,
+   tabstops: BTreeMap<u32, SnippetTabstop>,
+   variables: &'a dyn Fn(&str) -> Option<String>,
+   // Positions of `$`s that aren't the start of a valid construct. Whether one is doesn't depend
+   // on what encloses it, so each is only tried once; retrying them from every enclosing
+   // placeholder that fails in turn is exponential in the nesting depth.
+   failed: HashSet<usize>,
+}
+
+struct Checkpoint {
+   pos: usize,
+   text_len: usize,
+   len: u32,
+   tabstops: BTreeMap<u32, SnippetTabstop>,
+}
+
+impl Parser<'_> {
+   fn peek(&self) -> Option<char> {
+      self.chars.get(self.pos).copied()
+   }
+
+   fn push(&mut self, ch: char) {
+      self.text.push(ch);
+      self.len += ch.len_utf16() as u32;
+   }
+
+   fn push_str(&mut self, s: &str) {
+      s.chars().for_each(|ch| self.push(ch));
+   }
+
+   fn checkpoint(&self) -> Checkpoint {
+      Checkpoint {
+         pos: self.pos,
+         text_len: self.text.len(),
+         len: self.len,
+         tabstops: self.tabstops.clone(),
+      }
+   }
+
+   fn restore(&mut self, checkpoint: Checkpoint) {
+      self.pos = checkpoint.pos;
+      self.text.truncate(checkpoint.text_len);
+      self.len = checkpoint.len;
+      self.tabstops = checkpoint.tabstops;
+   }
+
+   fn add_tabstop(&mut self, index: u32, start: u32, choices: Option<Vec<String>>) {
+      let tabstop = self.tabstops.entry(index).or_insert(SnippetTabstop {
+         index,
+         ranges: Vec::new(),
+         choices: None,
+      });
+      tabstop.ranges.push(SnippetRange {
+         start,
+         end: self.len,
+      });
+      if choices.is_some() {
+         tabstop.choices = choices;
+      }
+   }
+
+   /// Consumes text up to an unescaped `terminator`, which is consumed too. Returns false if the
+   /// input ended first.
+   fn parse_until(&mut self, terminator: Option<char>) -> bool {
+      while let Some(ch) = self.peek() {
+         match ch {
+            '\\' if matches!(self.chars.get(self.pos + 1), Some('$' | '}' | '\\')) => {
+               self.push(self.chars[self.pos + 1]);
+               self.pos += 2;
+            }
+            _ if Some(ch) == terminator => {
+               self.pos += 1;
+               return true;
+            }
+            '$' => {
+               if !self.parse_dollar() {
+                  self.push('$');
+                  self.pos += 1;
+               }
+            }
+            _ => {
+               self.push(ch);
+               self.pos += 1;
+            }
+         }
+      }
+      terminator.is_none()
+   }
+
+   fn parse_int(&mut self) -> Option<u32> {
+      let start = self.pos;
+      while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+         self.pos += 1;
+      }
+      self.chars[start..self.pos]
+         .iter()
+         .collect::<String>()
+         .parse()
+         .ok()
+   }
+
+   fn parse_name(&mut self) -> Option<String> {
+      if !self
+         .peek()
+         .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+      {
+         return None;
+      }
+      let start = self.pos;
+      while self
+         .peek()
+         .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+      {
+         self.pos += 1;
+      }
+      Some(self.chars[start..self.pos].iter().collect())
+   }
+
+   /// Parses the construct starting at the `$` under the cursor. On failure nothing is consumed.
+   fn parse_dollar(&mut self) -> bool {
+      if self.failed.contains(&self.pos) {
+         return false;
+      }
+      let checkpoint = self.checkpoint();
+      self.pos += 1;
+
+      let parsed = if self.peek() == Some('{') {
+         self.pos += 1;
+         self.parse_braced()
+      } else if let Some(index) = self.parse_int() {
+         self.add_tabstop(index, self.len, None);
+         true
+      } else if let Some(name) = self.parse_name() {
+         let value = (self.variables)(&name).unwrap_or_default();
+         self.push_str(&value);
+         true
+      } else {
+         false
+      };
+
+      if !parsed {
+         self.restore(checkpoint);
+         self.failed.insert(self.pos);
+      }
+      parsed
+   }
+
+   fn parse_braced(&mut self) -> bool {
+      let start = self.len;
+
+      if let Some(index) = self.parse_int() {
+         return match self.peek() {
+            Some('}') => {
+               self.pos += 1;
+               self.add_tabstop(index, start, None);
+               true
+            }
+            Some(':') => {
+               self.pos += 1;
+               if !self.parse_until(Some('}')) {
+                  return false;
+               }
+               self.add_tabstop(index, start, None);
+               true
+            }
+            Some('|') => {
+               self.pos += 1;
+               let Some(choices) = self.parse_choices() else {
+                  return false;
+               };
+               self.push_str(&choices[0]);
+               self.add_tabstop(index, start, Some(choices));
+               true
+            }
+            Some('/') => {
+               if !self.skip_transform() {
+                  return false;
+               }
+               self.add_tabstop(index, start, None);
+               true
+            }
+            _ => false,
+         };
+      }
+
+      let Some(name) = self.parse_name() else {
+         return false;
+      };
+      let value = (self.variables)(&name);
+      match self.peek() {
+         Some('}') => {
+            self.pos += 1;
+            self.push_str(&value.unwrap_or_default());
+            true
+         }
+         Some(':') => {
+            self.pos += 1;
+            let before_default = self.checkpoint();
+            if !self.parse_until(Some('}')) {
+               return false;
+            }
+            // A known variable replaces its default
+            if let Some(value) = value {
+               let pos = self.pos;
+               self.restore(before_default);
+               self.pos = pos;
+               self.push_str(&value);
+            }
+            true
+         }
+         Some('/') => {
+            if !self.skip_transform() {
+               return false;
+            }
+            self.push_str(&value.unwrap_or_default());
+            true
+         }
+         _ => false,
+      }
+   }
+
+   /// Reads `a,b|}` after the opening `|`. Choices may escape `,`, `|` and `\`.
+   fn parse_choices(&mut self) -> Option<Vec<String>> {
+      let mut choices = vec![String::new()];
+      loop {
+         match self.peek()? {
+            '\\'
+               if matches!(
+                  self.chars.get(self.pos + 1),
+                  Some(',' | '|' | '\\' | '$' | '}')
+               ) =>
+            {
+               choices.last_mut()?.push(self.chars[self.pos + 1]);
+               self.pos += 2;
+            }
+            ',' => {
+               choices.push(String::new());
+               self.pos += 1;
+            }
+            '|' if self.chars.get(self.pos + 1) == Some(&'}') => {
+               self.pos += 2;
+               return Some(choices);
+            }
+            ch => {
+               choices.last_mut()?.push(ch);
+               self.pos += 1;
+            }
+         }
+      }
+   }
+
+   /// Skips `/regex/format/options}`.
+   fn skip_transform(&mut self) -> bool {
+      while let Some(ch) = self.peek() {
+         self.pos += 1;
+         match ch {
+            '\\' => self.pos += 1,
+            '}' => return true,
+            _ => {}
+         }
+      }
+      false
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn range(start: u32, end: u32) -> SnippetRange {
+      SnippetRange { start, end }
+   }
+
+   #[test]
+   fn test_parse_tabstops_placeholders_and_choices() {
+      let snippet = parse("fn ${1:name}(${2:arg}: ${3|i32,u64|}) {\n\t$0\n}$1", |_| {
+         None
+      });
+      assert_eq!(snippet.text, "fn name(arg: i32) {\n\t\n}");
+
+      let indices: Vec<u32> = snippet.tabstops.iter().map(|t| t.index).collect();
+      assert_eq!(indices, vec![1, 2, 3, 0]);
+      assert_eq!(snippet.tabstops[0].ranges, vec![range(3, 7), range(23, 23)]);
+      assert_eq!(snippet.tabstops[1].ranges, vec![range(8, 11)]);
+      assert_eq!(
+         snippet.tabstops[2].choices,
+         Some(vec!["i32".to_string(), "u64".to_string()])
+      );
+      assert_eq!(snippet.tabstops[3].ranges, vec![range(21, 21)]);
+   }
+
+   #[test]
+   fn test_parse_variables_escapes_and_malformed_input() {
+      let variables = |name: &str| (name == "TM_FILENAME").then(|| "main.rs".to_string());
+
+      let snippet = parse(
+         "// $TM_FILENAME ${UNKNOWN:fallback} ${TM_FILENAME:x}",
+         variables,
+      );
+      assert_eq!(snippet.text, "// main.rs fallback main.rs");
+
+      let snippet = parse("cost: \\$5 ${1:a\\}b} $ ${2:open", |_| None);
+      assert_eq!(snippet.text, "cost: $5 a}b $ ${2:open");
+      assert_eq!(snippet.tabstops.len(), 1);
+
+      // Placeholders nest and offsets count UTF-16 units
+      let snippet = parse("😀${1:outer ${2:inner}}", |_| None);
+      assert_eq!(snippet.tabstops[0].ranges, vec![range(2, 13)]);
+      assert_eq!(snippet.tabstops[1].ranges, vec![range(8, 13)]);
+   }
+
+   #[test]
+   fn test_parse_deeply_nested_unclosed_placeholders() {
+      let input = "${1:".repeat(1000);
+      let snippet = parse(&input, |_| None);
+      assert_eq!(snippet.text, input);
+      assert!(snippet.tabstops.is_empty());
+
+      // Only the innermost placeholders close
+      let input = format!("{}${{2:x}}", "${1:".repeat(1000));
+      let snippet = parse(&input, |_| None);
+      assert_eq!(snippet.text, format!("{}x", "${1:".repeat(1000)));
+      assert_eq!(snippet.tabstops.len(), 1);
+      assert_eq!(snippet.tabstops[0].ranges, vec![range(4000, 4001)]);
+   }
+}
@@ -0,0 +1,252 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+   collections::{HashMap, VecDeque},
+   sync::{
+      Mutex,
+      atomic::{AtomicU64, Ordering},
+   },
+   time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Entries kept per server; older ones are dropped first.
+const TRACE_CAPACITY: usize = 2000;
+/// Payloads are cut to this many bytes. `size` still reports the full length.
+const MAX_PAYLOAD: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceDirection {
+   /// Client to server
+   Outgoing,
+   /// Server to client
+   Incoming,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceKind {
+   Request,
+   Response,
+   /// A response carrying an error
+   Error,
+   Notification,
+   /// A line the server wrote to stderr
+   Stderr,
+}
+
+/// One JSON-RPC message, or stderr line, exchanged with a server.
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEntry {
+   /// Increases by one per entry, so the UI can tell which entries it already has
+   pub seq: u64,
+   /// Milliseconds since the Unix epoch
+   pub timestamp: u64,
+   pub direction: TraceDirection,
+   pub kind: TraceKind,
+   /// For responses, the method of the request they answer
+   pub method: Option<String>,
+   pub id: Option<Value>,
+   /// For responses, the time since the request was sent
+   pub latency_ms: Option<u64>,
+   /// Size of the whole message body in bytes
+   pub size: usize,
+   pub payload: String,
+   pub truncated: bool,
+}
+
+pub type TraceListener = Box<dyn Fn(&TraceEntry) + Send + Sync>;
+
+/// Bounded record of a server's traffic. Outlives restarts of the server, so the messages
+/// leading up to a crash stay visible.
+#[derive(Default)]
+pub struct MessageTrace {
+   entries: Mutex<VecDeque<TraceEntry>>,
+   next_seq: AtomicU64,
+   /// Method and send time of requests waiting for an answer, by the direction they were sent in
+   in_flight: Mutex<HashMap<(TraceDirection, String), (String, Instant)>>,
+   listener: Mutex<Option<TraceListener>>,
+}
+
+impl MessageTrace {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   /// Records a message that was sent or received. `body` is its serialized form, as written to
+   /// or read from the server, so it isn't serialized again.
+   pub fn record(&self, direction: TraceDirection, message: &Value, body: &str) {
+      let method = message.get("method").and_then(Value::as_str);
+      let id = message.get("id").cloned();
+      let id_key = id.as_ref().map(Value::to_string);
+
+      let (kind, method, latency_ms) = match (method, id_key) {
+         (Some(method), Some(id_key)) => {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            // Servers that never answer shouldn't grow this forever
+            if in_flight.len() >= TRACE_CAPACITY {
+               in_flight.clear();
+            }
+            in_flight.insert((direction, id_key), (method.to_string(), Instant::now()));
+            (TraceKind::Request, Some(method.to_string()), None)
+         }
+         (Some(method), None) => (TraceKind::Notification, Some(method.to_string()), None),
+         (None, id_key) => {
+            // A response travels the other way from the request it answers
+            let request_direction = match direction {
+               TraceDirection::Outgoing => TraceDirection::Incoming,
+               TraceDirection::Incoming => TraceDirection::Outgoing,
+            };
+            let request = id_key.and_then(|id_key| {
+               self
+                  .in_flight
+                  .lock()
+                  .unwrap()
+                  .remove(&(request_direction, id_key))
+            });
+            let kind = if message.get("error").is_some() {
+               TraceKind::Error
+            } else {
+               TraceKind::Response
+            };
+            match request {
+               Some((method, sent)) => {
+                  (kind, Some(method), Some(sent.elapsed().as_millis() as u64))
+               }
+               None => (kind, None, None),
+            }
+         }
+      };
+
+      let (payload, truncated) = truncate(body);
+      self.push(TraceEntry {
+         seq: 0,
+         timestamp: 0,
+         direction,
+         kind,
+         method,
+         id,
+         latency_ms,
+         size: body.len(),
+         payload,
+         truncated,
+      });
+   }
+
+   pub fn record_stderr(&self, line: &str) {
+      let (payload, truncated) = truncate(line);
+      self.push(TraceEntry {
+         seq: 0,
+         timestamp: 0,
+         direction: TraceDirection::Incoming,
+         kind: TraceKind::Stderr,
+         method: None,
+         id: None,
+         latency_ms: None,
+         size: line.len(),
+         payload,
+         truncated,
+      });
+   }
+
+   fn push(&self, mut entry: TraceEntry) {
+      entry.seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+      entry.timestamp = SystemTime::now()
+         .duration_since(UNIX_EPOCH)
+         .map(|d| d.as_millis() as u64)
+         .unwrap_or_default();
+
+      if let Some(listener) = self.listener.lock().unwrap().as_ref() {
+         listener(&entry);
+      }
+
+      let mut entries = self.entries.lock().unwrap();
+      if entries.len() == TRACE_CAPACITY {
+         entries.pop_front();
+      }
+      entries.push_back(entry);
+   }
+
+   /// Entries with a `seq` of at least `since`, oldest first.
+   pub fn entries(&self, since: Option<u64>) -> Vec<TraceEntry> {
+      self
+         .entries
+         .lock()
+         .unwrap()
+         .iter()
+         .filter(|entry| since.is_none_or(|since| entry.seq >= since))
+         .cloned()
+         .collect()
+   }
+
+   pub fn clear(&self) {
+      self.entries.lock().unwrap().clear();
+   }
+
+   /// Installs a callback run for every new entry, or removes it. It runs on the thread that
+   /// sent or received the message, so it must not block.
+   pub fn set_listener(&self, listener: Option<TraceListener>) {
+      *self.listener.lock().unwrap() = listener;
+   }
+}
+
+fn truncate(payload: &str) -> (String, bool) {
+   if payload.len() <= MAX_PAYLOAD {
+      return (payload.to_string(), false);
+   }
+   let mut end = MAX_PAYLOAD;
+   while !payload.is_char_boundary(end) {
+      end -= 1;
+   }
+   (payload[..end].to_string(), true)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   #[test]
+   fn test_records_pairs_and_bounds_entries() {
+      let trace = MessageTrace::new();
+      let request = json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/hover"});
+      trace.record(TraceDirection::Outgoing, &request, &request.to_string());
+      let response = json!({"jsonrpc": "2.0", "id": 1, "result": null});
+      trace.record(TraceDirection::Incoming, &response, &response.to_string());
+      // A server request reusing the same id doesn't match our request
+      let server_request = json!({"jsonrpc": "2.0", "id": 1, "method": "workspace/configuration"});
+      trace.record(
+         TraceDirection::Incoming,
+         &server_request,
+         &server_request.to_string(),
+      );
+      let error = json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32601}});
+      trace.record(TraceDirection::Outgoing, &error, &error.to_string());
+
+      let entries = trace.entries(None);
+      assert_eq!(entries[0].kind, TraceKind::Request);
+      assert_eq!(entries[1].kind, TraceKind::Response);
+      assert_eq!(entries[1].method.as_deref(), Some("textDocument/hover"));
+      assert!(entries[1].latency_ms.is_some());
+      assert_eq!(entries[3].kind, TraceKind::Error);
+      assert_eq!(
+         entries[3].method.as_deref(),
+         Some("workspace/configuration")
+      );
+
+      let long = json!({"jsonrpc": "2.0", "method": "x", "params": "é".repeat(MAX_PAYLOAD)});
+      trace.record(TraceDirection::Outgoing, &long, &long.to_string());
+      let last = trace.entries(Some(4));
+      assert_eq!(last.len(), 1);
+      assert!(last[0].truncated);
+      assert_eq!(last[0].size, long.to_string().len());
+      assert!(last[0].payload.len() <= MAX_PAYLOAD);
+
+      for _ in 0..TRACE_CAPACITY {
+         trace.record_stderr("warning");
+      }
+      let entries = trace.entries(None);
+      assert_eq!(entries.len(), TRACE_CAPACITY);
+      assert_eq!(entries[0].seq, 5);
+   }
+}
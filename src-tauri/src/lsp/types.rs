@@ -1,4 +1,11 @@
+use super::{snippet::Snippet, trace::TraceEntry, workspace_edit::WorkspaceEditResult};
+use lsp_types::{
+   CodeActionOrCommand, CompletionItem, DocumentLink, InlayHint, Position, Range,
+   ServerCapabilities, WorkspaceSymbol,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LspError {
@@ -14,3 +21,299 @@ impl From<anyhow::Error> for LspError {
 }
 
 pub type LspResult<T> = Result<T, LspError>;
+
+/// A diagnostic flattened for the problems panel. Lines and columns are 1-based.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticItem {
+   pub file: String,
+   pub line: u32,
+   pub column: u32,
+   pub end_line: u32,
+   pub end_column: u32,
+   pub severity: String,
+   pub message: String,
+   pub source: Option<String>,
+   pub code: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticsChangedEvent {
+   pub file: String,
+   pub diagnostics: Vec<DiagnosticItem>,
+}
+
+/// Identifies a running language server: one instance per server and project root.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ServerKey {
+   pub root: PathBuf,
+   pub server: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GotoKind {
+   Definition,
+   Declaration,
+   TypeDefinition,
+   Implementation,
+}
+
+/// A location in a file with the text of the line it starts on.
+#[derive(Debug, Clone, Serialize)]
+pub struct LocationItem {
+   pub path: String,
+   pub range: Range,
+   pub preview: Option<String>,
+}
+
+/// Where a rename may happen. A missing `range` means the server defers to the editor's own
+/// word-at-cursor logic.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrepareRenameItem {
+   pub range: Option<Range>,
+   pub placeholder: Option<String>,
+}
+
+/// A code action tagged with the server that offered it, which must also resolve and run it.
+#[derive(Debug, Clone, Serialize)]
+pub struct CodeActionItem {
+   pub server: ServerKey,
+   pub action: CodeActionOrCommand,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CodeActionOutcome {
+   pub edit: Option<WorkspaceEditResult>,
+   pub command_result: Option<Value>,
+}
+
+/// A `$/progress` notification for work done progress, flattened across begin, report and end.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressEvent {
+   pub server: ServerKey,
+   pub token: String,
+   /// `begin`, `report` or `end`
+   pub kind: String,
+   pub title: Option<String>,
+   pub message: Option<String>,
+   pub percentage: Option<u32>,
+   pub cancellable: Option<bool>,
+}
+
+/// A `window/showMessage` or `window/logMessage` notification.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerMessageEvent {
+   pub server: ServerKey,
+   /// `error`, `warning`, `info` or `log`
+   pub kind: String,
+   pub message: String,
+}
+
+/// A `window/showMessageRequest` waiting on the user. The choice goes back through
+/// `lsp_respond_to_message_request` with the same `id`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageRequestEvent {
+   pub id: u64,
+   pub server: ServerKey,
+   pub kind: String,
+   pub message: String,
+   pub actions: Vec<String>,
+}
+
+/// A `window/showDocument` request. External documents are meant for the system browser.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShowDocumentEvent {
+   pub server: ServerKey,
+   pub uri: String,
+   pub path: Option<String>,
+   pub external: bool,
+   pub take_focus: bool,
+   pub selection: Option<Range>,
+}
+
+/// The capabilities a running server announced, so the UI can hide what it doesn't support.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerCapabilitiesItem {
+   pub server: ServerKey,
+   pub capabilities: ServerCapabilities,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerStatus {
+   Starting,
+   Running,
+   /// Exited unexpectedly, a restart is scheduled
+   Crashed,
+   Stopped,
+   /// Couldn't be started, or crashed too often to keep restarting
+   Failed,
+}
+
+/// Emitted as `lsp-server-status` whenever a server changes state, for the status bar.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerStatusEvent {
+   pub server: ServerKey,
+   pub status: ServerStatus,
+   pub message: Option<String>,
+}
+
+/// A completion item tagged with the server that produced it, which must also resolve it.
+/// Snippet items come with their text already parsed.
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionEntry {
+   #[serde(flatten)]
+   pub item: CompletionItem,
+   pub server: ServerKey,
+   pub snippet: Option<Snippet>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionListResult {
+   /// Typing more should re-query instead of filtering these items
+   pub is_incomplete: bool,
+   pub items: Vec<CompletionEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SignatureHelpItem {
+   pub signatures: Vec<SignatureItem>,
+   pub active_signature: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SignatureItem {
+   pub label: String,
+   /// Markdown
+   pub documentation: Option<String>,
+   pub parameters: Vec<ParameterItem>,
+   pub active_parameter: Option<u32>,
+}
+
+/// A parameter of a signature. `start` and `end` are UTF-16 offsets into the signature label,
+/// for highlighting the active one.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParameterItem {
+   pub label: String,
+   pub start: Option<u32>,
+   pub end: Option<u32>,
+   pub documentation: Option<String>,
+}
+
+/// An inlay hint flattened for rendering, with the original kept for `inlayHint/resolve`.
+#[derive(Debug, Clone, Serialize)]
+pub struct InlayHintItem {
+   pub server: ServerKey,
+   pub position: Position,
+   pub text: String,
+   /// `type`, `parameter` or none
+   pub kind: Option<String>,
+   pub padding_left: bool,
+   pub padding_right: bool,
+   pub tooltip: Option<String>,
+   pub hint: InlayHint,
+}
+
+/// A node of a document outline.
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentSymbolItem {
+   pub name: String,
+   pub detail: Option<String>,
+   pub kind: String,
+   /// The whole declaration
+   pub range: Range,
+   /// The identifier, where the cursor goes when the symbol is picked
+   pub selection_range: Range,
+   pub children: Vec<DocumentSymbolItem>,
+}
+
+/// A project-wide symbol. `range` is missing until resolved for servers that return only the
+/// file; the original is kept for `workspaceSymbol/resolve`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceSymbolItem {
+   pub server: ServerKey,
+   pub name: String,
+   pub kind: String,
+   pub container_name: Option<String>,
+   pub path: String,
+   pub range: Option<Range>,
+   pub symbol: WorkspaceSymbol,
+}
+
+/// An occurrence of the symbol under the cursor.
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentHighlightItem {
+   pub range: Range,
+   /// `text`, `read` or `write`
+   pub kind: String,
+}
+
+/// A clickable range, with the original kept for `documentLink/resolve`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentLinkItem {
+   pub server: ServerKey,
+   pub range: Range,
+   /// A path for files, the URI otherwise; missing until resolved for some servers
+   pub target: Option<String>,
+   pub tooltip: Option<String>,
+   pub link: DocumentLink,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FoldingRangeItem {
+   pub start_line: u32,
+   pub start_character: Option<u32>,
+   pub end_line: u32,
+   pub end_character: Option<u32>,
+   /// `comment`, `imports` or `region`
+   pub kind: Option<String>,
+   pub collapsed_text: Option<String>,
+}
+
+/// A node of a call or type hierarchy. Children are loaded when the node is expanded, by passing
+/// `item`, the server's own item, back along with a direction.
+#[derive(Debug, Clone, Serialize)]
+pub struct HierarchyNode {
+   pub server: ServerKey,
+   pub name: String,
+   pub kind: String,
+   pub detail: Option<String>,
+   pub path: String,
+   pub range: Range,
+   pub selection_range: Range,
+   /// Where the calls linking this node to its parent are: in this node's file for incoming
+   /// calls, in the parent's for outgoing ones. Empty for roots and type hierarchies.
+   pub call_ranges: Vec<Range>,
+   pub item: Value,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallHierarchyDirection {
+   /// Functions calling the node
+   Incoming,
+   /// Functions the node calls
+   Outgoing,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TypeHierarchyDirection {
+   Supertypes,
+   Subtypes,
+}
+
+/// A workspace symbol scored against the picker query by `fuzzy_match`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceSymbolMatch {
+   pub symbol: WorkspaceSymbolItem,
+   pub score: i64,
+   /// Matched character indices in the symbol name
+   pub indices: Vec<u32>,
+}
+
+/// A new entry in a watched server's message trace.
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEvent {
+   pub server: ServerKey,
+   pub entry: TraceEntry,
+}
@@ -0,0 +1,467 @@
+use super::documents;
+use anyhow::{Context, Result, bail};
+use lsp_types::{
+   CreateFile, DeleteFile, DocumentChangeOperation, DocumentChanges, OneOf, RenameFile, ResourceOp,
+   TextEdit, Url, WorkspaceEdit,
+};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// One step of a workspace edit, in the order the server asked for them to be applied.
+#[derive(Debug, Clone)]
+pub enum EditOperation {
+   Edit {
+      uri: Url,
+      /// Version the edits were computed against, when the server pinned one
+      version: Option<i32>,
+      edits: Vec<TextEdit>,
+   },
+   Create(CreateFile),
+   Rename(RenameFile),
+   Delete(DeleteFile),
+}
+
+impl EditOperation {
+   pub fn uri(&self) -> &Url {
+      match self {
+         EditOperation::Edit { uri, .. } => uri,
+         EditOperation::Create(op) => &op.uri,
+         EditOperation::Rename(op) => &op.old_uri,
+         EditOperation::Delete(op) => &op.uri,
+      }
+   }
+}
+
+/// Flattens either form of a workspace edit into a list of operations. `documentChanges` wins
+/// over `changes` when a server sends both, as the spec asks.
+pub fn collect_operations(edit: WorkspaceEdit) -> Vec<EditOperation> {
+   if let Some(document_changes) = edit.document_changes {
+      let changes = match document_changes {
+         DocumentChanges::Edits(edits) => edits
+            .into_iter()
+            .map(DocumentChangeOperation::Edit)
+            .collect(),
+         DocumentChanges::Operations(operations) => operations,
+      };
+
+      return changes
+         .into_iter()
+         .map(|change| match change {
+            DocumentChangeOperation::Edit(edit) => EditOperation::Edit {
+               uri: edit.text_document.uri,
+               version: edit.text_document.version,
+               edits: edit
+                  .edits
+                  .into_iter()
+                  .map(|edit| match edit {
+                     OneOf::Left(edit) => edit,
+                     OneOf::Right(annotated) => annotated.text_edit,
+                  })
+                  .collect(),
+            },
+            DocumentChangeOperation::Op(ResourceOp::Create(op)) => EditOperation::Create(op),
+            DocumentChangeOperation::Op(ResourceOp::Rename(op)) => EditOperation::Rename(op),
+            DocumentChangeOperation::Op(ResourceOp::Delete(op)) => EditOperation::Delete(op),
+         })
+         .collect();
+   }
+
+   let mut changes: Vec<(Url, Vec<TextEdit>)> =
+      edit.changes.unwrap_or_default().into_iter().collect();
+   changes.sort_by(|a, b| a.0.cmp(&b.0));
+   changes
+      .into_iter()
+      .map(|(uri, edits)| EditOperation::Edit {
+         uri,
+         version: None,
+         edits,
+      })
+      .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileEditKind {
+   Edit,
+   Create,
+   Rename,
+   Delete,
+}
+
+/// What a workspace edit will do to one file, shown to the user before it's applied.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileEditSummary {
+   pub path: String,
+   pub kind: FileEditKind,
+   pub edit_count: usize,
+   pub new_path: Option<String>,
+   pub is_open: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceEditPreview {
+   pub edit: WorkspaceEdit,
+   pub files: Vec<FileEditSummary>,
+   pub total_edits: usize,
+}
+
+/// Outcome of applying the operations for one file. `text` and `version` are set for open
+/// documents so the editor can replace its buffer.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileEditOutcome {
+   pub path: String,
+   pub kind: FileEditKind,
+   pub success: bool,
+   pub error: Option<String>,
+   pub new_path: Option<String>,
+   pub text: Option<String>,
+   pub version: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceEditResult {
+   pub applied: bool,
+   pub files: Vec<FileEditOutcome>,
+}
+
+impl WorkspaceEditResult {
+   /// The first failure, phrased for the `failureReason` of a `workspace/applyEdit` response.
+   pub fn failure_reason(&self) -> Option<String> {
+      self.files.iter().find(|file| !file.success).map(|file| {
+         format!(
+            "{}: {}",
+            file.path,
+            file.error.as_deref().unwrap_or("failed")
+         )
+      })
+   }
+}
+
+pub fn uri_to_display_path(uri: &Url) -> String {
+   uri.to_file_path()
+      .map(|path| path.to_string_lossy().to_string())
+      .unwrap_or_else(|_| uri.to_string())
+}
+
+/// Summarizes operations per file. Several edits to the same file are merged into one entry.
+pub fn summarize(
+   operations: &[EditOperation],
+   is_open: impl Fn(&Url) -> bool,
+) -> Vec<FileEditSummary> {
+   let mut files: Vec<FileEditSummary> = Vec::new();
+
+   for operation in operations {
+      let path = uri_to_display_path(operation.uri());
+      let (kind, edit_count, new_path) = match operation {
+         EditOperation::Edit { edits, .. } => (FileEditKind::Edit, edits.len(), None),
+         EditOperation::Create(_) => (FileEditKind::Create, 0, None),
+         EditOperation::Rename(op) => (
+            FileEditKind::Rename,
+            0,
+            Some(uri_to_display_path(&op.new_uri)),
+         ),
+         EditOperation::Delete(_) => (FileEditKind::Delete, 0, None),
+      };
+
+      if kind == FileEditKind::Edit
+         && let Some(existing) = files
+            .iter_mut()
+            .find(|file| file.path == path && file.kind == FileEditKind::Edit)
+      {
+         existing.edit_count += edit_count;
+         continue;
+      }
+
+      files.push(FileEditSummary {
+         is_open: is_open(operation.uri()),
+         path,
+         kind,
+         edit_count,
+         new_path,
+      });
+   }
+
+   files
+}
+
+fn file_path(uri: &Url) -> Result<PathBuf> {
+   uri.to_file_path()
+      .map_err(|_| anyhow::anyhow!("Not a file URI: {}", uri))
+}
+
+/// Errors when a document moved on from the version an edit was computed against.
+pub fn check_version(expected: Option<i32>, current: i32) -> Result<()> {
+   if let Some(expected) = expected
+      && expected != current
+   {
+      bail!(
+         "Document changed since the edit was computed (version {} vs {})",
+         expected,
+         current
+      );
+   }
+   Ok(())
+}
+
+/// Applies text edits to a file on disk. Nothing is written when the edits don't apply.
+pub fn edit_file(path: &Path, edits: &[TextEdit]) -> Result<()> {
+   let text = std::fs::read_to_string(path).context("Failed to read file")?;
+   let new_text = documents::apply_text_edits(&text, edits)?;
+   std::fs::write(path, new_text).context("Failed to write file")
+}
+
+pub fn create_file(op: &CreateFile) -> Result<()> {
+   let path = file_path(&op.uri)?;
+   let options = op.options.as_ref();
+
+   if path.exists() {
+      if options.and_then(|o| o.overwrite).unwrap_or(false) {
+         // Fall through and truncate
+      } else if options.and_then(|o| o.ignore_if_exists).unwrap_or(false) {
+         return Ok(());
+      } else {
+         bail!("File already exists");
+      }
+   }
+
+   if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent).context("Failed to create parent directory")?;
+   }
+   std::fs::write(&path, "").context("Failed to create file")?;
+   Ok(())
+}
+
+/// Moves a file on disk, returning its old and new path, or `None` when the target exists and
+/// the server asked for that to be ignored.
+pub fn rename_file(op: &RenameFile) -> Result<Option<(PathBuf, PathBuf)>> {
+   let old_path = file_path(&op.old_uri)?;
+   let new_path = file_path(&op.new_uri)?;
+   let options = op.options.as_ref();
+
+   if new_path.exists() {
+      if options.and_then(|o| o.overwrite).unwrap_or(false) {
+         // Fall through and replace the target
+      } else if options.and_then(|o| o.ignore_if_exists).unwrap_or(false) {
+         return Ok(None);
+      } else {
+         bail!("Target {} already exists", new_path.display());
+      }
+   }
+
+   if let Some(parent) = new_path.parent() {
+      std::fs::create_dir_all(parent).context("Failed to create parent directory")?;
+   }
+   std::fs::rename(&old_path, &new_path).context("Failed to rename file")?;
+   Ok(Some((old_path, new_path)))
+}
+
+/// Deletes a file or directory on disk, returning its path, or `None` when it didn't exist and
+/// the server asked for that to be ignored.
+pub fn delete_file(op: &DeleteFile) -> Result<Option<PathBuf>> {
+   let path = file_path(&op.uri)?;
+   let options = op.options.as_ref();
+
+   if !path.exists() {
+      if options
+         .and_then(|o| o.ignore_if_not_exists)
+         .unwrap_or(false)
+      {
+         return Ok(None);
+      }
+      bail!("File does not exist");
+   }
+
+   if path.is_dir() {
+      if options.and_then(|o| o.recursive).unwrap_or(false) {
+         std::fs::remove_dir_all(&path).context("Failed to delete directory")?;
+      } else {
+         std::fs::remove_dir(&path).context("Failed to delete directory")?;
+      }
+   } else {
+      std::fs::remove_file(&path).context("Failed to delete file")?;
+   }
+   Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use lsp_types::{
+      CreateFileOptions, OptionalVersionedTextDocumentIdentifier, Position, Range,
+      RenameFileOptions, TextDocumentEdit,
+   };
+   use std::collections::HashMap;
+
+   fn edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> TextEdit {
+      TextEdit {
+         range: Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+         new_text: new_text.to_string(),
+      }
+   }
+
+   fn uri(path: &Path) -> Url {
+      Url::from_file_path(path).unwrap()
+   }
+
+   #[test]
+   fn test_collects_and_summarizes_operations() {
+      let a = Url::parse("file:///src/a.rs").unwrap();
+      let b = Url::parse("file:///src/b.rs").unwrap();
+
+      // `changes` alone become edits sorted by file
+      let changes = WorkspaceEdit {
+         changes: Some(HashMap::from([
+            (b.clone(), vec![edit((0, 0), (0, 1), "x")]),
+            (a.clone(), vec![edit((0, 0), (0, 1), "y")]),
+         ])),
+         ..Default::default()
+      };
+      let operations = collect_operations(changes);
+      assert_eq!(operations[0].uri(), &a);
+      assert_eq!(operations[1].uri(), &b);
+
+      // `documentChanges` win over `changes` and keep their order
+      let text_edit = |uri: &Url, version| {
+         DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+               uri: uri.clone(),
+               version,
+            },
+            edits: vec![
+               OneOf::Left(edit((0, 0), (0, 1), "x")),
+               OneOf::Left(edit((1, 0), (1, 1), "y")),
+            ],
+         })
+      };
+      let edit = WorkspaceEdit {
+         changes: Some(HashMap::from([(a.clone(), Vec::new())])),
+         document_changes: Some(DocumentChanges::Operations(vec![
+            text_edit(&a, Some(3)),
+            DocumentChangeOperation::Op(ResourceOp::Rename(RenameFile {
+               old_uri: b.clone(),
+               new_uri: Url::parse("file:///src/c.rs").unwrap(),
+               options: None,
+               annotation_id: None,
+            })),
+            text_edit(&a, None),
+         ])),
+         change_annotations: None,
+      };
+      let operations = collect_operations(edit);
+      assert_eq!(operations.len(), 3);
+      assert!(matches!(
+         operations[0],
+         EditOperation::Edit {
+            version: Some(3),
+            ..
+         }
+      ));
+
+      // Edits to one file are merged, the rename keeps its own entry
+      let files = summarize(&operations, |uri| uri == &a);
+      assert_eq!(files.len(), 2);
+      assert_eq!(files[0].path, "/src/a.rs");
+      assert_eq!(files[0].edit_count, 4);
+      assert!(files[0].is_open);
+      assert_eq!(files[1].kind, FileEditKind::Rename);
+      assert_eq!(files[1].new_path.as_deref(), Some("/src/c.rs"));
+      assert!(!files[1].is_open);
+   }
+
+   #[test]
+   fn test_failure_reason_names_the_first_failed_file() {
+      let outcome = |path: &str, error: Option<&str>| FileEditOutcome {
+         path: path.to_string(),
+         kind: FileEditKind::Edit,
+         success: error.is_none(),
+         error: error.map(str::to_string),
+         new_path: None,
+         text: None,
+         version: None,
+      };
+      let result = WorkspaceEditResult {
+         applied: false,
+         files: vec![
+            outcome("/src/a.rs", None),
+            outcome("/src/b.rs", Some("Overlapping text edits")),
+            outcome("/src/c.rs", Some("File does not exist")),
+         ],
+      };
+      assert_eq!(
+         result.failure_reason().as_deref(),
+         Some("/src/b.rs: Overlapping text edits")
+      );
+
+      let result = WorkspaceEditResult {
+         applied: true,
+         files: vec![outcome("/src/a.rs", None)],
+      };
+      assert_eq!(result.failure_reason(), None);
+   }
+
+   #[test]
+   fn test_file_operations_on_disk() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("a.rs");
+      std::fs::write(&path, "fn a() {}\n").unwrap();
+
+      edit_file(&path, &[edit((0, 3), (0, 4), "b")]).unwrap();
+      assert_eq!(std::fs::read_to_string(&path).unwrap(), "fn b() {}\n");
+
+      // Overlapping edits are rejected before anything is written
+      let overlapping = [edit((0, 0), (0, 5), "x"), edit((0, 3), (0, 7), "y")];
+      assert!(edit_file(&path, &overlapping).is_err());
+      assert_eq!(std::fs::read_to_string(&path).unwrap(), "fn b() {}\n");
+
+      // Edits computed against an older version are refused
+      assert!(check_version(Some(2), 3).is_err());
+      assert!(check_version(Some(3), 3).is_ok());
+      assert!(check_version(None, 3).is_ok());
+
+      // Renames move the file, into new directories too, and don't replace existing files
+      let renamed = dir.path().join("nested/b.rs");
+      let rename = |old: &Path, new: &Path, options| RenameFile {
+         old_uri: uri(old),
+         new_uri: uri(new),
+         options,
+         annotation_id: None,
+      };
+      assert_eq!(
+         rename_file(&rename(&path, &renamed, None)).unwrap(),
+         Some((path.clone(), renamed.clone()))
+      );
+      assert!(!path.exists());
+      assert_eq!(std::fs::read_to_string(&renamed).unwrap(), "fn b() {}\n");
+
+      std::fs::write(&path, "other").unwrap();
+      assert!(rename_file(&rename(&path, &renamed, None)).is_err());
+      let ignore = Some(RenameFileOptions {
+         overwrite: None,
+         ignore_if_exists: Some(true),
+      });
+      assert_eq!(rename_file(&rename(&path, &renamed, ignore)).unwrap(), None);
+      assert_eq!(std::fs::read_to_string(&renamed).unwrap(), "fn b() {}\n");
+
+      // Creating an existing file fails unless told to ignore or overwrite it
+      let create = |options| CreateFile {
+         uri: uri(&path),
+         options,
+         annotation_id: None,
+      };
+      assert!(create_file(&create(None)).is_err());
+      create_file(&create(Some(CreateFileOptions {
+         overwrite: Some(true),
+         ignore_if_exists: None,
+      })))
+      .unwrap();
+      assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+      let delete = DeleteFile {
+         uri: uri(&path),
+         options: None,
+      };
+      assert_eq!(delete_file(&delete).unwrap(), Some(path.clone()));
+      assert!(!path.exists());
+      assert!(delete_file(&delete).is_err());
+   }
+}
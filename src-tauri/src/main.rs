@@ -26,6 +26,7 @@ mod mcp_bridge;
 mod menu;
 use crate::shell::get_shells;
 mod ssh;
+mod syntax;
 mod terminal;
 mod xterm_terminal;
 
@@ -83,6 +84,10 @@ fn main() {
 
          // Set up theme cache
          app.manage(theme::ThemeCache::new(std::collections::HashMap::new()));
+         app.manage(syntax::SyntaxDocuments::default());
+         if let Ok(config_dir) = app.path().app_config_dir() {
+            syntax::load_user_languages(&config_dir);
+         }
 
          // Auto-start interceptor on app launch
          {
@@ -367,6 +372,11 @@ fn main() {
          validate_font,
          // Token commands
          get_tokens,
+         open_syntax_document,
+         get_tokens_in_range,
+         edit_syntax_document,
+         close_syntax_document,
+         reload_user_grammars,
          // SQLite commands
          get_sqlite_tables,
          query_sqlite,
@@ -378,12 +388,50 @@ fn main() {
          lsp_start,
          lsp_stop,
          lsp_get_completions,
+         lsp_resolve_completion,
          lsp_get_hover,
+         lsp_cancel_request,
+         lsp_signature_help,
+         lsp_inlay_hints,
+         lsp_resolve_inlay_hint,
+         lsp_document_symbols,
+         lsp_document_highlights,
+         lsp_document_links,
+         lsp_resolve_document_link,
+         lsp_folding_ranges,
+         lsp_selection_ranges,
+         lsp_prepare_call_hierarchy,
+         lsp_call_hierarchy_calls,
+         lsp_prepare_type_hierarchy,
+         lsp_type_hierarchy_types,
+         lsp_workspace_symbols,
+         lsp_resolve_workspace_symbol,
+         lsp_goto_definition,
+         lsp_goto_declaration,
+         lsp_goto_type_definition,
+         lsp_goto_implementation,
+         lsp_find_references,
+         lsp_prepare_rename,
+         lsp_rename,
+         lsp_apply_workspace_edit,
+         lsp_code_actions,
+         lsp_resolve_code_action,
+         lsp_apply_code_action,
+         lsp_execute_command,
+         lsp_respond_to_message_request,
+         lsp_get_server_capabilities,
+         lsp_restart_server,
          lsp_document_open,
          lsp_document_change,
          lsp_document_close,
          lsp_is_language_supported,
          get_diagnostics,
+         lsp_pull_workspace_diagnostics,
+         lsp_get_trace,
+         lsp_clear_trace,
+         lsp_watch_trace,
+         lsp_export_trace,
+         lsp_set_trace,
          // Fuzzy matching commands
          fuzzy_match,
          fuzzy_find_files,
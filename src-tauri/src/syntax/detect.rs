@@ -0,0 +1,169 @@
+use super::{
+   languages::{language_for_extension, language_for_name},
+   runtime,
+};
+use std::path::Path;
+
+/// Modelines are looked for in this many lines at the start and end of a file, like Vim does.
+const MODELINE_LINES: usize = 5;
+
+/// Picks a language for a file from, in order: a Vim or Emacs modeline, the file types of user
+/// languages, its file name, its extension, and its shebang line.
+pub fn detect_language(path: Option<&str>, extension: &str, content: &str) -> Option<String> {
+   let file_name = path
+      .and_then(|path| Path::new(path).file_name())
+      .and_then(|name| name.to_str());
+   language_from_modeline(content)
+      .or_else(|| runtime::user_language_for_file(file_name, extension))
+      .or_else(|| {
+         file_name
+            .and_then(language_for_file_name)
+            .map(str::to_string)
+      })
+      .or_else(|| language_for_extension(extension).map(str::to_string))
+      .or_else(|| content.lines().next().and_then(language_from_shebang))
+}
+
+/// A built-in language by any of its names, or a user language by its name.
+fn language_named(name: &str) -> Option<String> {
+   language_for_name(name)
+      .map(str::to_string)
+      .or_else(|| runtime::user_language(&name.trim().to_lowercase()).map(|language| language.name))
+}
+
+/// Files recognized by their whole name, which usually have no extension.
+fn language_for_file_name(name: &str) -> Option<&'static str> {
+   Some(match name {
+      ".bashrc" | ".bash_profile" | ".bash_login" | ".bash_logout" | ".profile" | ".zshrc"
+      | ".zshenv" | ".zprofile" | ".envrc" | "PKGBUILD" => "bash",
+      "Cargo.lock" | "Pipfile" | "poetry.lock" | "uv.lock" => "toml",
+      "Gemfile" | "Rakefile" | "Podfile" | "Vagrantfile" | "Brewfile" | "Guardfile"
+      | "Fastfile" => "ruby",
+      ".babelrc" | ".prettierrc" | ".eslintrc" | "composer.lock" | "flake.lock" => "json",
+      "Dockerfile" | "Containerfile" => "dockerfile",
+      "Makefile" | "makefile" | "GNUmakefile" => "make",
+      _ => return None,
+   })
+}
+
+/// `#!/usr/bin/env python3` or `#!/bin/bash`. Version suffixes like `python3.11` are dropped.
+fn language_from_shebang(line: &str) -> Option<String> {
+   let mut words = line.strip_prefix("#!")?.split_whitespace();
+   let mut program = words.next()?.rsplit('/').next()?;
+   if program == "env" {
+      // Skip flags like `-S` and variable assignments
+      program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+   }
+   let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+   language_named(program)
+}
+
+fn language_from_modeline(content: &str) -> Option<String> {
+   content
+      .lines()
+      .take(MODELINE_LINES)
+      .chain(content.lines().rev().take(MODELINE_LINES))
+      .find_map(|line| vim_modeline(line).or_else(|| emacs_modeline(line)))
+}
+
+/// `vim: set ft=ruby:` or `vi: filetype=ruby`, anywhere in a line.
+fn vim_modeline(line: &str) -> Option<String> {
+   let options = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+      line.match_indices(marker).find_map(|(i, _)| {
+         // `ex:` inside a word like `index:` isn't a modeline
+         let at_word_start = line[..i].chars().last().is_none_or(char::is_whitespace);
+         at_word_start.then(|| &line[i + marker.len()..])
+      })
+   })?;
+
+   options
+      .split(|c: char| c.is_whitespace() || c == ':')
+      .find_map(|option| {
+         let (name, value) = option.split_once('=')?;
+         matches!(name, "ft" | "filetype" | "syn" | "syntax").then_some(value)
+      })
+      .and_then(language_named)
+}
+
+/// `-*- mode: ruby -*-`, or just `-*- ruby -*-`.
+fn emacs_modeline(line: &str) -> Option<String> {
+   let start = line.find("-*-")? + 3;
+   let end = start + line[start..].find("-*-")?;
+   let variables = line[start..end].trim();
+   if !variables.contains(':') {
+      return language_named(variables);
+   }
+
+   variables.split(';').find_map(|variable| {
+      let (name, value) = variable.split_once(':')?;
+      if name.trim().eq_ignore_ascii_case("mode") {
+         language_named(value.trim())
+      } else {
+         None
+      }
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_detects_by_modeline_name_extension_and_shebang() {
+      assert_eq!(
+         detect_language(Some("/home/me/.bashrc"), "", "").as_deref(),
+         Some("bash")
+      );
+      assert_eq!(
+         detect_language(Some("/repo/Cargo.lock"), "lock", "").as_deref(),
+         Some("toml")
+      );
+      assert_eq!(
+         detect_language(Some("/repo/Gemfile"), "", "").as_deref(),
+         Some("ruby")
+      );
+      assert_eq!(
+         detect_language(Some("/repo/Dockerfile"), "", "").as_deref(),
+         Some("dockerfile")
+      );
+      assert_eq!(
+         detect_language(Some("/repo/Makefile"), "", "").as_deref(),
+         Some("make")
+      );
+      assert_eq!(detect_language(None, "exs", "").as_deref(), Some("elixir"));
+      assert_eq!(detect_language(None, "tf", "").as_deref(), Some("hcl"));
+      assert_eq!(
+         detect_language(None, "", "#!/usr/bin/env -S python3.11 -u\n").as_deref(),
+         Some("python")
+      );
+      assert_eq!(
+         detect_language(None, "", "#!/bin/sh\necho hi\n").as_deref(),
+         Some("bash")
+      );
+      assert_eq!(
+         detect_language(None, "", "#!/usr/bin/env node\n").as_deref(),
+         Some("javascript")
+      );
+
+      // Modelines win over the extension
+      let vim = "puts 1\n# vim: set ts=2 ft=ruby:\n";
+      assert_eq!(
+         detect_language(Some("/repo/script.txt"), "py", vim).as_deref(),
+         Some("ruby")
+      );
+      let emacs = "#!/bin/sh\n# -*- mode: python; coding: utf-8 -*-\n";
+      assert_eq!(detect_language(None, "", emacs).as_deref(), Some("python"));
+      assert_eq!(
+         detect_language(None, "", "// -*- c++ -*-\n").as_deref(),
+         Some("cpp")
+      );
+      assert_eq!(
+         detect_language(None, "", "index: ft=ruby\n").as_deref(),
+         None
+      );
+      assert_eq!(
+         detect_language(None, "txt", "plain text\n").as_deref(),
+         None
+      );
+   }
+}
@@ -0,0 +1,328 @@
+use super::{
+   highlight::{HighlightConfig, Token, highlight},
+   languages::get_language_config,
+};
+use crate::lsp::documents::position_to_offset;
+use anyhow::{Context, Result};
+use lsp_types::{Position, TextDocumentContentChangeEvent};
+use serde::{Deserialize, Serialize};
+use std::{
+   collections::HashMap,
+   ops::Range,
+   sync::{Arc, Mutex},
+};
+use tree_sitter::{InputEdit, Point, Tree};
+
+/// Lines further than this from an edit are left alone even if the edit is on the same line, so
+/// editing a minified file doesn't repaint all of it.
+const LINE_MARGIN: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ByteRange {
+   pub start: usize,
+   pub end: usize,
+}
+
+/// Part of a document to highlight. Ends are exclusive; lines count from zero.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "unit", rename_all = "lowercase")]
+pub enum TokenRange {
+   Bytes { start: usize, end: usize },
+   Lines { start: u32, end: u32 },
+}
+
+impl TokenRange {
+   pub fn to_bytes(self, text: &str) -> Range<usize> {
+      match self {
+         TokenRange::Bytes { start, end } => start..end,
+         TokenRange::Lines { start, end } => {
+            let offset = |line| position_to_offset(text, Position::new(line, 0));
+            offset(start)..offset(end)
+         }
+      }
+   }
+}
+
+/// Tokens that changed after an edit. The frontend shifts its tokens by the edit, drops those
+/// inside `ranges` and adds `tokens`, which cover exactly those ranges.
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenUpdate {
+   pub ranges: Vec<ByteRange>,
+   pub tokens: Vec<Token>,
+}
+
+/// An open document with its syntax tree, kept so edits reparse incrementally.
+struct SyntaxDocument {
+   config: Arc<HighlightConfig>,
+   tree: Tree,
+   text: String,
+   /// The file it was opened from, so semantic tokens can be asked for
+   path: Option<String>,
+}
+
+/// Syntax trees of open documents, by the frontend's document id.
+#[derive(Default)]
+pub struct SyntaxDocuments {
+   documents: Mutex<HashMap<String, SyntaxDocument>>,
+}
+
+impl SyntaxDocuments {
+   /// Parses a document. Its tokens are asked for with `tokens_in_range`, usually for just the
+   /// visible part. Opening an id again replaces it.
+   pub fn open(&self, id: &str, language: &str, path: Option<String>, text: String) -> Result<()> {
+      let config = get_language_config(language)?;
+      let tree = config.parse(&text, None)?;
+
+      self.documents.lock().unwrap().insert(
+         id.to_string(),
+         SyntaxDocument {
+            config,
+            tree,
+            text,
+            path,
+         },
+      );
+      Ok(())
+   }
+
+   /// The file path and current text of a document opened from a file.
+   pub fn source(&self, id: &str) -> Option<(String, String)> {
+      let documents = self.documents.lock().unwrap();
+      let document = documents.get(id)?;
+      Some((document.path.clone()?, document.text.clone()))
+   }
+
+   /// Tokens of an open document in `range`, clipped to it.
+   pub fn tokens_in_range(&self, id: &str, range: TokenRange) -> Result<Vec<Token>> {
+      let documents = self.documents.lock().unwrap();
+      let document = documents.get(id).context("Document is not open")?;
+
+      Ok(highlight(
+         &document.config,
+         &document.tree,
+         &document.text,
+         range.to_bytes(&document.text),
+      ))
+   }
+
+   /// Applies `didChange`-style changes, reparses incrementally and returns the tokens of the
+   /// lines whose highlighting may have changed.
+   pub fn edit(&self, id: &str, changes: &[TextDocumentContentChangeEvent]) -> Result<TokenUpdate> {
+      let mut documents = self.documents.lock().unwrap();
+      let document = documents.get_mut(id).context("Document is not open")?;
+
+      let mut edited = Vec::new();
+      for change in changes {
+         let Some(range) = change.range else {
+            document.text = change.text.clone();
+            document.tree = document.config.parse(&document.text, None)?;
+            edited = vec![0..document.text.len()];
+            continue;
+         };
+
+         let start = position_to_offset(&document.text, range.start);
+         let end = position_to_offset(&document.text, range.end).max(start);
+         let input_edit = InputEdit {
+            start_byte: start,
+            old_end_byte: end,
+            new_end_byte: start + change.text.len(),
+            start_position: point_at(&document.text, start),
+            old_end_position: point_at(&document.text, end),
+            new_end_position: point_after(point_at(&document.text, start), &change.text),
+         };
+
+         document.text.replace_range(start..end, &change.text);
+         document.tree.edit(&input_edit);
+         edited = shift_ranges(edited, &input_edit);
+      }
+
+      let new_tree = document
+         .config
+         .parse(&document.text, Some(&document.tree))?;
+      let mut changed: Vec<Range<usize>> = document
+         .tree
+         .changed_ranges(&new_tree)
+         .map(|range| range.start_byte..range.end_byte)
+         .chain(edited)
+         .map(|range| expand_to_lines(&document.text, range))
+         .collect();
+      document.tree = new_tree;
+
+      changed.sort_by_key(|range| range.start);
+      let mut ranges: Vec<Range<usize>> = Vec::new();
+      for range in changed {
+         match ranges.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => ranges.push(range),
+         }
+      }
+
+      let tokens = ranges
+         .iter()
+         .flat_map(|range| {
+            highlight(
+               &document.config,
+               &document.tree,
+               &document.text,
+               range.clone(),
+            )
+         })
+         .collect();
+      Ok(TokenUpdate {
+         ranges: ranges
+            .into_iter()
+            .map(|range| ByteRange {
+               start: range.start,
+               end: range.end,
+            })
+            .collect(),
+         tokens,
+      })
+   }
+
+   pub fn close(&self, id: &str) {
+      self.documents.lock().unwrap().remove(id);
+   }
+}
+
+/// Tree-sitter position of a byte offset; columns count bytes.
+fn point_at(text: &str, offset: usize) -> Point {
+   let before = &text[..offset];
+   let row = before.matches('\n').count();
+   let column = before.rfind('\n').map_or(offset, |i| offset - i - 1);
+   Point { row, column }
+}
+
+fn point_after(start: Point, inserted: &str) -> Point {
+   match inserted.rfind('\n') {
+      Some(i) => Point {
+         row: start.row + inserted.matches('\n').count(),
+         column: inserted.len() - i - 1,
+      },
+      None => Point {
+         row: start.row,
+         column: start.column + inserted.len(),
+      },
+   }
+}
+
+/// Moves ranges edited by earlier changes to where they are after `edit`, and adds the text it
+/// inserted.
+fn shift_ranges(ranges: Vec<Range<usize>>, edit: &InputEdit) -> Vec<Range<usize>> {
+   let mut inserted = edit.start_byte..edit.new_end_byte;
+   let mut shifted = Vec::new();
+   for range in ranges {
+      if range.end < edit.start_byte {
+         shifted.push(range);
+      } else if range.start > edit.old_end_byte {
+         shifted.push(
+            range.start + edit.new_end_byte - edit.old_end_byte
+               ..range.end + edit.new_end_byte - edit.old_end_byte,
+         );
+      } else {
+         // Overlaps the replaced text: whatever survives is next to the insertion
+         inserted.start = inserted.start.min(range.start);
+         let tail = range.end.saturating_sub(edit.old_end_byte);
+         inserted.end = inserted.end.max(edit.new_end_byte + tail);
+      }
+   }
+   shifted.push(inserted);
+   shifted
+}
+
+/// Widens a range to whole lines, so tokens are rarely cut off mid-line. Long lines are only
+/// widened by `LINE_MARGIN` on either side.
+fn expand_to_lines(text: &str, range: Range<usize>) -> Range<usize> {
+   let start = range.start.min(text.len());
+   let end = range.end.clamp(start, text.len());
+   let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+   let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+
+   let mut start = line_start.max(start.saturating_sub(LINE_MARGIN));
+   while !text.is_char_boundary(start) {
+      start -= 1;
+   }
+   let mut end = line_end.min(end + LINE_MARGIN);
+   while !text.is_char_boundary(end) {
+      end += 1;
+   }
+   start..end
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use lsp_types::Range as LspRange;
+
+   fn change(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+      TextDocumentContentChangeEvent {
+         range: Some(LspRange::new(
+            Position::new(start.0, start.1),
+            Position::new(end.0, end.1),
+         )),
+         range_length: None,
+         text: text.to_string(),
+      }
+   }
+
+   #[test]
+   fn test_edit_reparses_and_returns_changed_lines() {
+      let documents = SyntaxDocuments::default();
+      let text = "let a = 1;\nlet b = 2;\nlet c = 3;\n".to_string();
+      documents.open("doc", "rust", None, text).unwrap();
+
+      // Typing inside a number only touches its line
+      let update = documents
+         .edit("doc", &[change((1, 8), (1, 9), "42")])
+         .unwrap();
+      assert_eq!(update.ranges, vec![ByteRange { start: 11, end: 22 }]);
+      assert!(
+         update
+            .tokens
+            .iter()
+            .any(|t| t.token_type == "constant" && (t.start, t.end) == (19, 21))
+      );
+
+      // Commenting out two lines in one batch repaints both of them
+      let update = documents
+         .edit(
+            "doc",
+            &[
+               change((0, 0), (0, 0), "/* "),
+               change((1, 11), (1, 11), " */"),
+            ],
+         )
+         .unwrap();
+      assert_eq!(update.ranges, vec![ByteRange { start: 0, end: 28 }]);
+      assert!(
+         update
+            .tokens
+            .iter()
+            .any(|t| t.token_type == "comment" && (t.start, t.end) == (0, 28))
+      );
+
+      // Only the asked-for lines are highlighted
+      let tokens = documents
+         .tokens_in_range("doc", TokenRange::Lines { start: 2, end: 3 })
+         .unwrap();
+      assert!(tokens.iter().all(|t| t.start >= 29 && t.end <= 40));
+      assert!(tokens.iter().any(|t| t.token_type == "keyword"));
+
+      let documents = documents.documents.lock().unwrap();
+      let document = &documents["doc"];
+      assert_eq!(document.text, "/* let a = 1;\nlet b = 42; */\nlet c = 3;\n");
+      let fresh = document.config.parse(&document.text, None).unwrap();
+      assert_eq!(
+         document.tree.root_node().to_sexp(),
+         fresh.root_node().to_sexp()
+      );
+   }
+
+   #[test]
+   fn test_long_lines_are_widened_by_a_margin() {
+      let text = "x".repeat(10 * LINE_MARGIN);
+      let range = expand_to_lines(&text, 5 * LINE_MARGIN..5 * LINE_MARGIN + 1);
+      assert_eq!(range, 4 * LINE_MARGIN..6 * LINE_MARGIN + 1);
+      assert_eq!(expand_to_lines("ab\ncd\nef", 4..4), 3..5);
+   }
+}
@@ -0,0 +1,560 @@
+use super::{
+   languages::injected_language_config,
+   locals::{LocalsQuery, find_locals},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+   collections::{BTreeMap, HashMap, hash_map::Entry},
+   ops::Range,
+};
+use tree_sitter::{Language, Node, Parser, Query, QueryCursor, StreamingIterator, Tree};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Token {
+   pub start: usize,
+   pub end: usize,
+   pub token_type: String,
+   pub class_name: String,
+   /// The capture name from the highlight query, like `function.method`. Absent for tokens
+   /// that didn't come from tree-sitter.
+   pub capture: Option<String>,
+}
+
+// Standard highlight names used by Tree-sitter
+const HIGHLIGHT_NAMES: &[&str] = &[
+   "attribute",
+   "comment",
+   "constant",
+   "constant.builtin",
+   "constructor",
+   "embedded",
+   "error",
+   "function",
+   "function.builtin",
+   "function.method",
+   "keyword",
+   "keyword.control",
+   "keyword.function",
+   "keyword.operator",
+   "keyword.return",
+   "module",
+   "number",
+   "operator",
+   "property",
+   "property.builtin",
+   "punctuation",
+   "punctuation.bracket",
+   "punctuation.delimiter",
+   "punctuation.special",
+   "string",
+   "string.escape",
+   "string.special",
+   "tag",
+   "type",
+   "type.builtin",
+   "variable",
+   "variable.builtin",
+   "variable.parameter",
+];
+
+fn map_highlight_to_class(highlight_name: &str) -> (&str, &str) {
+   match highlight_name {
+      "keyword" | "keyword.control" | "keyword.function" | "keyword.operator"
+      | "keyword.return" => ("keyword", "token-keyword"),
+      "string" | "string.escape" | "string.special" => ("string", "token-string"),
+      "number" => ("number", "token-number"),
+      "constant" | "constant.builtin" => ("constant", "token-constant"),
+      "comment" => ("comment", "token-comment"),
+      "function" | "function.builtin" | "function.method" => ("function", "token-function"),
+      "type" | "type.builtin" => ("type", "token-type"),
+      "variable" | "variable.builtin" | "variable.parameter" => ("identifier", "token-identifier"),
+      "property" | "property.builtin" => ("property", "token-property"),
+      "operator" => ("operator", "token-operator"),
+      "punctuation" | "punctuation.bracket" | "punctuation.delimiter" | "punctuation.special" => {
+         ("punctuation", "token-punctuation")
+      }
+      "tag" => ("jsx", "token-jsx"),
+      "attribute" => ("jsx-attribute", "token-jsx-attribute"),
+      _ => ("text", "token-text"),
+   }
+}
+
+/// The most specific recognized name a capture falls under, like tree-sitter-highlight does:
+/// `function.method.call` becomes `function.method`, and `spell` nothing.
+fn resolve_highlight(capture_name: &str) -> Option<usize> {
+   let parts: Vec<&str> = capture_name.split('.').collect();
+   HIGHLIGHT_NAMES
+      .iter()
+      .enumerate()
+      .filter(|(_, name)| {
+         let name_parts: Vec<&str> = name.split('.').collect();
+         name_parts.len() <= parts.len() && parts[..name_parts.len()] == name_parts[..]
+      })
+      .max_by_key(|(_, name)| name.len())
+      .map(|(index, _)| index)
+}
+
+/// Injected languages nest at most this deep, so grammars injecting themselves (like Rust
+/// macros) stay bounded.
+const MAX_INJECTION_DEPTH: usize = 4;
+
+/// A grammar with its compiled highlight query, and optionally the query finding other
+/// languages embedded in it.
+pub struct HighlightConfig {
+   pub language: Language,
+   query: Query,
+   /// The highlight each capture of the query maps to, if any
+   highlight_indices: Vec<Option<usize>>,
+   /// Patterns marked `(#is-not? local)`, which don't apply to local variables
+   non_local_patterns: Vec<bool>,
+   injections: Option<InjectionQuery>,
+   locals: Option<LocalsQuery>,
+}
+
+struct InjectionQuery {
+   query: Query,
+   content_capture: Option<u32>,
+   language_capture: Option<u32>,
+}
+
+impl HighlightConfig {
+   pub fn new(language: Language, highlights_query: &str) -> Result<Self> {
+      let query = Query::new(&language, highlights_query).context("Invalid highlights query")?;
+      let highlight_indices = query
+         .capture_names()
+         .iter()
+         .map(|name| resolve_highlight(name))
+         .collect();
+      let non_local_patterns = (0..query.pattern_count())
+         .map(|pattern| {
+            query
+               .property_predicates(pattern)
+               .iter()
+               .any(|(property, is_positive)| !is_positive && &*property.key == "local")
+         })
+         .collect();
+      Ok(Self {
+         language,
+         query,
+         highlight_indices,
+         non_local_patterns,
+         injections: None,
+         locals: None,
+      })
+   }
+
+   /// Adds an injections query, using the `injection.content` and `injection.language`
+   /// captures and `#set!` properties tree-sitter-highlight understands.
+   pub fn with_injections(mut self, injections_query: &str) -> Result<Self> {
+      let query =
+         Query::new(&self.language, injections_query).context("Invalid injections query")?;
+      self.injections = Some(InjectionQuery {
+         content_capture: query.capture_index_for_name("injection.content"),
+         language_capture: query.capture_index_for_name("injection.language"),
+         query,
+      });
+      Ok(self)
+   }
+
+   /// Adds a locals query, so references to a local variable are highlighted like its
+   /// definition, e.g. as a parameter.
+   pub fn with_locals(mut self, locals_query: &str) -> Result<Self> {
+      self.locals = Some(LocalsQuery::new(&self.language, locals_query)?);
+      Ok(self)
+   }
+
+   /// Parses `text`, reusing `old_tree` if it has been edited to match.
+   pub fn parse(&self, text: &str, old_tree: Option<&Tree>) -> Result<Tree> {
+      let mut parser = Parser::new();
+      parser.set_language(&self.language)?;
+      parser.parse(text, old_tree).context("Failed to parse")
+   }
+
+   /// Parses only the parts of `text` in `ranges`, as if they were one document.
+   fn parse_ranges(&self, text: &str, ranges: &[tree_sitter::Range]) -> Result<Tree> {
+      let mut parser = Parser::new();
+      parser.set_language(&self.language)?;
+      parser.set_included_ranges(ranges)?;
+      parser.parse(text, None).context("Failed to parse")
+   }
+}
+
+/// A capture to paint, in the order of the query pattern that produced it.
+struct Span<'a> {
+   start: usize,
+   end: usize,
+   pattern: usize,
+   highlight: usize,
+   capture: &'a str,
+}
+
+/// The highlights of the range being highlighted, as runs of bytes painted by the same capture.
+/// Every paint gets its own id, so neighbouring captures with the same highlight still become
+/// separate tokens. Runs are kept rather than a highlight per byte, so memory grows with the
+/// number of captures instead of the size of the range.
+struct Canvas {
+   range: Range<usize>,
+   /// Start of each run and the paint covering it, up to the start of the next run
+   runs: BTreeMap<usize, Option<usize>>,
+   /// Highlight and capture name of each paint
+   highlights: Vec<(usize, String)>,
+}
+
+impl Canvas {
+   fn new(range: Range<usize>) -> Self {
+      Self {
+         runs: BTreeMap::from([(range.start, None)]),
+         range,
+         highlights: Vec::new(),
+      }
+   }
+
+   fn paint(&mut self, span: Range<usize>, highlight: usize, capture: &str) {
+      let start = span.start.max(self.range.start);
+      let end = span.end.min(self.range.end);
+      if start >= end {
+         return;
+      }
+
+      let id = self.highlights.len();
+      self.highlights.push((highlight, capture.to_string()));
+
+      // Whatever was under the end of the span continues after it
+      let after = self.runs.range(..=end).next_back().and_then(|(_, id)| *id);
+      let covered: Vec<usize> = self
+         .runs
+         .range(start..end)
+         .map(|(start, _)| *start)
+         .collect();
+      for run in covered {
+         self.runs.remove(&run);
+      }
+      self.runs.insert(start, Some(id));
+      if end < self.range.end {
+         self.runs.entry(end).or_insert(after);
+      }
+   }
+
+   fn into_tokens(self, text: &str) -> Vec<Token> {
+      let mut tokens = Vec::new();
+      let mut runs = self.runs.into_iter().peekable();
+      while let Some((start, current)) = runs.next() {
+         // Runs split by a paint that ended up overwritten still belong together
+         while runs.next_if(|(_, id)| *id == current).is_some() {}
+         let end = runs.peek().map_or(self.range.end, |(start, _)| *start);
+
+         // Skip whitespace-only tokens, and runs that split a character because the range did
+         if let Some(id) = current
+            && let Some(text) = text.get(start..end)
+            && !text.trim().is_empty()
+         {
+            let (highlight, capture) = &self.highlights[id];
+            let (token_type, class_name) = map_highlight_to_class(HIGHLIGHT_NAMES[*highlight]);
+            // Dotted captures also get a class of their own, like `token-function-method`
+            let class_name = if capture.contains('.') {
+               format!("{} token-{}", class_name, capture.replace('.', "-"))
+            } else {
+               class_name.to_string()
+            };
+            tokens.push(Token {
+               start,
+               end,
+               token_type: token_type.to_string(),
+               class_name,
+               capture: Some(capture.clone()),
+            });
+         }
+      }
+      tokens
+   }
+}
+
+/// Tokens for the part of `text` in `range`. Where captures nest, the innermost one wins; where
+/// several patterns capture the same node, the last in the query wins, as with
+/// tree-sitter-highlight. Injected languages are highlighted on top of the text they're
+/// embedded in. Tokens are clipped to `range`.
+pub fn highlight(
+   config: &HighlightConfig,
+   tree: &Tree,
+   text: &str,
+   range: Range<usize>,
+) -> Vec<Token> {
+   let range = range.start.min(text.len())..range.end.min(text.len());
+   if range.is_empty() {
+      return Vec::new();
+   }
+
+   let mut canvas = Canvas::new(range);
+   paint_layer(&mut canvas, config, tree, text, None, 0);
+   canvas.into_tokens(text)
+}
+
+/// Paints one language layer, then the layers injected into it. `included` limits an injected
+/// layer to the text it was parsed from; `None` means all of it.
+fn paint_layer(
+   canvas: &mut Canvas,
+   config: &HighlightConfig,
+   tree: &Tree,
+   text: &str,
+   included: Option<&[Range<usize>]>,
+   depth: usize,
+) {
+   let locals = config
+      .locals
+      .as_ref()
+      .map(|locals| find_locals(locals, tree, text, canvas.range.clone()));
+   // Definitions can be outside the range, and their highlights are needed for references
+   let query_range = locals
+      .as_ref()
+      .map_or(canvas.range.clone(), |locals| locals.range.clone());
+
+   let capture_names = config.query.capture_names();
+   let mut spans = Vec::new();
+   let mut cursor = QueryCursor::new();
+   cursor.set_byte_range(query_range);
+   let mut matches = cursor.matches(&config.query, tree.root_node(), text.as_bytes());
+   while let Some(m) = matches.next() {
+      for capture in m.captures {
+         let Some(highlight) = config.highlight_indices[capture.index as usize] else {
+            continue;
+         };
+         let node = (capture.node.start_byte(), capture.node.end_byte());
+         if config.non_local_patterns[m.pattern_index]
+            && locals.as_ref().is_some_and(|locals| locals.contains(node))
+         {
+            continue;
+         }
+         spans.push(Span {
+            start: node.0,
+            end: node.1,
+            pattern: m.pattern_index,
+            highlight,
+            capture: capture_names[capture.index as usize],
+         });
+      }
+   }
+
+   // Outer spans are painted first so inner ones overwrite them, and for the same node later
+   // patterns overwrite earlier ones
+   spans.sort_by_key(|span| (span.start, std::cmp::Reverse(span.end), span.pattern));
+
+   // References to a local take the highlight its definition ended up with
+   if let Some(locals) = &locals
+      && !locals.references.is_empty()
+   {
+      let definitions: HashMap<(usize, usize), (usize, &str)> = spans
+         .iter()
+         .filter(|span| locals.definitions.contains(&(span.start, span.end)))
+         .map(|span| ((span.start, span.end), (span.highlight, span.capture)))
+         .collect();
+      for span in &mut spans {
+         if let Some(definition) = locals.references.get(&(span.start, span.end))
+            && let Some((highlight, capture)) = definitions.get(definition)
+         {
+            span.highlight = *highlight;
+            span.capture = capture;
+         }
+      }
+   }
+
+   for span in &spans {
+      match included {
+         None => canvas.paint(span.start..span.end, span.highlight, span.capture),
+         Some(included) => {
+            // Injected trees can span the host text between their ranges; that isn't theirs
+            let first = included.partition_point(|range| range.end <= span.start);
+            for range in included[first..]
+               .iter()
+               .take_while(|range| range.start < span.end)
+            {
+               canvas.paint(
+                  span.start.max(range.start)..span.end.min(range.end),
+                  span.highlight,
+                  span.capture,
+               );
+            }
+         }
+      }
+   }
+
+   if depth >= MAX_INJECTION_DEPTH {
+      return;
+   }
+   for (language, ranges) in injections(config, tree, text, canvas.range.clone()) {
+      let Some(injected) = injected_language_config(&language) else {
+         continue;
+      };
+      let tree = match injected.parse_ranges(text, &ranges) {
+         Ok(tree) => tree,
+         Err(e) => {
+            log::debug!("Failed to parse injected {}: {}", language, e);
+            continue;
+         }
+      };
+      let included: Vec<Range<usize>> = ranges
+         .iter()
+         .map(|range| range.start_byte..range.end_byte)
+         .collect();
+      paint_layer(canvas, &injected, &tree, text, Some(&included), depth + 1);
+   }
+}
+
+/// Languages embedded in `range` of a tree, with the ranges of text each is parsed from.
+/// Combined injections put all their matches in one layer, like the Ruby of an ERB template.
+fn injections(
+   config: &HighlightConfig,
+   tree: &Tree,
+   text: &str,
+   range: Range<usize>,
+) -> Vec<(String, Vec<tree_sitter::Range>)> {
+   let Some(injections) = &config.injections else {
+      return Vec::new();
+   };
+
+   let mut layers: Vec<(String, Vec<tree_sitter::Range>)> = Vec::new();
+   let mut combined_layers: HashMap<(usize, String), usize> = HashMap::new();
+   let mut cursor = QueryCursor::new();
+   cursor.set_byte_range(range);
+   let mut matches = cursor.matches(&injections.query, tree.root_node(), text.as_bytes());
+   while let Some(m) = matches.next() {
+      let mut language = None;
+      let mut combined = false;
+      let mut include_children = false;
+      for property in injections.query.property_settings(m.pattern_index) {
+         match property.key.as_ref() {
+            "injection.language" => language = property.value.as_deref().map(str::to_string),
+            "injection.combined" => combined = true,
+            "injection.include-children" => include_children = true,
+            _ => {}
+         }
+      }
+
+      let mut ranges = Vec::new();
+      for capture in m.captures {
+         if Some(capture.index) == injections.language_capture {
+            language = capture
+               .node
+               .utf8_text(text.as_bytes())
+               .ok()
+               .map(str::to_string);
+         } else if Some(capture.index) == injections.content_capture {
+            content_ranges(capture.node, include_children, &mut ranges);
+         }
+      }
+      let Some(language) = language else {
+         continue;
+      };
+      if ranges.is_empty() {
+         continue;
+      }
+
+      if combined {
+         match combined_layers.entry((m.pattern_index, language.clone())) {
+            Entry::Occupied(entry) => layers[*entry.get()].1.extend(ranges),
+            Entry::Vacant(entry) => {
+               entry.insert(layers.len());
+               layers.push((language, ranges));
+            }
+         }
+      } else {
+         layers.push((language, ranges));
+      }
+   }
+
+   for (_, ranges) in &mut layers {
+      // Included ranges must be ordered and can't overlap
+      ranges.sort_by_key(|range| range.start_byte);
+      ranges.dedup_by(|range, previous| range.start_byte < previous.end_byte);
+   }
+   layers
+}
+
+/// The ranges of an injected node. Unless children are included, the text of its named child
+/// nodes belongs to the host language and is left out. Anonymous children are kept, since some
+/// grammars (like Markdown's) emit punctuation inside the injected text as tokens.
+fn content_ranges(node: Node, include_children: bool, ranges: &mut Vec<tree_sitter::Range>) {
+   if include_children {
+      ranges.push(node.range());
+      return;
+   }
+
+   let mut start = node.range();
+   let mut cursor = node.walk();
+   for child in node.named_children(&mut cursor) {
+      if child.start_byte() > start.start_byte {
+         ranges.push(tree_sitter::Range {
+            end_byte: child.start_byte(),
+            end_point: child.start_position(),
+            ..start
+         });
+      }
+      start.start_byte = child.end_byte();
+      start.start_point = child.end_position();
+   }
+   if start.end_byte > start.start_byte {
+      ranges.push(start);
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::syntax::{get_language_config, locals::MAX_WIDENING};
+
+   #[test]
+   fn test_locals_of_a_minified_file_stay_near_the_viewport() {
+      let config = get_language_config("javascript").unwrap();
+      let mut text = String::from("(function(){");
+      while text.len() < 1024 * 1024 {
+         text.push_str("var a=1;function f(b){return a+b}f(a);");
+      }
+      text.push_str("})();");
+      assert!(!text.contains('\n'));
+      let tree = config.parse(&text, None).unwrap();
+
+      let viewport = text.len() / 2..text.len() / 2 + 2000;
+      let locals = find_locals(
+         config.locals.as_ref().unwrap(),
+         &tree,
+         &text,
+         viewport.clone(),
+      );
+      assert!(locals.range.start >= viewport.start - MAX_WIDENING);
+      assert!(locals.range.end <= viewport.end + MAX_WIDENING);
+      assert!(!locals.references.is_empty());
+
+      let tokens = highlight(&config, &tree, &text, viewport.clone());
+      assert!(!tokens.is_empty());
+      assert!(
+         tokens
+            .iter()
+            .all(|token| token.start >= viewport.start && token.end <= viewport.end)
+      );
+   }
+
+   #[test]
+   fn test_canvas_keeps_runs_of_nested_paints() {
+      let text = "let value = call(arg);";
+      let mut canvas = Canvas::new(4..text.len());
+      canvas.paint(0..text.len(), 0, "variable");
+      canvas.paint(12..20, 1, "function.call");
+      canvas.paint(17..20, 0, "variable");
+      canvas.paint(17..20, 2, "keyword");
+
+      let tokens: Vec<(usize, usize, String)> = canvas
+         .into_tokens(text)
+         .into_iter()
+         .map(|token| (token.start, token.end, token.capture.unwrap()))
+         .collect();
+      assert_eq!(
+         tokens,
+         vec![
+            (4, 12, "variable".to_string()),
+            (12, 17, "function.call".to_string()),
+            (17, 20, "keyword".to_string()),
+            (20, 22, "variable".to_string()),
+         ]
+      );
+   }
+}
@@ -0,0 +1,426 @@
+use super::{
+   highlight::HighlightConfig,
+   runtime::{self, UserGrammars, UserLanguage},
+};
+use anyhow::{Context, Result, bail};
+use std::{
+   borrow::Cow,
+   collections::HashMap,
+   sync::{Arc, Mutex},
+};
+use tree_sitter::Language;
+
+// The grammar ships this as injections-text.scm but doesn't export it; it highlights the HTML
+// around `<?php` tags
+const PHP_TEXT_INJECTIONS_QUERY: &str = r#"((text) @injection.content
+ (#set! injection.language "html")
+ (#set! injection.combined))"#;
+
+// Neither the JavaScript nor the TypeScript queries cover decorators
+const TYPESCRIPT_DECORATORS_QUERY: &str = r#"(decorator "@" @function.decorator)
+(decorator (identifier) @function.decorator)
+(decorator (call_expression function: (identifier) @function.decorator))
+(decorator (member_expression property: (property_identifier) @function.decorator))"#;
+
+// The Make queries use older capture names (`@conditional`, `@include`, `@repeat`) for these
+const MAKE_KEYWORDS_QUERY: &str = r#"["ifeq" "ifneq" "ifdef" "ifndef" "else" "endif" "foreach"] @keyword.control
+["include" "sinclude" "-include"] @keyword"#;
+
+lazy_static::lazy_static! {
+    // Compiled queries by language name, kept until user grammars are reloaded
+    static ref CONFIGS: Mutex<HashMap<String, Arc<HighlightConfig>>> = Mutex::new(HashMap::new());
+    // Why languages failed to compile, so injections of them aren't retried on every highlight
+    static ref FAILED_CONFIGS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+/// The highlight configuration for a language, compiled on first use.
+pub fn get_language_config(language_name: &str) -> Result<Arc<HighlightConfig>> {
+   if let Some(config) = CONFIGS.lock().unwrap().get(language_name) {
+      return Ok(config.clone());
+   }
+   if let Some(error) = FAILED_CONFIGS.lock().unwrap().get(language_name) {
+      bail!("{}", error);
+   }
+
+   // Compiling can take a while, so it happens outside the lock. Two threads racing on the
+   // same language both compile it, and the first one's result is kept.
+   let config = match compile_language_config(language_name, &runtime::user_grammars()) {
+      Ok(config) => Arc::new(config),
+      Err(e) => {
+         FAILED_CONFIGS
+            .lock()
+            .unwrap()
+            .insert(language_name.to_string(), format!("{:#}", e));
+         return Err(e);
+      }
+   };
+   Ok(CONFIGS
+      .lock()
+      .unwrap()
+      .entry(language_name.to_string())
+      .or_insert(config)
+      .clone())
+}
+
+/// Compiles a language's queries, taking the language and query overrides from `user_grammars`.
+pub(super) fn compile_language_config(
+   language_name: &str,
+   user_grammars: &UserGrammars,
+) -> Result<HighlightConfig> {
+   let user_source = |user: &UserLanguage| -> Result<LanguageSource> {
+      Ok(LanguageSource {
+         language: user_grammars.load_grammar(&user.grammar)?,
+         highlights: runtime::read_query(user, "highlights")?
+            .with_context(|| format!("{:?} has no highlights.scm", user.queries))?
+            .into(),
+         injections: runtime::read_query(user, "injections")?.map(Into::into),
+         locals: runtime::read_query(user, "locals")?.map(Into::into),
+      })
+   };
+   let source = match user_grammars.language(language_name).map(user_source) {
+      Some(Ok(source)) => source,
+      // A broken user grammar for a language we ship shouldn't cost its highlighting
+      Some(Err(e)) => match builtin_language(language_name) {
+         Some(source) => {
+            log::warn!(
+               "Using the built-in {} grammar, the user one failed: {:#}",
+               language_name,
+               e
+            );
+            source
+         }
+         None => return Err(e),
+      },
+      None => builtin_language(language_name)
+         .with_context(|| format!("Unsupported language: {}", language_name))?,
+   };
+
+   // Queries in the user config directory replace the ones the language comes with
+   let query = |kind, default: Option<Cow<'static, str>>| {
+      user_grammars
+         .query_override(language_name, kind)
+         .map(Cow::Owned)
+         .or(default)
+   };
+   let highlights = query("highlights", Some(source.highlights)).unwrap_or_default();
+   let mut config = HighlightConfig::new(source.language, &highlights)?;
+   if let Some(injections) = query("injections", source.injections) {
+      config = config.with_injections(&injections)?;
+   }
+   if let Some(locals) = query("locals", source.locals) {
+      config = config.with_locals(&locals)?;
+   }
+   Ok(config)
+}
+
+/// A grammar with the source of its queries.
+struct LanguageSource {
+   language: Language,
+   highlights: Cow<'static, str>,
+   injections: Option<Cow<'static, str>>,
+   locals: Option<Cow<'static, str>>,
+}
+
+impl LanguageSource {
+   fn new(language: Language, highlights: impl Into<Cow<'static, str>>) -> Self {
+      Self {
+         language,
+         highlights: highlights.into(),
+         injections: None,
+         locals: None,
+      }
+   }
+
+   fn injections(mut self, query: impl Into<Cow<'static, str>>) -> Self {
+      self.injections = Some(query.into());
+      self
+   }
+
+   fn locals(mut self, query: impl Into<Cow<'static, str>>) -> Self {
+      self.locals = Some(query.into());
+      self
+   }
+}
+
+/// The compiled-in grammar and queries of a language.
+fn builtin_language(language_name: &str) -> Option<LanguageSource> {
+   Some(match language_name {
+      "javascript" | "js" => LanguageSource::new(
+         tree_sitter_javascript::LANGUAGE.into(),
+         format!(
+            "{}\n{}",
+            tree_sitter_javascript::HIGHLIGHT_QUERY,
+            tree_sitter_javascript::JSX_HIGHLIGHT_QUERY
+         ),
+      )
+      .injections(tree_sitter_javascript::INJECTIONS_QUERY)
+      .locals(tree_sitter_javascript::LOCALS_QUERY),
+      "typescript" | "ts" => {
+         // TypeScript's queries only cover what it adds, so they're layered on JavaScript's.
+         // Later patterns win, so TypeScript's come last.
+         LanguageSource::new(
+            tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            format!(
+               "{}\n{}\n{}",
+               tree_sitter_javascript::HIGHLIGHT_QUERY,
+               tree_sitter_typescript::HIGHLIGHTS_QUERY,
+               TYPESCRIPT_DECORATORS_QUERY
+            ),
+         )
+         .injections(tree_sitter_javascript::INJECTIONS_QUERY)
+         .locals(format!(
+            "{}\n{}",
+            tree_sitter_javascript::LOCALS_QUERY,
+            tree_sitter_typescript::LOCALS_QUERY
+         ))
+      }
+      "tsx" => {
+         // TSX also takes the JSX highlights
+         LanguageSource::new(
+            tree_sitter_typescript::LANGUAGE_TSX.into(),
+            format!(
+               "{}\n{}\n{}\n{}",
+               tree_sitter_javascript::HIGHLIGHT_QUERY,
+               tree_sitter_typescript::HIGHLIGHTS_QUERY,
+               TYPESCRIPT_DECORATORS_QUERY,
+               tree_sitter_javascript::JSX_HIGHLIGHT_QUERY
+            ),
+         )
+         .injections(tree_sitter_javascript::INJECTIONS_QUERY)
+         .locals(format!(
+            "{}\n{}",
+            tree_sitter_javascript::LOCALS_QUERY,
+            tree_sitter_typescript::LOCALS_QUERY
+         ))
+      }
+      "json" => LanguageSource::new(
+         tree_sitter_json::LANGUAGE.into(),
+         tree_sitter_json::HIGHLIGHTS_QUERY,
+      ),
+      "yaml" | "yml" => LanguageSource::new(
+         tree_sitter_yaml::LANGUAGE.into(),
+         tree_sitter_yaml::HIGHLIGHTS_QUERY,
+      ),
+      "go" => LanguageSource::new(
+         tree_sitter_go::LANGUAGE.into(),
+         tree_sitter_go::HIGHLIGHTS_QUERY,
+      ),
+      "ruby" | "rb" => LanguageSource::new(
+         tree_sitter_ruby::LANGUAGE.into(),
+         tree_sitter_ruby::HIGHLIGHTS_QUERY,
+      )
+      .locals(tree_sitter_ruby::LOCALS_QUERY),
+      "rust" | "rs" => LanguageSource::new(
+         tree_sitter_rust::LANGUAGE.into(),
+         tree_sitter_rust::HIGHLIGHTS_QUERY,
+      )
+      .injections(tree_sitter_rust::INJECTIONS_QUERY),
+      "erb" | "html.erb" => LanguageSource::new(
+         tree_sitter_embedded_template::LANGUAGE.into(),
+         tree_sitter_embedded_template::HIGHLIGHTS_QUERY,
+      )
+      .injections(tree_sitter_embedded_template::INJECTIONS_ERB_QUERY),
+      "python" | "py" => LanguageSource::new(
+         tree_sitter_python::LANGUAGE.into(),
+         tree_sitter_python::HIGHLIGHTS_QUERY,
+      ),
+      "html" => LanguageSource::new(
+         tree_sitter_html::LANGUAGE.into(),
+         tree_sitter_html::HIGHLIGHTS_QUERY,
+      )
+      .injections(tree_sitter_html::INJECTIONS_QUERY),
+      "css" => LanguageSource::new(
+         tree_sitter_css::LANGUAGE.into(),
+         tree_sitter_css::HIGHLIGHTS_QUERY,
+      ),
+      "markdown" | "md" => LanguageSource::new(
+         tree_sitter_md::LANGUAGE.into(),
+         tree_sitter_md::HIGHLIGHT_QUERY_BLOCK,
+      )
+      .injections(tree_sitter_md::INJECTION_QUERY_BLOCK),
+      // Only reached through injections from "markdown"
+      "markdown_inline" => LanguageSource::new(
+         tree_sitter_md::INLINE_LANGUAGE.into(),
+         tree_sitter_md::HIGHLIGHT_QUERY_INLINE,
+      )
+      .injections(tree_sitter_md::INJECTION_QUERY_INLINE),
+      "bash" | "sh" => LanguageSource::new(
+         tree_sitter_bash::LANGUAGE.into(),
+         tree_sitter_bash::HIGHLIGHT_QUERY,
+      ),
+      "toml" => LanguageSource::new(
+         tree_sitter_toml_ng::LANGUAGE.into(),
+         tree_sitter_toml_ng::HIGHLIGHTS_QUERY,
+      ),
+      "java" => LanguageSource::new(
+         tree_sitter_java::LANGUAGE.into(),
+         tree_sitter_java::HIGHLIGHTS_QUERY,
+      ),
+      "c" => LanguageSource::new(
+         tree_sitter_c::LANGUAGE.into(),
+         tree_sitter_c::HIGHLIGHT_QUERY,
+      ),
+      "cpp" | "cxx" | "cc" => LanguageSource::new(
+         tree_sitter_cpp::LANGUAGE.into(),
+         tree_sitter_cpp::HIGHLIGHT_QUERY,
+      ),
+      "php" => LanguageSource::new(
+         tree_sitter_php::LANGUAGE_PHP.into(),
+         tree_sitter_php::HIGHLIGHTS_QUERY,
+      )
+      .injections(format!(
+         "{}\n{}",
+         tree_sitter_php::INJECTIONS_QUERY,
+         PHP_TEXT_INJECTIONS_QUERY
+      )),
+      "csharp" => LanguageSource::new(
+         tree_sitter_c_sharp::LANGUAGE.into(),
+         include_str!("queries/c_sharp/highlights.scm"),
+      ),
+      "elixir" => LanguageSource::new(
+         tree_sitter_elixir::LANGUAGE.into(),
+         tree_sitter_elixir::HIGHLIGHTS_QUERY,
+      )
+      .injections(tree_sitter_elixir::INJECTIONS_QUERY),
+      "swift" => LanguageSource::new(
+         tree_sitter_swift::LANGUAGE.into(),
+         tree_sitter_swift::HIGHLIGHTS_QUERY,
+      )
+      .injections(tree_sitter_swift::INJECTIONS_QUERY)
+      .locals(tree_sitter_swift::LOCALS_QUERY),
+      "lua" => LanguageSource::new(
+         tree_sitter_lua::LANGUAGE.into(),
+         tree_sitter_lua::HIGHLIGHTS_QUERY,
+      )
+      .injections(tree_sitter_lua::INJECTIONS_QUERY)
+      .locals(tree_sitter_lua::LOCALS_QUERY),
+      "kotlin" => LanguageSource::new(
+         tree_sitter_kotlin_ng::LANGUAGE.into(),
+         include_str!("queries/kotlin/highlights.scm"),
+      ),
+      "sql" => LanguageSource::new(
+         tree_sitter_sequel::LANGUAGE.into(),
+         tree_sitter_sequel::HIGHLIGHTS_QUERY,
+      ),
+      "dockerfile" => LanguageSource::new(
+         tree_sitter_containerfile::LANGUAGE.into(),
+         tree_sitter_containerfile::HIGHLIGHTS_QUERY,
+      )
+      .injections(tree_sitter_containerfile::INJECTIONS_QUERY),
+      "zig" => LanguageSource::new(
+         tree_sitter_zig::LANGUAGE.into(),
+         tree_sitter_zig::HIGHLIGHTS_QUERY,
+      )
+      .injections(tree_sitter_zig::INJECTIONS_QUERY),
+      "haskell" => LanguageSource::new(
+         tree_sitter_haskell::LANGUAGE.into(),
+         tree_sitter_haskell::HIGHLIGHTS_QUERY,
+      )
+      .injections(tree_sitter_haskell::INJECTIONS_QUERY)
+      .locals(tree_sitter_haskell::LOCALS_QUERY),
+      "scala" => LanguageSource::new(
+         tree_sitter_scala::LANGUAGE.into(),
+         tree_sitter_scala::HIGHLIGHTS_QUERY,
+      )
+      .locals(tree_sitter_scala::LOCALS_QUERY),
+      "nix" => LanguageSource::new(
+         tree_sitter_nix::LANGUAGE.into(),
+         tree_sitter_nix::HIGHLIGHTS_QUERY,
+      )
+      .injections(tree_sitter_nix::INJECTIONS_QUERY),
+      "hcl" => LanguageSource::new(
+         tree_sitter_hcl::LANGUAGE.into(),
+         include_str!("queries/hcl/highlights.scm"),
+      ),
+      "make" => LanguageSource::new(
+         tree_sitter_make::LANGUAGE.into(),
+         format!(
+            "{}\n{}",
+            tree_sitter_make::HIGHLIGHTS_QUERY,
+            MAKE_KEYWORDS_QUERY
+         ),
+      ),
+      _ => return None,
+   })
+}
+
+/// Drops compiled configs and remembered failures, so the next use picks up changed user grammars
+/// and queries. Documents already open keep the config they were opened with.
+pub(super) fn clear_language_configs() {
+   CONFIGS.lock().unwrap().clear();
+   FAILED_CONFIGS.lock().unwrap().clear();
+}
+
+/// The config for a language named by an injection, which may be a language name, a file
+/// extension, or whatever a Markdown code fence says.
+pub(super) fn injected_language_config(name: &str) -> Option<Arc<HighlightConfig>> {
+   let language = language_for_name(name)
+      .map(Cow::Borrowed)
+      .unwrap_or_else(|| Cow::Owned(name.trim().to_lowercase()));
+   get_language_config(&language).ok()
+}
+
+/// The language a name refers to, as found in injections, code fences, shebangs and
+/// modelines. Extensions are accepted too.
+pub fn language_for_name(name: &str) -> Option<&'static str> {
+   let name = name.trim().to_lowercase();
+   Some(match name.as_str() {
+      "javascript" | "node" | "nodejs" => "javascript",
+      "typescript" | "deno" | "ts-node" | "bun" => "typescript",
+      "python" => "python",
+      "rust" => "rust",
+      "golang" => "go",
+      "shell" | "zsh" | "dash" | "ksh" => "bash",
+      "markdown_inline" => "markdown_inline",
+      "csharp" | "c_sharp" | "c#" => "csharp",
+      "elixir" => "elixir",
+      "swift" => "swift",
+      "luajit" => "lua",
+      "postgresql" | "postgres" | "mysql" | "sqlite" => "sql",
+      "docker" | "containerfile" => "dockerfile",
+      "runhaskell" | "runghc" => "haskell",
+      "nix-shell" => "nix",
+      "terraform" => "hcl",
+      "makefile" | "gmake" => "make",
+      _ => return language_for_extension(&name),
+   })
+}
+
+/// The language `get_language_config` knows a file extension by.
+pub fn language_for_extension(extension: &str) -> Option<&'static str> {
+   Some(match extension {
+      "js" | "jsx" => "javascript",
+      "ts" => "typescript",
+      "tsx" => "tsx",
+      "json" => "json",
+      "yml" | "yaml" => "yaml",
+      "go" => "go",
+      "rb" | "ruby" => "ruby",
+      "rs" => "rust",
+      "erb" | "html.erb" => "erb",
+      "py" => "python",
+      "html" | "htm" => "html",
+      "css" => "css",
+      "md" | "markdown" => "markdown",
+      "sh" | "bash" => "bash",
+      "toml" => "toml",
+      "java" => "java",
+      "c" => "c",
+      "cpp" | "cxx" | "cc" | "c++" | "hpp" | "hxx" | "h++" => "cpp",
+      "php" => "php",
+      "cs" | "csx" => "csharp",
+      "ex" | "exs" => "elixir",
+      "swift" => "swift",
+      "lua" => "lua",
+      "kt" | "kts" => "kotlin",
+      "sql" => "sql",
+      "dockerfile" | "containerfile" => "dockerfile",
+      "zig" => "zig",
+      "hs" => "haskell",
+      "scala" | "sc" | "sbt" => "scala",
+      "nix" => "nix",
+      "hcl" | "tf" | "tfvars" => "hcl",
+      "mk" | "mak" | "make" => "make",
+      _ => return None,
+   })
+}
@@ -0,0 +1,137 @@
+use anyhow::{Context, Result};
+use std::{
+   collections::{HashMap, HashSet},
+   ops::Range,
+};
+use tree_sitter::{Language, Query, QueryCursor, StreamingIterator, Tree};
+
+/// How far around the requested range definitions and scopes are looked for. Without a limit a
+/// minified or IIFE-wrapped file, which is one top-level node, would be searched whole for every
+/// viewport.
+pub(super) const MAX_WIDENING: usize = 64 * 1024;
+
+/// A compiled locals query, using the `local.scope`, `local.definition` and `local.reference`
+/// captures tree-sitter-highlight understands.
+pub struct LocalsQuery {
+   query: Query,
+   scope_capture: Option<u32>,
+   definition_capture: Option<u32>,
+   reference_capture: Option<u32>,
+}
+
+impl LocalsQuery {
+   pub fn new(language: &Language, source: &str) -> Result<Self> {
+      let query = Query::new(language, source).context("Invalid locals query")?;
+      Ok(Self {
+         scope_capture: query.capture_index_for_name("local.scope"),
+         definition_capture: query.capture_index_for_name("local.definition"),
+         reference_capture: query.capture_index_for_name("local.reference"),
+         query,
+      })
+   }
+}
+
+/// Local variables found in part of a tree. Nodes are identified by their byte range.
+pub struct Locals {
+   /// The part of the tree that was searched: every top-level node touching the requested
+   /// range, up to `MAX_WIDENING` on either side, so definitions above a viewport are still found
+   pub range: Range<usize>,
+   pub definitions: HashSet<(usize, usize)>,
+   /// References, with the definition each resolves to
+   pub references: HashMap<(usize, usize), (usize, usize)>,
+}
+
+impl Locals {
+   pub fn contains(&self, node: (usize, usize)) -> bool {
+      self.definitions.contains(&node) || self.references.contains_key(&node)
+   }
+}
+
+struct Scope {
+   end: usize,
+   inherits: bool,
+   definitions: Vec<(String, (usize, usize))>,
+}
+
+/// Resolves references to local definitions around `range`. A reference resolves to the
+/// latest definition of its name that came before it, in its scope or one it inherits from.
+/// Definitions more than `MAX_WIDENING` before `range` aren't seen.
+pub fn find_locals(query: &LocalsQuery, tree: &Tree, text: &str, range: Range<usize>) -> Locals {
+   let root = tree.root_node();
+   let mut cursor = root.walk();
+   let top_level: Vec<_> = root
+      .children(&mut cursor)
+      .filter(|node| node.end_byte() >= range.start && node.start_byte() <= range.end)
+      .collect();
+   let range = match (top_level.first(), top_level.last()) {
+      (Some(first), Some(last)) => {
+         first
+            .start_byte()
+            .max(range.start.saturating_sub(MAX_WIDENING))
+            ..last.end_byte().min(range.end.saturating_add(MAX_WIDENING))
+      }
+      _ => range,
+   };
+
+   let mut locals = Locals {
+      range: range.clone(),
+      definitions: HashSet::new(),
+      references: HashMap::new(),
+   };
+   let mut scopes = vec![Scope {
+      end: usize::MAX,
+      inherits: false,
+      definitions: Vec::new(),
+   }];
+
+   let mut cursor = QueryCursor::new();
+   cursor.set_byte_range(range);
+   let mut captures = cursor.captures(&query.query, root, text.as_bytes());
+   while let Some((m, capture_index)) = captures.next() {
+      let capture = m.captures[*capture_index];
+      let node = capture.node;
+      let key = (node.start_byte(), node.end_byte());
+      while scopes.len() > 1 && scopes.last().is_some_and(|scope| scope.end <= key.0) {
+         scopes.pop();
+      }
+
+      let index = Some(capture.index);
+      if index == query.scope_capture {
+         let inherits = !query
+            .query
+            .property_settings(m.pattern_index)
+            .iter()
+            .any(|p| &*p.key == "local.scope-inherits" && p.value.as_deref() == Some("false"));
+         scopes.push(Scope {
+            end: key.1,
+            inherits,
+            definitions: Vec::new(),
+         });
+      } else if index == query.definition_capture {
+         let Ok(name) = node.utf8_text(text.as_bytes()) else {
+            continue;
+         };
+         scopes
+            .last_mut()
+            .unwrap()
+            .definitions
+            .push((name.to_string(), key));
+         locals.definitions.insert(key);
+      } else if index == query.reference_capture && !locals.definitions.contains(&key) {
+         let Ok(name) = node.utf8_text(text.as_bytes()) else {
+            continue;
+         };
+         for scope in scopes.iter().rev() {
+            if let Some((_, definition)) = scope.definitions.iter().rev().find(|(n, _)| n == name) {
+               locals.references.insert(key, *definition);
+               break;
+            }
+            if !scope.inherits {
+               break;
+            }
+         }
+      }
+   }
+
+   locals
+}
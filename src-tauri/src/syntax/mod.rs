@@ -0,0 +1,12 @@
+mod detect;
+mod document;
+mod highlight;
+mod languages;
+mod locals;
+mod runtime;
+
+pub use detect::detect_language;
+pub use document::{SyntaxDocuments, TokenRange, TokenUpdate};
+pub use highlight::{Token, highlight};
+pub use languages::get_language_config;
+pub use runtime::{UserLanguage, load_user_languages};
@@ -0,0 +1,90 @@
+; For tree-sitter-hcl 1.1.0, which doesn't ship highlight queries
+
+(comment) @comment
+
+(identifier) @variable
+
+; Blocks like `resource "aws_instance" "web" { ... }`
+(block (identifier) @keyword)
+(block (string_lit) @type)
+
+(attribute (identifier) @property)
+(object_elem key: (expression (variable_expr (identifier) @property)))
+(get_attr (identifier) @property)
+
+(function_call (identifier) @function)
+
+((variable_expr (identifier) @variable.builtin)
+ (#any-of? @variable.builtin "var" "local" "module" "data" "path" "terraform" "each" "count" "self"))
+
+(for_intro (identifier) @variable.parameter)
+
+[
+  (string_lit)
+  (quoted_template_start)
+  (quoted_template_end)
+  (template_literal)
+  (heredoc_template)
+] @string
+
+[
+  (heredoc_identifier)
+  (heredoc_start)
+] @string.special
+
+(numeric_lit) @number
+(bool_lit) @constant.builtin
+(null_lit) @constant.builtin
+
+[
+  "if"
+  "else"
+  "endif"
+  "for"
+  "endfor"
+  "in"
+] @keyword.control
+
+[
+  "!"
+  "!="
+  "%"
+  "&&"
+  "*"
+  "+"
+  "-"
+  "/"
+  "<"
+  "<="
+  "=="
+  "=>"
+  ">"
+  ">="
+  "?"
+  "||"
+  "="
+  ":"
+] @operator
+
+[
+  "("
+  ")"
+  "["
+  "]"
+  "{"
+  "}"
+  (template_interpolation_start)
+  (template_interpolation_end)
+  (template_directive_start)
+  (template_directive_end)
+] @punctuation.bracket
+
+[
+  "."
+  ".*"
+  "[*]"
+  ","
+] @punctuation.delimiter
+
+(strip_marker) @punctuation.special
+(ellipsis) @punctuation.special
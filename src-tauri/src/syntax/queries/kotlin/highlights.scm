@@ -0,0 +1,203 @@
+; For tree-sitter-kotlin-ng 1.1.0, which doesn't ship highlight queries
+
+(identifier) @variable
+
+((identifier) @constant.builtin
+ (#any-of? @constant.builtin "true" "false" "null"))
+
+((identifier) @constant
+ (#match? @constant "^[A-Z][A-Z0-9_]+$"))
+
+;; Declarations
+
+(class_declaration name: (identifier) @type)
+(object_declaration name: (identifier) @type)
+(type_alias type: (identifier) @type)
+(type_parameter (identifier) @type)
+(enum_entry (identifier) @constant)
+
+(function_declaration name: (identifier) @function)
+
+(parameter (identifier) @variable.parameter)
+(class_parameter (identifier) @property)
+(lambda_parameters (variable_declaration (identifier) @variable.parameter))
+(property_declaration (variable_declaration (identifier) @property))
+
+;; Types
+
+(user_type (identifier) @type)
+
+;; Calls and members
+
+(call_expression (identifier) @function.call)
+(call_expression (navigation_expression (identifier) @function.method.call .))
+(navigation_expression (identifier) @property .)
+(value_argument (identifier) @variable.parameter "=")
+
+(this_expression) @variable.builtin
+(super_expression) @variable.builtin
+
+;; Annotations, packages and labels
+
+(annotation (user_type (identifier) @attribute))
+(annotation (constructor_invocation (user_type (identifier) @attribute)))
+"@" @attribute
+
+(package_header (qualified_identifier (identifier) @module))
+(import (qualified_identifier (identifier) @module))
+
+(label) @string.special
+
+;; Literals
+
+[
+  (line_comment)
+  (block_comment)
+  (shebang)
+] @comment
+
+[
+  (string_literal)
+  (multiline_string_literal)
+  (character_literal)
+] @string
+
+(escape_sequence) @string.escape
+(interpolation ["$" "${" "}"] @punctuation.special)
+
+[
+  (number_literal)
+  (float_literal)
+] @number
+
+;; Keywords
+
+[
+  "class"
+  "interface"
+  "object"
+  "companion"
+  "typealias"
+  "val"
+  "var"
+  "constructor"
+  "init"
+  "by"
+  "get"
+  "set"
+  "where"
+  "import"
+  "package"
+  "enum"
+  "data"
+  "value"
+  "annotation"
+  "sealed"
+  "inner"
+  "abstract"
+  "final"
+  "open"
+  "override"
+  "lateinit"
+  "const"
+  "private"
+  "protected"
+  "public"
+  "internal"
+  "suspend"
+  "inline"
+  "noinline"
+  "crossinline"
+  "tailrec"
+  "operator"
+  "infix"
+  "external"
+  "vararg"
+  "expect"
+  "actual"
+  "out"
+  "dynamic"
+] @keyword
+
+(reification_modifier) @keyword
+
+"fun" @keyword.function
+
+[
+  "if"
+  "else"
+  "when"
+  "for"
+  "while"
+  "do"
+  "try"
+  "catch"
+  "finally"
+  "throw"
+] @keyword.control
+
+[
+  "return"
+  "return@"
+] @keyword.return
+
+[
+  "as"
+  "as?"
+  "in"
+  "!in"
+  "is"
+  "!is"
+] @keyword.operator
+
+;; Operators and punctuation
+
+[
+  "!"
+  "!!"
+  "!="
+  "!=="
+  "%"
+  "%="
+  "&&"
+  "*"
+  "*="
+  "+"
+  "++"
+  "+="
+  "-"
+  "--"
+  "-="
+  "->"
+  ".."
+  "..<"
+  "/"
+  "/="
+  "<"
+  "<="
+  "="
+  "=="
+  "==="
+  ">"
+  ">="
+  "?:"
+  "||"
+  "::"
+] @operator
+
+[
+  "("
+  ")"
+  "["
+  "]"
+  "{"
+  "}"
+] @punctuation.bracket
+
+[
+  "."
+  "?."
+  ","
+  ";"
+  ":"
+] @punctuation.delimiter
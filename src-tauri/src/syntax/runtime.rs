@@ -0,0 +1,326 @@
+use super::languages::clear_language_configs;
+use anyhow::{Context, Result, bail};
+use libloading::{Library, Symbol};
+use serde::{Deserialize, Serialize};
+use std::{
+   collections::HashMap,
+   fs,
+   path::{Path, PathBuf},
+   sync::Mutex,
+};
+use tree_sitter::{LANGUAGE_VERSION, Language, MIN_COMPATIBLE_LANGUAGE_VERSION, ffi::TSLanguage};
+
+/// Prebuilt grammars, `<grammar>.so` (or `.dylib`/`.dll`), like Helix's `runtime/grammars`.
+const GRAMMARS_DIR: &str = "grammars";
+/// Queries by language, `<language>/highlights.scm` and so on, like Helix's `runtime/queries`.
+/// These also override the queries of built-in languages.
+const QUERIES_DIR: &str = "queries";
+/// Helix-style `[[language]]` entries naming the grammar and file types of a language.
+const LANGUAGES_FILE: &str = "languages.toml";
+/// Zed-style language directories, each with a `config.toml` and its queries.
+const LANGUAGES_DIR: &str = "languages";
+
+/// A language defined in the user config directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct UserLanguage {
+   pub name: String,
+   pub grammar: String,
+   /// Extensions, or whole file names like `Dockerfile`
+   pub file_types: Vec<String>,
+   /// The directory its queries are in
+   pub queries: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct HelixLanguages {
+   #[serde(default)]
+   language: Vec<HelixLanguage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct HelixLanguage {
+   name: String,
+   grammar: Option<String>,
+   /// Helix also allows `{ glob = "..." }` entries, which are skipped
+   #[serde(default)]
+   file_types: Vec<toml::Value>,
+}
+
+#[derive(Deserialize)]
+struct ZedLanguageConfig {
+   grammar: Option<String>,
+   #[serde(default)]
+   path_suffixes: Vec<String>,
+}
+
+/// The languages and queries found in a user config directory.
+#[derive(Default, Clone)]
+pub struct UserGrammars {
+   config_dir: Option<PathBuf>,
+   languages: Vec<UserLanguage>,
+}
+
+lazy_static::lazy_static! {
+    // What `load_user_languages` last read, used by `get_language_config` and `detect_language`
+    static ref USER_GRAMMARS: Mutex<UserGrammars> = Mutex::new(UserGrammars::default());
+    // Libraries are never unloaded, since trees and queries made from them may still be around
+    static ref LOADED_GRAMMARS: Mutex<HashMap<PathBuf, Language>> = Mutex::new(HashMap::new());
+}
+
+/// Reads the language definitions in `config_dir` and makes them the ones in use. Grammars
+/// themselves load when first used. Calling this again picks up new languages and changed
+/// queries.
+pub fn load_user_languages(config_dir: &Path) -> Vec<UserLanguage> {
+   let user_grammars = UserGrammars::read(config_dir);
+   let languages = user_grammars.languages.clone();
+   *USER_GRAMMARS.lock().unwrap() = user_grammars;
+   clear_language_configs();
+   languages
+}
+
+/// The user grammars in use.
+pub fn user_grammars() -> UserGrammars {
+   USER_GRAMMARS.lock().unwrap().clone()
+}
+
+pub fn user_language(name: &str) -> Option<UserLanguage> {
+   USER_GRAMMARS.lock().unwrap().language(name).cloned()
+}
+
+pub fn user_language_for_file(file_name: Option<&str>, extension: &str) -> Option<String> {
+   USER_GRAMMARS
+      .lock()
+      .unwrap()
+      .language_for_file(file_name, extension)
+      .map(|language| language.name.clone())
+}
+
+impl UserGrammars {
+   /// Reads both the Helix and the Zed layout. Files that can't be read are logged and skipped.
+   pub fn read(config_dir: &Path) -> Self {
+      let mut languages = Vec::new();
+
+      let languages_file = config_dir.join(LANGUAGES_FILE);
+      if languages_file.exists() {
+         match read_helix_languages(&languages_file, config_dir) {
+            Ok(found) => languages.extend(found),
+            Err(e) => log::warn!("Failed to read {:?}: {:#}", languages_file, e),
+         }
+      }
+
+      if let Ok(entries) = fs::read_dir(config_dir.join(LANGUAGES_DIR)) {
+         for entry in entries.flatten() {
+            let dir = entry.path();
+            let config_file = dir.join("config.toml");
+            if !config_file.exists() {
+               continue;
+            }
+            match read_zed_language(&dir, &config_file) {
+               Ok(language) => languages.push(language),
+               Err(e) => log::warn!("Failed to read {:?}: {:#}", config_file, e),
+            }
+         }
+      }
+
+      log::info!(
+         "Found {} user languages in {:?}",
+         languages.len(),
+         config_dir
+      );
+      Self {
+         config_dir: Some(config_dir.to_path_buf()),
+         languages,
+      }
+   }
+
+   pub fn language(&self, name: &str) -> Option<&UserLanguage> {
+      self.languages.iter().find(|language| language.name == name)
+   }
+
+   /// The user language for a file, matched by whole file name first and then by extension.
+   pub fn language_for_file(
+      &self,
+      file_name: Option<&str>,
+      extension: &str,
+   ) -> Option<&UserLanguage> {
+      let matches = |file_type: &str| {
+         self
+            .languages
+            .iter()
+            .find(|language| language.file_types.iter().any(|t| t == file_type))
+      };
+      file_name.and_then(matches).or_else(|| matches(extension))
+   }
+
+   /// The user's replacement for one of a language's queries, like `highlights`, if any.
+   pub fn query_override(&self, language: &str, kind: &str) -> Option<String> {
+      let path = self
+         .config_dir
+         .as_ref()?
+         .join(QUERIES_DIR)
+         .join(language)
+         .join(format!("{kind}.scm"));
+      fs::read_to_string(path).ok()
+   }
+
+   /// Loads `<grammar>.so` from the grammars directory, or returns it if it's already loaded.
+   pub fn load_grammar(&self, grammar: &str) -> Result<Language> {
+      let config_dir = self
+         .config_dir
+         .as_ref()
+         .context("User grammars are not set up")?;
+      let path = config_dir
+         .join(GRAMMARS_DIR)
+         .join(grammar)
+         .with_extension(std::env::consts::DLL_EXTENSION);
+
+      let mut loaded = LOADED_GRAMMARS.lock().unwrap();
+      if let Some(language) = loaded.get(&path) {
+         return Ok(language.clone());
+      }
+
+      let symbol_name = format!("tree_sitter_{}", grammar.replace('-', "_"));
+      let language = unsafe {
+         let library =
+            Library::new(&path).with_context(|| format!("Failed to load grammar {:?}", path))?;
+         let constructor: Symbol<unsafe extern "C" fn() -> *const TSLanguage> = library
+            .get(symbol_name.as_bytes())
+            .with_context(|| format!("{:?} has no {} function", path, symbol_name))?;
+         let raw = constructor();
+         std::mem::forget(library);
+         if raw.is_null() {
+            bail!("{} in {:?} returned no language", symbol_name, path);
+         }
+         Language::from_raw(raw)
+      };
+
+      let version = language.abi_version();
+      if !(MIN_COMPATIBLE_LANGUAGE_VERSION..=LANGUAGE_VERSION).contains(&version) {
+         bail!(
+            "Grammar {:?} was generated for tree-sitter ABI {}, but {}..={} is supported",
+            path,
+            version,
+            MIN_COMPATIBLE_LANGUAGE_VERSION,
+            LANGUAGE_VERSION
+         );
+      }
+
+      log::info!("Loaded grammar {:?}", path);
+      loaded.insert(path, language.clone());
+      Ok(language)
+   }
+}
+
+fn read_helix_languages(path: &Path, config_dir: &Path) -> Result<Vec<UserLanguage>> {
+   let file: HelixLanguages = toml::from_str(&fs::read_to_string(path)?)?;
+   Ok(file
+      .language
+      .into_iter()
+      .map(|language| UserLanguage {
+         grammar: language.grammar.unwrap_or_else(|| language.name.clone()),
+         file_types: language
+            .file_types
+            .into_iter()
+            .filter_map(|file_type| file_type.as_str().map(str::to_string))
+            .collect(),
+         queries: config_dir.join(QUERIES_DIR).join(&language.name),
+         name: language.name,
+      })
+      .collect())
+}
+
+fn read_zed_language(dir: &Path, config_file: &Path) -> Result<UserLanguage> {
+   let config: ZedLanguageConfig = toml::from_str(&fs::read_to_string(config_file)?)?;
+   let name = dir
+      .file_name()
+      .and_then(|name| name.to_str())
+      .context("Invalid language directory name")?
+      .to_string();
+   Ok(UserLanguage {
+      grammar: config.grammar.unwrap_or_else(|| name.clone()),
+      file_types: config.path_suffixes,
+      queries: dir.to_path_buf(),
+      name,
+   })
+}
+
+/// Reads one of a user language's queries; only `highlights` is required.
+pub fn read_query(language: &UserLanguage, kind: &str) -> Result<Option<String>> {
+   let path = language.queries.join(format!("{kind}.scm"));
+   match fs::read_to_string(&path) {
+      Ok(query) => Ok(Some(query)),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(e).with_context(|| format!("Failed to read {:?}", path)),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::syntax::{highlight, languages::compile_language_config};
+
+   #[test]
+   fn test_reads_user_languages_and_query_overrides() {
+      let config_dir = tempfile::tempdir().unwrap();
+      let dir = config_dir.path();
+      fs::write(
+         dir.join(LANGUAGES_FILE),
+         r#"
+[[language]]
+name = "lua"
+file-types = ["lua", { glob = "*.rockspec" }]
+
+[[language]]
+name = "gleam"
+file-types = ["gleam"]
+"#,
+      )
+      .unwrap();
+      fs::create_dir_all(dir.join(LANGUAGES_DIR).join("dockerfile")).unwrap();
+      fs::write(
+         dir.join(LANGUAGES_DIR).join("dockerfile/config.toml"),
+         "name = \"Dockerfile\"\npath_suffixes = [\"Dockerfile\"]\n",
+      )
+      .unwrap();
+      fs::create_dir_all(dir.join(QUERIES_DIR).join("json")).unwrap();
+      fs::write(
+         dir.join(QUERIES_DIR).join("json/highlights.scm"),
+         "(string) @keyword",
+      )
+      .unwrap();
+
+      // Read into a value of its own, so tests running alongside keep the grammars in use
+      let user_grammars = UserGrammars::read(dir);
+      assert_eq!(user_grammars.languages.len(), 3);
+      assert_eq!(user_grammars.languages[0].file_types, vec!["lua"]);
+      let name_of = |language: Option<&UserLanguage>| language.map(|l| l.name.clone());
+      assert_eq!(
+         name_of(user_grammars.language_for_file(Some("Dockerfile"), "")).as_deref(),
+         Some("dockerfile")
+      );
+      assert_eq!(
+         name_of(user_grammars.language_for_file(None, "lua")).as_deref(),
+         Some("lua")
+      );
+
+      // The grammar isn't there, so the language can't be used
+      let error = compile_language_config("gleam", &user_grammars)
+         .err()
+         .unwrap();
+      assert!(format!("{error:#}").contains("Failed to load grammar"));
+
+      // unless one is built in
+      let config = compile_language_config("lua", &user_grammars).unwrap();
+      let text = "local x = 1";
+      let tree = config.parse(text, None).unwrap();
+      assert!(!tree.root_node().has_error());
+
+      // Built-in languages take the user's queries
+      let config = compile_language_config("json", &user_grammars).unwrap();
+      let text = r#"{"a": 1}"#;
+      let tree = config.parse(text, None).unwrap();
+      let tokens = highlight(&config, &tree, text, 0..text.len());
+      assert_eq!(tokens[0].token_type, "keyword");
+   }
+}
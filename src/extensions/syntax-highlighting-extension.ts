@@ -93,7 +93,7 @@ class SyntaxHighlighter {
       const extension = this.filePath?.split(".").pop() || "txt";
 
       // Fetch tokens from Rust API
-      this.tokens = await getTokens(content, extension);
+      this.tokens = await getTokens(content, extension, this.filePath ?? undefined);
 
       // Cache tokens in buffer store
       const bufferStore = useBufferStore.getState();
@@ -63,7 +63,10 @@ export class LspClient {
     try {
       console.log(`Getting completions for ${filePath}:${line}:${character}`);
       console.log(`Active workspaces: ${Array.from(this.activeWorkspaces).join(", ")}`);
-      const completions = await invoke<CompletionItem[]>("lsp_get_completions", {
+      const { items: completions } = await invoke<{
+        is_incomplete: boolean;
+        items: CompletionItem[];
+      }>("lsp_get_completions", {
         filePath,
         line,
         character,
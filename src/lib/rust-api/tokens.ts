@@ -5,8 +5,65 @@ export interface Token {
   end: number;
   token_type: string;
   class_name: string;
+  capture?: string | null;
 }
 
-export async function getTokens(content: string, fileExtension: string): Promise<Token[]> {
-  return invoke<Token[]>("get_tokens", { content, fileExtension });
+export async function getTokens(
+  content: string,
+  fileExtension: string,
+  filePath?: string,
+): Promise<Token[]> {
+  return invoke<Token[]>("get_tokens", { content, fileExtension, filePath });
+}
+
+export interface TokenUpdate {
+  ranges: { start: number; end: number }[];
+  tokens: Token[];
+}
+
+export interface TextChange {
+  range?: {
+    start: { line: number; character: number };
+    end: { line: number; character: number };
+  };
+  text: string;
+}
+
+export type TokenRange =
+  | { unit: "bytes"; start: number; end: number }
+  | { unit: "lines"; start: number; end: number };
+
+export async function openSyntaxDocument(
+  documentId: string,
+  content: string,
+  fileExtension: string,
+  filePath?: string,
+): Promise<void> {
+  return invoke<void>("open_syntax_document", { documentId, content, fileExtension, filePath });
+}
+
+export async function getTokensInRange(documentId: string, range: TokenRange): Promise<Token[]> {
+  return invoke<Token[]>("get_tokens_in_range", { documentId, range });
+}
+
+export async function editSyntaxDocument(
+  documentId: string,
+  changes: TextChange[],
+): Promise<TokenUpdate> {
+  return invoke<TokenUpdate>("edit_syntax_document", { documentId, changes });
+}
+
+export async function closeSyntaxDocument(documentId: string): Promise<void> {
+  return invoke<void>("close_syntax_document", { documentId });
+}
+
+export interface UserLanguage {
+  name: string;
+  grammar: string;
+  file_types: string[];
+  queries: string;
+}
+
+export async function reloadUserGrammars(): Promise<UserLanguage[]> {
+  return invoke<UserLanguage[]>("reload_user_grammars");
 }